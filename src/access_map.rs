@@ -0,0 +1,224 @@
+use std::iter::Peekable;
+use std::ops::Range;
+use std::slice::Iter;
+
+use super::Access;
+
+/// A map from non-overlapping byte ranges to access permissions, or to
+/// other attributes of the pages, like their [`Caching`](super::Caching).
+/// Offsets that are not covered by any range have no recorded access.
+/// Adjacent ranges with the same access are always coalesced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AccessMap<T = Access> {
+    runs: Vec<(Range<usize>, T)>,
+}
+
+impl<T> Default for AccessMap<T> {
+    fn default() -> Self {
+        Self { runs: Vec::new() }
+    }
+}
+
+impl<T: Copy + PartialEq> AccessMap<T> {
+    /// Record `access` for `range`, overriding any access previously
+    /// recorded for the bytes in that range.
+    pub(crate) fn insert(&mut self, range: Range<usize>, access: T) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut runs = Vec::with_capacity(self.runs.len() + 2);
+        let mut inserted = false;
+        for (run, run_access) in self.runs.drain(..) {
+            if run.end <= range.start {
+                runs.push((run, run_access));
+                continue;
+            }
+            if run.start < range.start {
+                runs.push((run.start..range.start, run_access));
+            }
+            if !inserted && run.start >= range.start {
+                runs.push((range.clone(), access));
+                inserted = true;
+            }
+            if run.end > range.end {
+                if !inserted {
+                    runs.push((range.clone(), access));
+                    inserted = true;
+                }
+                runs.push((run.start.max(range.end)..run.end, run_access));
+            }
+        }
+        if !inserted {
+            runs.push((range, access));
+        }
+
+        // coalesce adjacent runs with the same access
+        let mut coalesced: Vec<(Range<usize>, T)> = Vec::with_capacity(runs.len());
+        for (run, run_access) in runs {
+            match coalesced.last_mut() {
+                Some((last, last_access))
+                    if last.end == run.start && *last_access == run_access =>
+                {
+                    last.end = run.end;
+                }
+                _ => coalesced.push((run, run_access)),
+            }
+        }
+        self.runs = coalesced;
+    }
+
+    /// Remove the access recorded for the bytes in `range`, if any.
+    pub(crate) fn remove(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut runs = Vec::with_capacity(self.runs.len() + 1);
+        for (run, run_access) in self.runs.drain(..) {
+            if run.end <= range.start || run.start >= range.end {
+                runs.push((run, run_access));
+                continue;
+            }
+            if run.start < range.start {
+                runs.push((run.start..range.start, run_access));
+            }
+            if run.end > range.end {
+                runs.push((range.end..run.end, run_access));
+            }
+        }
+        self.runs = runs;
+    }
+
+    /// Returns the access recorded for the bytes in `range`, with offsets
+    /// relative to the start of `range`.
+    pub(crate) fn slice(&self, range: Range<usize>) -> AccessMap<T> {
+        let runs = self
+            .runs
+            .iter()
+            .filter(|(run, _)| run.start < range.end && range.start < run.end)
+            .map(|(run, access)| {
+                let start = run.start.max(range.start) - range.start;
+                let end = run.end.min(range.end) - range.start;
+                (start..end, *access)
+            })
+            .collect();
+        AccessMap { runs }
+    }
+
+    /// Returns the access recorded for the byte at `offset`, if any.
+    pub(crate) fn get(&self, offset: usize) -> Option<T> {
+        let idx = self.runs.partition_point(|(run, _)| run.end <= offset);
+        match self.runs.get(idx) {
+            Some((run, access)) if run.contains(&offset) => Some(*access),
+            _ => None,
+        }
+    }
+
+    /// Returns an iterator over the coalesced runs of access covering
+    /// `0..len`, where offsets without recorded access have `default`
+    /// access.
+    pub(crate) fn regions(&self, len: usize, default: T) -> Regions<'_, T> {
+        Regions {
+            runs: self.runs.iter().peekable(),
+            cursor: 0,
+            len,
+            default,
+        }
+    }
+
+    /// Returns the coalesced runs of `0..len` where the access recorded in
+    /// `other` differs from the access recorded in `self`, together with
+    /// the access in `other`.
+    /// Offsets without recorded access have `default` access in both maps.
+    pub(crate) fn diff(
+        &self,
+        other: &AccessMap<T>,
+        len: usize,
+        default: T,
+    ) -> Vec<(Range<usize>, T)> {
+        let mut diff: Vec<(Range<usize>, T)> = Vec::new();
+        let mut current = self.regions(len, default).peekable();
+        for (run, access) in other.regions(len, default) {
+            let mut start = run.start;
+            while start < run.end {
+                let (cur, cur_access) = current.peek().cloned().unwrap();
+                let end = cur.end.min(run.end);
+                if cur_access != access {
+                    match diff.last_mut() {
+                        Some((last, last_access))
+                            if last.end == start && *last_access == access =>
+                        {
+                            last.end = end;
+                        }
+                        _ => diff.push((start..end, access)),
+                    }
+                }
+                if cur.end <= run.end {
+                    current.next();
+                }
+                start = end;
+            }
+        }
+        diff
+    }
+
+    /// Remove all recorded ranges.
+    pub(crate) fn clear(&mut self) {
+        self.runs.clear();
+    }
+}
+
+/// Iterator over the coalesced runs of an [`AccessMap`].
+/// See [`AccessMap::regions`].
+pub(crate) struct Regions<'a, T = Access> {
+    runs: Peekable<Iter<'a, (Range<usize>, T)>>,
+    cursor: usize,
+    len: usize,
+    default: T,
+}
+
+impl<T: Copy + PartialEq> Regions<'_, T> {
+    fn next_run(&mut self) -> Option<(Range<usize>, T)> {
+        if self.cursor >= self.len {
+            return None;
+        }
+        let (range, access) = match self.runs.peek() {
+            Some((run, _)) if run.start > self.cursor => {
+                (self.cursor..run.start.min(self.len), self.default)
+            }
+            Some((run, access)) => {
+                let run = self.cursor..run.end.min(self.len);
+                let access = *access;
+                self.runs.next();
+                (run, access)
+            }
+            None => (self.cursor..self.len, self.default),
+        };
+        self.cursor = range.end;
+        Some((range, access))
+    }
+}
+
+impl<T: Copy + PartialEq> Iterator for Regions<'_, T> {
+    type Item = (Range<usize>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut range, access) = self.next_run()?;
+        let mut peek = Regions {
+            runs: self.runs.clone(),
+            cursor: self.cursor,
+            len: self.len,
+            default: self.default,
+        };
+        while let Some((next, next_access)) = peek.next_run() {
+            if next_access != access {
+                break;
+            }
+            range.end = next.end;
+            self.runs = peek.runs.clone();
+            self.cursor = peek.cursor;
+        }
+        Some((range, access))
+    }
+}
@@ -0,0 +1,134 @@
+use std::ops::{Range, RangeBounds};
+
+use super::{is_zero, protection_range, Access, View, ViewMode};
+
+/// Hints about how the memory of a view will be accessed, to pass to
+/// [`advise`](View::advise).
+///
+/// On Linux and macOS these map to the `madvise` advice with the same
+/// name. On Windows only [`WillNeed`](Advice::WillNeed) and
+/// [`DontNeed`](Advice::DontNeed) have an effect.
+/// [`HugePage`](Advice::HugePage) and [`NoHugePage`](Advice::NoHugePage)
+/// are only supported on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// The region will be accessed soon, so it's worth reading it ahead.
+    WillNeed,
+    /// The region won't be accessed soon, so its memory can be released.
+    /// For copy-on-write views, this also discards the changes made to
+    /// the region.
+    DontNeed,
+    /// The region will be accessed sequentially.
+    Sequential,
+    /// The region will be accessed in random order.
+    Random,
+    /// The region should be backed by transparent huge pages when
+    /// possible, see [`view_populated`](crate::Snapshot::view_populated)
+    /// to populate them up front.
+    /// This doesn't need huge pages to be reserved in advance, and falls
+    /// back to regular pages when there are none available.
+    /// It has no effect if transparent huge pages are disabled, and
+    /// returns an `InvalidInput` error if the kernel doesn't support them.
+    HugePage,
+    /// The region should not be backed by transparent huge pages.
+    NoHugePage,
+}
+
+impl<S> View<S> {
+    /// Give the system a hint about how a memory region of this view will
+    /// be accessed, so that it can manage the memory of the view more
+    /// efficiently.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter.
+    ///
+    /// Unlike the other hints, [`Advice::DontNeed`] changes the content of
+    /// copy-on-write views: any change made to the region is discarded,
+    /// and the region reads the content of the root snapshot again, like
+    /// with [`restore_range`](View::restore_range) but keeping the memory
+    /// protection of the region.
+    /// This makes it a cheap way to restore part of a view, and why this
+    /// requires a mutable borrow of the view.
+    /// For mutable views the content is preserved, since changes are
+    /// already in the root snapshot.
+    pub fn advise(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        advice: Advice,
+    ) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        if advice == Advice::DontNeed && self.write_back {
+            // the changes are not written back to the snapshot yet, so the
+            // pages can't be dropped
+            return Ok(());
+        }
+        if advice == Advice::DontNeed && self.mode == ViewMode::Cow {
+            // Pages mapped from other snapshots would read the content of
+            // those snapshots again, rather than that of the root snapshot.
+            if self.overlaid || !self.advise_impl(region.clone(), advice)? {
+                self.restore_range_impl(region.clone())?;
+                self.remap_layers(region.clone())?;
+                self.reapply_protection(region)?;
+            }
+            return Ok(());
+        }
+        self.advise_impl(region, advice)?;
+        Ok(())
+    }
+
+    /// Release the memory of the pages of this view that are entirely
+    /// zero, e.g., pages that were written and later zeroed, and return
+    /// the number of pages released.
+    /// The pages still read as zero afterwards.
+    ///
+    /// Only the pages that are resident, readable, and for copy-on-write
+    /// views [dirty](View::dirty_pages), are scanned, so compacting the
+    /// view doesn't fault in any page.
+    /// Pages are released like with [`zero_fast`](View::zero_fast): on
+    /// Linux, for a mutable view this punches holes in the backing of the
+    /// snapshot, and on Linux, macOS and FreeBSD, for a copy-on-write view
+    /// this replaces the pages with the shared zero page.
+    /// Where pages can't be released this way, e.g., on Windows, or for
+    /// mutable views of a snapshot with [checkpoints](super::Snapshot::checkpoint),
+    /// no page is released and this returns `0`.
+    pub fn compact(&mut self) -> std::io::Result<usize> {
+        let page = super::page_size();
+        if self.write_back || self.size == 0 || self.offset % page != 0 {
+            return Ok(0);
+        }
+        let size = self.size / page * page;
+        let candidates: Vec<Range<usize>> = match self.mode {
+            ViewMode::Mutable => std::iter::once(0..size).collect(),
+            ViewMode::Cow => self.dirty_pages()?,
+        };
+        let resident = self.residency_impl(0..size)?;
+        let mut zero = Vec::<Range<usize>>::new();
+        for (run, allow) in self.regions() {
+            if !allow.contains(Access::READ) {
+                continue;
+            }
+            for candidate in &candidates {
+                let start = run.start.max(candidate.start);
+                let end = run.end.min(candidate.end).min(size);
+                for offset in (start..end).step_by(page) {
+                    let page_range = offset..offset + page;
+                    if !resident[offset / page] || !is_zero(&self.as_full_slice()[page_range]) {
+                        continue;
+                    }
+                    match zero.last_mut() {
+                        Some(last) if last.end == offset => last.end += page,
+                        _ => zero.push(offset..offset + page),
+                    }
+                }
+            }
+        }
+        let mut released = 0;
+        for region in zero {
+            let len = region.len();
+            if !self.zero_pages_impl(region)? {
+                break;
+            }
+            released += len / page;
+        }
+        Ok(released)
+    }
+}
@@ -0,0 +1,91 @@
+//! Debug-only detection of aliased mutable views.
+//!
+//! Mutable views map their snapshot with `MAP_SHARED`, so two mutable
+//! views of the same backing alias the same memory.
+//! Since views are `Send` and `Sync`, mutating aliased views from
+//! different threads is a data race.
+//! Several snapshots can share a backing, for instance when they are
+//! created from the same file, so each backing has a count of its live
+//! mutable views, which every mutable view of the backing holds.
+//! The count is looked up once, when the view is created, so that
+//! checking it on mutable access is a single atomic load.
+
+use std::collections::HashMap;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use super::r#impl::backing_key;
+use super::RawFileDescriptor;
+
+type BackingKey = (u64, u64);
+
+/// The number of live mutable views of a backing.
+struct Backing {
+    views: AtomicUsize,
+}
+
+/// A count of mutable views, freed when its last view is dropped.
+/// It's accessed through a pointer rather than an `Arc`, so that views
+/// don't appear to have interior mutability.
+#[derive(Clone, Copy)]
+struct BackingPtr(NonNull<Backing>);
+
+// SAFETY: the count is only accessed atomically, and it's only freed
+// while holding the lock of the registry.
+unsafe impl Send for BackingPtr {}
+
+static BACKINGS: LazyLock<Mutex<HashMap<BackingKey, BackingPtr>>> = LazyLock::new(Default::default);
+
+fn backings() -> std::sync::MutexGuard<'static, HashMap<BackingKey, BackingPtr>> {
+    BACKINGS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The registration of a mutable view in the count of its backing,
+/// released when dropped.
+pub(crate) struct MutableView {
+    key: BackingKey,
+    backing: BackingPtr,
+}
+
+impl MutableView {
+    /// Register a new mutable view of the backing `fd`, mapped at `ptr`.
+    pub(crate) fn acquire(fd: RawFileDescriptor, ptr: *const u8) -> Self {
+        let key = backing_key(fd, ptr);
+        let backing = *backings().entry(key).or_insert_with(|| {
+            let views = AtomicUsize::new(0);
+            BackingPtr(NonNull::from(Box::leak(Box::new(Backing { views }))))
+        });
+        // the count can't be freed while the registry is locked, or while
+        // this view is registered in it
+        unsafe { backing.0.as_ref() }
+            .views
+            .fetch_add(1, Ordering::Relaxed);
+        Self { key, backing }
+    }
+
+    /// Panics if other mutable views of the same backing are alive.
+    #[inline]
+    pub(crate) fn check(&self) {
+        let count = unsafe { self.backing.0.as_ref() }
+            .views
+            .load(Ordering::Relaxed);
+        if count > 1 {
+            panic!(
+                "memsnap: mutable access to a view aliased by {} other mutable views of the same snapshot backing",
+                count - 1
+            );
+        }
+    }
+}
+
+impl Drop for MutableView {
+    fn drop(&mut self) {
+        let mut backings = backings();
+        let views = &unsafe { self.backing.0.as_ref() }.views;
+        if views.fetch_sub(1, Ordering::Relaxed) == 1 {
+            backings.remove(&self.key);
+            drop(unsafe { Box::from_raw(self.backing.0.as_ptr()) });
+        }
+    }
+}
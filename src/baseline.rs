@@ -0,0 +1,58 @@
+use super::{MutView, Snapshot};
+
+impl MutView<'_> {
+    /// Capture the current content of this mutable view as a baseline,
+    /// that the view can later be reverted to with
+    /// [`revert_to_baseline`](Self::revert_to_baseline), replacing any
+    /// previous baseline.
+    /// Unlike [`Snapshot::checkpoint`], the baseline belongs to this view,
+    /// and is discarded when the view is dropped.
+    ///
+    /// The content is captured with [`Snapshot::try_clone_cow`], which
+    /// shares the physical pages of the snapshot where the platform
+    /// supports it, and copies the content otherwise.
+    pub fn checkpoint_baseline(&mut self) -> std::io::Result<()> {
+        let baseline = if self.write_back {
+            // the changes of the view are not in the snapshot yet
+            let mut baseline = Snapshot::from_slice(self.as_full_slice())?;
+            baseline.len = self.len;
+            baseline
+        } else {
+            self._snapshot.try_clone_cow()?.0
+        };
+        self.baseline = Some(Box::new(baseline));
+        Ok(())
+    }
+
+    /// Revert the content of this mutable view to the baseline captured
+    /// with [`checkpoint_baseline`](Self::checkpoint_baseline).
+    /// Only the pages that differ from the baseline are written, and the
+    /// baseline is kept, so the view can be reverted to it again.
+    /// The changes are reflected in the root snapshot, as any other write
+    /// to the view.
+    ///
+    /// Returns an `InvalidInput` error if the view has no baseline.
+    pub fn revert_to_baseline(&mut self) -> std::io::Result<()> {
+        let Some(baseline) = self.baseline.take() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The view has no baseline to revert to",
+            ));
+        };
+        let reverted = self.revert_to(&baseline);
+        self.baseline = Some(baseline);
+        reverted
+    }
+
+    fn revert_to(&mut self, baseline: &Snapshot) -> std::io::Result<()> {
+        let src = baseline.view()?;
+        let page = super::page_size();
+        let dst = self.as_full_mut_slice();
+        for (dst, src) in dst.chunks_mut(page).zip(src.as_full_slice().chunks(page)) {
+            if dst != src {
+                dst.copy_from_slice(src);
+            }
+        }
+        Ok(())
+    }
+}
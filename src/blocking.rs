@@ -0,0 +1,132 @@
+//! Async constructors of snapshots that don't block the executor.
+//!
+//! The blocking work runs on a thread of its own, and the returned future
+//! completes when it's done. This doesn't depend on any particular async
+//! runtime, so the futures can be awaited from any executor.
+//! A new thread is spawned for every call, rather than keeping a pool.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::Snapshot;
+
+impl Snapshot {
+    /// Create a new snapshot from a file, like
+    /// [`from_file`](Snapshot::from_file), without blocking the calling
+    /// task.
+    ///
+    /// The file can be anything that borrows a file descriptor, or a handle
+    /// on Windows, like a [`std::fs::File`] or a `tokio::fs::File`.
+    /// The file descriptor is duplicated, so the file is still usable once
+    /// the snapshot is created. Writes of a `tokio::fs::File` that are
+    /// still in flight are not seen by the snapshot, so flush it first.
+    ///
+    /// Every call spawns a new OS thread to do the blocking work, and the
+    /// thread and its stack live until the work is done.
+    /// This is negligible next to reading a large file, but callers that
+    /// create many small snapshots should rather use the blocking pool of
+    /// their runtime, e.g., `tokio::task::spawn_blocking`.
+    pub fn from_file_async(
+        file: impl AsFile,
+    ) -> impl Future<Output = std::io::Result<Self>> + Send + 'static {
+        let file = try_clone_file(&file);
+        Blocking::spawn(move || Self::from_file(file?))
+    }
+
+    /// Create a new snapshot from the file at `path`, like
+    /// [`open_path`](Snapshot::open_path), without blocking the calling
+    /// task.
+    /// Like [`from_file_async`](Snapshot::from_file_async), this spawns a
+    /// thread for each call.
+    pub fn from_path_async(
+        path: impl AsRef<Path>,
+    ) -> impl Future<Output = std::io::Result<Self>> + Send + 'static {
+        let path = path.as_ref().to_owned();
+        Blocking::spawn(move || Self::open_path(path))
+    }
+
+    /// Create a new snapshot of the given size from the content read from
+    /// a reader, like [`read_from`](Snapshot::read_from), without blocking
+    /// the calling task.
+    /// The reader is dropped once the content is read.
+    /// Like [`from_file_async`](Snapshot::from_file_async), this spawns a
+    /// thread for each call.
+    pub fn read_from_async<R: std::io::Read + Send + 'static>(
+        mut r: R,
+        size: usize,
+    ) -> impl Future<Output = std::io::Result<Self>> + Send + 'static {
+        Blocking::spawn(move || Self::read_from(&mut r, size))
+    }
+}
+
+#[cfg(unix)]
+use std::os::fd::AsFd as AsFile;
+#[cfg(windows)]
+use std::os::windows::io::AsHandle as AsFile;
+
+/// Duplicate the file descriptor of `file` into a [`std::fs::File`].
+fn try_clone_file(file: &impl AsFile) -> std::io::Result<std::fs::File> {
+    #[cfg(unix)]
+    let owned = file.as_fd().try_clone_to_owned()?;
+    #[cfg(windows)]
+    let owned = file.as_handle().try_clone_to_owned()?;
+    Ok(owned.into())
+}
+
+/// A future that completes with the result of a closure running on a
+/// thread of its own.
+struct Blocking<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+struct State<T> {
+    result: Option<std::thread::Result<std::io::Result<T>>>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Blocking<T> {
+    fn spawn(f: impl FnOnce() -> std::io::Result<T> + Send + 'static) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            result: None,
+            waker: None,
+        }));
+        let shared = state.clone();
+        let spawned = std::thread::Builder::new()
+            .name("memsnap-blocking".into())
+            .spawn(move || {
+                // the panic is resumed when the future is polled
+                let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+                let mut state = shared.lock().unwrap_or_else(|err| err.into_inner());
+                state.result = Some(result);
+                let waker = state.waker.take();
+                drop(state);
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+        if let Err(err) = spawned {
+            state.lock().unwrap().result = Some(Ok(Err(err)));
+        }
+        Self { state }
+    }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = std::io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        match state.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(payload)) => std::panic::resume_unwind(payload),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
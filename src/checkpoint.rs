@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::ptr::NonNull;
+
+use super::zeroize::volatile_zero;
+use super::{Access, Caching, MutView, Snapshot, View, ViewMode};
+
+/// Identifies a checkpoint of a [`Snapshot`], see
+/// [`Snapshot::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CheckpointId(u64);
+
+/// The stack of checkpoints of a snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct Checkpoints {
+    next_id: u64,
+    levels: Vec<Level>,
+}
+
+/// A checkpoint, with the original content of the pages modified after
+/// it was taken, and before the next checkpoint was taken.
+#[derive(Debug)]
+struct Level {
+    id: u64,
+    pages: BTreeMap<usize, Box<[u8]>>,
+}
+
+/// Records the changes of a mutable view of a snapshot with checkpoints.
+///
+/// The view is mapped as copy-on-write, and the pages that were modified
+/// are written back to the snapshot when the view is dropped, after
+/// stashing their original content in the current checkpoint.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    /// The checkpoints of the snapshot, which is mutably borrowed by the
+    /// view for as long as the journal exists.
+    checkpoints: NonNull<Checkpoints>,
+    /// A mutable mapping of the snapshot, created up front so that writing
+    /// the changes back can't fail.
+    target: View<()>,
+}
+
+impl Snapshot {
+    /// Take a checkpoint of the current content of this snapshot, which
+    /// can later be restored with [`rollback`](Snapshot::rollback).
+    ///
+    /// Checkpoints form a stack. The original content of every page that
+    /// is modified after a checkpoint is taken is stashed in it, so the
+    /// memory used by a checkpoint is proportional to the number of pages
+    /// modified, not to the size of the snapshot.
+    ///
+    /// While a snapshot has checkpoints, its [mutable views](Snapshot::view_mut)
+    /// are copy-on-write mappings, and their changes are written back to
+    /// the snapshot when they are dropped.
+    /// On Linux and Windows only the pages that were written are written
+    /// back, on other platforms every page is compared with the snapshot.
+    pub fn checkpoint(&mut self) -> CheckpointId {
+        let checkpoints = self.checkpoints.get_or_insert_with(Default::default);
+        let id = checkpoints.next_id;
+        checkpoints.next_id += 1;
+        checkpoints.levels.push(Level {
+            id,
+            pages: BTreeMap::new(),
+        });
+        CheckpointId(id)
+    }
+
+    /// Revert every change made to this snapshot since the checkpoint `id`
+    /// was taken.
+    /// Only the pages that were modified are written.
+    ///
+    /// The checkpoints taken after `id` are discarded, while `id` itself
+    /// is kept, so it can be rolled back to again.
+    /// Resizing the snapshot is not reverted, and pages past its current
+    /// size are not restored.
+    ///
+    /// Returns an `InvalidInput` error if `id` is not a checkpoint of this
+    /// snapshot, or if it was discarded.
+    pub fn rollback(&mut self, id: CheckpointId) -> std::io::Result<()> {
+        let pos = self.find_checkpoint(id)?;
+        self.make_unique()?;
+        let mut target = View::new((), self.as_raw_fd(), self.size, ViewMode::Mutable)?;
+
+        let page = super::page_size();
+        let checkpoints = self.checkpoints.as_mut().unwrap();
+        // restore the newest checkpoints first, so that the content stashed
+        // in older checkpoints takes precedence
+        for level in checkpoints.levels.drain(pos..).rev() {
+            for (idx, content) in level.pages {
+                if let Some(dst) = target
+                    .as_full_mut_slice()
+                    .get_mut(idx * page..(idx + 1) * page)
+                {
+                    dst.copy_from_slice(&content);
+                }
+            }
+        }
+        checkpoints.levels.push(Level {
+            id: id.0,
+            pages: BTreeMap::new(),
+        });
+        Ok(())
+    }
+
+    /// Discard the checkpoint `id`, and the checkpoints taken after it,
+    /// keeping the changes made since.
+    /// The changes can still be reverted by rolling back to an earlier
+    /// checkpoint.
+    ///
+    /// Returns an `InvalidInput` error if `id` is not a checkpoint of this
+    /// snapshot, or if it was discarded.
+    pub fn release_checkpoint(&mut self, id: CheckpointId) -> std::io::Result<()> {
+        let pos = self.find_checkpoint(id)?;
+        let checkpoints = self.checkpoints.as_mut().unwrap();
+        let released: Vec<_> = checkpoints.levels.drain(pos..).collect();
+        let Some(parent) = checkpoints.levels.last_mut() else {
+            self.checkpoints = None;
+            return Ok(());
+        };
+        for level in released {
+            for (idx, content) in level.pages {
+                parent.pages.entry(idx).or_insert(content);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of checkpoints of this snapshot.
+    pub fn checkpoint_count(&self) -> usize {
+        self.checkpoints
+            .as_ref()
+            .map_or(0, |checkpoints| checkpoints.levels.len())
+    }
+
+    /// Returns the position of the checkpoint `id` in the stack.
+    fn find_checkpoint(&self, id: CheckpointId) -> std::io::Result<usize> {
+        self.checkpoints
+            .as_ref()
+            .and_then(|checkpoints| checkpoints.position(id))
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Unknown checkpoint")
+            })
+    }
+
+    /// Create a mutable view of this snapshot that records its changes in
+    /// the current checkpoint, if the snapshot has checkpoints.
+    pub(crate) fn journaled_view_mut(&mut self) -> std::io::Result<Option<MutView<'_>>> {
+        let (fd, size) = (self.as_raw_fd(), self.size);
+        let Some(checkpoints) = self.checkpoints.as_deref_mut() else {
+            return Ok(None);
+        };
+        let checkpoints = NonNull::from(checkpoints);
+        let target = View::new((), fd, size, ViewMode::Mutable)?;
+        let mut view = MutView::new(self, fd, size, ViewMode::Cow)?;
+        view.write_back = true;
+        view.journal = Some(Box::new(Journal {
+            checkpoints,
+            target,
+        }));
+        Ok(Some(view))
+    }
+}
+
+impl Checkpoints {
+    fn position(&self, id: CheckpointId) -> Option<usize> {
+        self.levels.iter().position(|level| level.id == id.0)
+    }
+
+    /// Zeroize the content stashed in every checkpoint.
+    pub(crate) fn zeroize(&mut self) {
+        for level in &mut self.levels {
+            for content in level.pages.values_mut() {
+                volatile_zero(content);
+            }
+        }
+    }
+
+    /// Returns the number of pages stashed in each checkpoint.
+    #[cfg(test)]
+    pub(crate) fn stashed_pages(&self) -> Vec<usize> {
+        self.levels.iter().map(|level| level.pages.len()).collect()
+    }
+}
+
+impl Journal {
+    /// Write the changes of `view` back to the snapshot, stashing the
+    /// original content of the modified pages in the current checkpoint.
+    pub(crate) fn commit<S>(&mut self, view: &mut View<S>) {
+        let whole = std::iter::once(0..view.size).collect();
+        let dirty = if view.overlaid {
+            whole
+        } else {
+            view.dirty_pages_impl().unwrap_or(whole)
+        };
+        // inaccessible pages still need to be read
+        let _ = view.protect_impl(0..view.size, Access::READ, Caching::Default);
+
+        // SAFETY: the checkpoints live in their own allocation, owned by
+        // the snapshot, which the view borrows mutably, so nothing else
+        // accesses them until the view is dropped.
+        let checkpoints = unsafe { self.checkpoints.as_mut() };
+        let mut stash = checkpoints.levels.last_mut().map(|level| &mut level.pages);
+        let page = super::page_size();
+        for region in dirty {
+            for start in region.step_by(page) {
+                let src = &view.as_full_slice()[start..start + page];
+                let dst = &mut self.target.as_full_mut_slice()[start..start + page];
+                if src == dst {
+                    continue;
+                }
+                if let Some(stash) = stash.as_mut() {
+                    stash.entry(start / page).or_insert_with(|| dst.into());
+                }
+                dst.copy_from_slice(src);
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use super::{MutView, View};
+
+/// A cursor over the content of a [`View`], created with
+/// [`cursor`](View::cursor), that implements [`Read`] and [`Seek`].
+///
+/// Seeking past the end of the view is allowed, and reading from there
+/// returns zero bytes.
+#[derive(Debug, Clone)]
+pub struct ViewCursor<'a>(Cursor<&'a [u8]>);
+
+/// A cursor over the content of a [`MutView`], created with
+/// [`cursor_mut`](View::cursor_mut), that implements [`Read`], [`Write`]
+/// and [`Seek`].
+///
+/// Writes go directly to the root snapshot. Seeking past the end of the
+/// view is allowed, and writing from there writes zero bytes.
+#[derive(Debug)]
+pub struct ViewCursorMut<'a>(Cursor<&'a mut [u8]>);
+
+impl<S> View<S> {
+    /// Create a cursor to read the content of this view, starting at the
+    /// beginning of the view.
+    pub fn cursor(&self) -> ViewCursor<'_> {
+        ViewCursor(Cursor::new(self.as_slice()))
+    }
+}
+
+impl MutView<'_> {
+    /// Create a cursor to read and write the content of this view, starting
+    /// at the beginning of the view.
+    pub fn cursor_mut(&mut self) -> ViewCursorMut<'_> {
+        ViewCursorMut(Cursor::new(self.as_mut_slice()))
+    }
+}
+
+impl MutView<'_> {
+    /// Returns the offset where the next write through the [`Write`]
+    /// implementation of this view will start.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Set the offset where the next write through the [`Write`]
+    /// implementation of this view will start.
+    /// The offset can be past the end of the view, in which case writes
+    /// write zero bytes.
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+}
+
+/// Writes to a mutable view start at its [`position`](View::position),
+/// which starts at zero and advances with each write.
+/// Writes that don't fit in the view are truncated, and writes at the end
+/// of the view write zero bytes.
+impl Write for MutView<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = self.position;
+        if start >= self.len {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.len - start);
+        self.as_mut_slice()[start..start + len].copy_from_slice(&buf[..len]);
+        self.position += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ViewCursor<'_> {
+    /// Returns the current position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.0.position()
+    }
+}
+
+impl ViewCursorMut<'_> {
+    /// Returns the current position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.0.position()
+    }
+}
+
+impl Read for ViewCursor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for ViewCursor<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl Read for ViewCursorMut<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for ViewCursorMut<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for ViewCursorMut<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
@@ -0,0 +1,100 @@
+//! `Debug` implementations that show a preview of the content.
+//!
+//! Only a bounded window at the start and at the end of the content is
+//! read, so formatting is cheap for views and snapshots of any size.
+//! Bytes of a view that are protected against reads are shown as `??`
+//! instead of faulting.
+
+use super::{Access, Snapshot, View};
+
+/// The number of bytes shown at the start and at the end of the content.
+const PREVIEW_LEN: usize = 4;
+
+/// A hex preview of `len` bytes, like `[48 65 6c 6c ... 00 00 00 00]`,
+/// where `byte` returns the byte at an offset, or `None` if it can't be
+/// read.
+struct Preview<F> {
+    len: usize,
+    byte: F,
+}
+
+impl<F: Fn(usize) -> Option<u8>> Preview<F> {
+    fn write_byte(&self, f: &mut std::fmt::Formatter<'_>, offset: usize) -> std::fmt::Result {
+        match (self.byte)(offset) {
+            Some(byte) => write!(f, "{byte:02x}"),
+            None => f.write_str("??"),
+        }
+    }
+}
+
+impl<F: Fn(usize) -> Option<u8>> std::fmt::Debug for Preview<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[")?;
+        if self.len <= 2 * PREVIEW_LEN {
+            for offset in 0..self.len {
+                if offset > 0 {
+                    f.write_str(" ")?;
+                }
+                self.write_byte(f, offset)?;
+            }
+        } else {
+            for offset in 0..PREVIEW_LEN {
+                self.write_byte(f, offset)?;
+                f.write_str(" ")?;
+            }
+            f.write_str("...")?;
+            for offset in self.len - PREVIEW_LEN..self.len {
+                f.write_str(" ")?;
+                self.write_byte(f, offset)?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+impl<S> View<S> {
+    /// Returns a preview of the content of the view, skipping the bytes
+    /// that can't be read.
+    fn preview(&self) -> Preview<impl Fn(usize) -> Option<u8> + '_> {
+        let default = Access::READ | Access::WRITE;
+        Preview {
+            len: self.len,
+            byte: move |offset| {
+                let access = self.protection.get(offset).unwrap_or(default);
+                access
+                    .implied()
+                    .contains(Access::READ)
+                    .then(|| unsafe { self.ptr.add(offset).read() })
+            },
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for View<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("View")
+            .field("size", &self.size)
+            .field("len", &self.len)
+            .field("offset", &self.offset)
+            .field("mode", &self.mode)
+            .field("content", &self.preview())
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("Snapshot");
+        f.field("size", &self.size).field("len", &self.len);
+        if self.len == 0 {
+            // don't map a view just to show nothing
+            f.field("content", &format_args!("[]"));
+        } else {
+            match self.view() {
+                Ok(view) => f.field("content", &view.preview()),
+                Err(err) => f.field("content", &format_args!("<{err}>")),
+            };
+        }
+        f.finish_non_exhaustive()
+    }
+}
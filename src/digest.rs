@@ -0,0 +1,49 @@
+use sha2::{Digest as _, Sha256, Sha512};
+
+use super::Snapshot;
+
+/// Digest algorithms supported by
+/// [`from_file_verified`](Snapshot::from_file_verified).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgo {
+    /// SHA-256, with a 32 bytes digest.
+    Sha256,
+
+    /// SHA-512, with a 64 bytes digest.
+    Sha512,
+}
+
+impl DigestAlgo {
+    /// Returns the digest of `buf` with this algorithm.
+    pub fn digest(&self, buf: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgo::Sha256 => Sha256::digest(buf).to_vec(),
+            DigestAlgo::Sha512 => Sha512::digest(buf).to_vec(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Create a new snapshot from a file, like
+    /// [`from_file`](Snapshot::from_file), verifying that the digest of
+    /// the content of the file matches `expected`.
+    /// The digest is computed over the content of the file only, not
+    /// including the padding to the system page size.
+    /// Returns an `InvalidData` error if the digest doesn't match.
+    pub fn from_file_verified(
+        file: std::fs::File,
+        expected: &[u8],
+        algo: DigestAlgo,
+    ) -> std::io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        let snapshot = Self::from_file(file)?;
+        let digest = algo.digest(&snapshot.view()?[..len]);
+        if digest != expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Snapshot digest does not match the expected digest",
+            ));
+        }
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,87 @@
+/// The errors of this crate that are not failures of the system, to tell
+/// them apart from each other.
+///
+/// Fallible methods return [`std::io::Error`], which wraps these errors,
+/// so they can be recovered with [`SnapshotError::from`]:
+///
+/// ```
+/// # use memsnap::{Access, Snapshot, SnapshotError};
+/// let snapshot = Snapshot::zeroed(memsnap::page_size())?;
+/// let mut view = snapshot.view()?;
+/// let err = view.protect(1.., Access::READ).unwrap_err();
+/// assert!(matches!(SnapshotError::from(err), SnapshotError::NotPageAligned));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// A range or offset that must be page-aligned isn't.
+    /// Wrapped in an `InvalidInput` error.
+    NotPageAligned,
+    /// A range is empty, reversed or out of the bounds of its view or
+    /// snapshot, or an index is out of bounds.
+    /// Wrapped in an `InvalidInput` error.
+    RangeOutOfBounds,
+    /// A range used to slice a view is out of its bounds, see
+    /// [`View::slice`](crate::View::slice).
+    /// Wrapped in an `InvalidInput` error.
+    SliceOutOfBounds {
+        /// The range that was attempted.
+        range: std::ops::Range<usize>,
+        /// The length of the view.
+        len: usize,
+    },
+    /// The operation is not supported on this platform, or by the system.
+    /// Wrapped in an `Unsupported` error.
+    Unsupported(&'static str),
+    /// Any other error, e.g., a failure of the system.
+    Os(std::io::Error),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotPageAligned => f.write_str("Range must be page-aligned"),
+            Self::RangeOutOfBounds => f.write_str("Range is empty or out of bounds"),
+            Self::SliceOutOfBounds { range, len } => write!(
+                f,
+                "range {}..{} out of bounds for view of length {len}",
+                range.start, range.end
+            ),
+            Self::Unsupported(what) => f.write_str(what),
+            Self::Os(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Os(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<SnapshotError> for std::io::Error {
+    fn from(err: SnapshotError) -> Self {
+        let kind = match err {
+            SnapshotError::Os(err) => return err,
+            SnapshotError::Unsupported(_) => std::io::ErrorKind::Unsupported,
+            _ => std::io::ErrorKind::InvalidInput,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    /// Recover the error wrapped in `err`, or wrap `err` in
+    /// [`SnapshotError::Os`] if it doesn't wrap one.
+    fn from(err: std::io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Self>()) {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Self>().unwrap();
+        }
+        Self::Os(err)
+    }
+}
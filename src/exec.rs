@@ -0,0 +1,79 @@
+use std::ops::{Deref, Range, RangeBounds};
+
+use super::{protection_range, Access, MutView};
+
+/// A [`MutView`] with a region of executable code, created with
+/// [`into_executable`](MutView::into_executable).
+///
+/// The region can be read and executed, but not written, and the view
+/// can't be written through this wrapper, so its memory is never writable
+/// and executable at the same time.
+/// The wrapper dereferences to the whole [capacity](super::View::capacity)
+/// of the view, since the executable region is page-aligned and can extend
+/// past the length of the view.
+/// Use [`into_mut`](ExecView::into_mut) to get the mutable view back,
+/// which makes the region writable again, and no longer executable.
+#[derive(Debug)]
+pub struct ExecView<'a> {
+    view: MutView<'a>,
+    region: Range<usize>,
+}
+
+impl MutView<'_> {
+    /// Make a memory region of this view executable, allowing it to be
+    /// read and executed, but no longer written.
+    /// The same restrictions as for [`protect`](super::View::protect) apply
+    /// to the `region` parameter.
+    ///
+    /// On Windows, and on Linux on aarch64, the instruction cache is
+    /// flushed for the region, so that the code written to it before is
+    /// executed. On x86 the instruction cache is always coherent.
+    /// On other architectures, callers must flush the instruction cache
+    /// themselves, e.g., with `__builtin___clear_cache`.
+    pub fn make_executable(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        self.protect(region, Access::READ | Access::EXEC)
+    }
+}
+
+impl<'a> MutView<'a> {
+    /// Make a memory region of this view executable, like
+    /// [`make_executable`](MutView::make_executable), and wrap the view in
+    /// an [`ExecView`] that doesn't allow writing to it.
+    pub fn into_executable(
+        mut self,
+        region: impl RangeBounds<usize>,
+    ) -> std::io::Result<ExecView<'a>> {
+        let region = protection_range(region, self.size)?;
+        self.make_executable(region.clone())?;
+        Ok(ExecView { view: self, region })
+    }
+}
+
+impl<'a> ExecView<'a> {
+    /// Returns the executable region of the view.
+    pub fn region(&self) -> Range<usize> {
+        self.region.clone()
+    }
+
+    /// Returns a pointer to the start of the executable region.
+    pub fn as_ptr(&self) -> *const u8 {
+        // SAFETY: the region is within the capacity of the view
+        unsafe { self.view.as_ptr().add(self.region.start) }
+    }
+
+    /// Consumes the wrapper and returns the mutable view, with the
+    /// executable region allowed to be read and written again.
+    pub fn into_mut(mut self) -> std::io::Result<MutView<'a>> {
+        self.view
+            .protect(self.region, Access::READ | Access::WRITE)?;
+        Ok(self.view)
+    }
+}
+
+impl Deref for ExecView<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.view.as_full_slice()
+    }
+}
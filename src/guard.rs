@@ -0,0 +1,63 @@
+use std::ops::{Deref, DerefMut, Range, RangeBounds};
+
+use super::{protection_range, Access, Caching, View};
+
+/// A guard that restricts the access permissions of a memory region of a
+/// [`View`] while it's alive, created with
+/// [`protect_scoped`](View::protect_scoped).
+///
+/// When the guard is dropped, every page of the region gets back the
+/// access permissions and caching attributes it had before the guard was
+/// created.
+/// The guard dereferences to its view, so the view can still be used
+/// while the guard is alive.
+#[derive(Debug)]
+pub struct ProtectionGuard<'a, S> {
+    view: &'a mut View<S>,
+    old: Vec<(Range<usize>, Access, Caching)>,
+}
+
+impl<S> View<S> {
+    /// Restrict the access permissions of a memory region on this view,
+    /// like [`protect`](View::protect), until the returned guard is dropped.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter.
+    ///
+    /// Dropping the guard restores the access permissions that each page of
+    /// the region had when the guard was created, which are not necessarily
+    /// the default access of the view.
+    /// Errors restoring the permissions when the guard is dropped are
+    /// ignored.
+    pub fn protect_scoped(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        allow: Access,
+    ) -> std::io::Result<ProtectionGuard<'_, S>> {
+        let region = protection_range(region, self.size)?;
+        let old = self.protection_runs(region.clone());
+        self.set_protection(region, allow, Caching::Default)?;
+        Ok(ProtectionGuard { view: self, old })
+    }
+}
+
+impl<S> Deref for ProtectionGuard<'_, S> {
+    type Target = View<S>;
+
+    fn deref(&self) -> &View<S> {
+        self.view
+    }
+}
+
+impl<S> DerefMut for ProtectionGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut View<S> {
+        self.view
+    }
+}
+
+impl<S> Drop for ProtectionGuard<'_, S> {
+    fn drop(&mut self) {
+        for (run, old, caching) in std::mem::take(&mut self.old) {
+            let _ = self.view.set_protection(run, old, caching);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use std::ffi::CStr;
+use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::ptr::null_mut;
+
+use libc::{
+    MAP_ANON, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_NONE, PROT_READ,
+    PROT_WRITE,
+};
+
+pub type OwnedFileDescriptor = OwnedFd;
+pub type RawFileDescriptor = RawFd;
+
+use super::{
+    create_temp_file, effective_size, Access, Advice, Caching, Snapshot, SnapshotError, View,
+    ViewMode,
+};
+
+impl Snapshot {
+    pub(super) fn from_file_impl(file: std::fs::File) -> std::io::Result<Self> {
+        let size = file.metadata()?.len() as usize;
+        let size = size.next_multiple_of(super::page_size());
+        let file = file.into();
+
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn zeroed_impl(size: usize, name: &CStr) -> std::io::Result<Self> {
+        let size = size.next_multiple_of(super::page_size());
+        let mut fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
+        if fd < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::ENOSYS) {
+            // memfd_create is only available since FreeBSD 13, fall back
+            // to an anonymous shared memory object, which has no name to
+            // unlink
+            fd = unsafe { libc::shm_open(libc::SHM_ANON, libc::O_RDWR, 0o600) };
+        }
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+
+        // mapping a shared memory object past its end fails, so always give
+        // it at least a page
+        file.set_len(effective_size(size) as u64)?;
+        let file = file.into();
+
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn temp_file_impl(dir: &std::path::Path) -> std::io::Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).mode(0o600);
+        let (file, path) = create_temp_file(dir, &options)?;
+        std::fs::remove_file(path)?;
+        Ok(file)
+    }
+
+    pub(super) fn resize_impl(&mut self, new_size: usize) -> std::io::Result<bool> {
+        let fd = self.file.as_raw_fd();
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let len = if stat.st_mode & libc::S_IFMT == libc::S_IFREG {
+            new_size
+        } else {
+            effective_size(new_size)
+        };
+        let res = unsafe { libc::ftruncate(fd, len as _) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // the backing is not writable
+                Some(libc::EBADF | libc::EINVAL | libc::EPERM) => Ok(false),
+                _ => Err(err),
+            };
+        }
+        Ok(true)
+    }
+
+    pub(super) fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    pub(super) fn copy_to_file_impl(&self, file: &std::fs::File) -> std::io::Result<bool> {
+        copy_range_impl(self.as_raw_fd(), 0, file.as_raw_fd(), 0, self.size)
+    }
+
+    pub(super) fn clone_cow_impl(&self) -> std::io::Result<Option<Self>> {
+        // there are no reflinks on FreeBSD
+        Ok(None)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn map_impl(fd: RawFd, size: usize, mode: ViewMode) -> std::io::Result<*mut u8> {
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                effective_size(size),
+                PROT_READ | PROT_WRITE,
+                mode.as_posix(),
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(ptr as *mut u8)
+    }
+
+    pub(super) fn map_populated_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // There is no MAP_POPULATE, ask for the pages to be read ahead
+        // instead. This is only a hint, so failing to do it is not an error.
+        let ptr = Self::map_impl(fd, size, mode)?;
+        unsafe { libc::madvise(ptr as _, effective_size(size), libc::MADV_WILLNEED) };
+        Ok(ptr)
+    }
+
+    pub(super) fn map_guarded_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // Reserve the address range of the view and its guard pages, and
+        // map the view over the middle of it.
+        let guard = super::page_size();
+        let len = effective_size(size);
+        let base = unsafe {
+            libc::mmap(
+                null_mut(),
+                len + 2 * guard,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        if base == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                base.byte_add(guard),
+                len,
+                PROT_READ | PROT_WRITE,
+                mode.as_posix() | MAP_FIXED,
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::munmap(base, len + 2 * guard) };
+            return Err(err);
+        }
+
+        Ok(ptr as *mut u8)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn restore_impl(&mut self) -> std::io::Result<()> {
+        let new_ptr = unsafe {
+            libc::mmap(
+                self.ptr as _,
+                effective_size(self.size),
+                PROT_READ | PROT_WRITE,
+                self.mode.as_posix() | MAP_FIXED,
+                self.fd,
+                0,
+            )
+        };
+        if new_ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn restore_range_impl(&mut self, offset: Range<usize>) -> std::io::Result<()> {
+        let new_ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                self.mode.as_posix() | MAP_FIXED,
+                self.fd,
+                (self.offset + offset.start) as _,
+            )
+        };
+        if new_ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn protect_impl(
+        &mut self,
+        offset: Range<usize>,
+        allow: Access,
+        _caching: Caching,
+    ) -> std::io::Result<()> {
+        let res = unsafe {
+            libc::mprotect(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                allow.as_posix(),
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn dirty_pages_impl(&self) -> std::io::Result<Vec<Range<usize>>> {
+        // there is no cheap way to tell the pages that were written
+        Ok(std::iter::once(0..self.size).collect())
+    }
+
+    pub(super) fn residency_impl(&self, offset: Range<usize>) -> std::io::Result<Vec<bool>> {
+        let mut pages = vec![0 as libc::c_char; offset.len() / super::page_size()];
+        let res = unsafe {
+            libc::mincore(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                pages.as_mut_ptr(),
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(pages
+            .into_iter()
+            .map(|page| page as libc::c_int & libc::MINCORE_INCORE != 0)
+            .collect())
+    }
+
+    pub(super) fn query_protection_impl(&self, offset: usize) -> std::io::Result<Access> {
+        let addr = self.ptr as u64 + offset as u64;
+        let mut count = 0;
+        let entries = unsafe { libc::kinfo_getvmmap(libc::getpid(), &mut count) };
+        if entries.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        let protection = unsafe { std::slice::from_raw_parts(entries, count.max(0) as usize) }
+            .iter()
+            .find(|entry| entry.kve_start <= addr && addr < entry.kve_end)
+            .map(|entry| entry.kve_protection);
+        unsafe { libc::free(entries as _) };
+        let Some(protection) = protection else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "View is not mapped",
+            ));
+        };
+
+        let mut access = Access::NONE;
+        if protection & libc::KVME_PROT_READ != 0 {
+            access |= Access::READ;
+        }
+        if protection & libc::KVME_PROT_WRITE != 0 {
+            access |= Access::WRITE;
+        }
+        if protection & libc::KVME_PROT_EXEC != 0 {
+            access |= Access::EXEC;
+        }
+        Ok(access)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn overlay_impl(
+        &mut self,
+        offset: Range<usize>,
+        snapshot: &Snapshot,
+        src_offset: usize,
+    ) -> std::io::Result<()> {
+        let ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_FIXED,
+                snapshot.as_raw_fd(),
+                src_offset as _,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn unmap_impl(&mut self) {
+        let guard = if self.guarded { super::page_size() } else { 0 };
+        unsafe {
+            libc::munmap(
+                self.ptr.sub(guard) as _,
+                effective_size(self.size) + 2 * guard,
+            );
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn zero_pages_impl(&mut self, offset: Range<usize>) -> std::io::Result<bool> {
+        match self.mode {
+            // punching holes in shared memory objects needs fspacectl,
+            // which is only available since FreeBSD 14
+            ViewMode::Mutable => Ok(false),
+            ViewMode::Cow => {
+                let ptr = unsafe {
+                    libc::mmap(
+                        self.ptr.add(offset.start) as _,
+                        offset.len(),
+                        PROT_READ | PROT_WRITE,
+                        MAP_PRIVATE | MAP_ANON | MAP_FIXED,
+                        -1,
+                        0,
+                    )
+                };
+                if ptr == MAP_FAILED {
+                    return Err(std::io::Error::last_os_error());
+                }
+                self.overlaid = true;
+                self.reapply_protection(offset)?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn advise_impl(
+        &mut self,
+        offset: Range<usize>,
+        advice: Advice,
+    ) -> std::io::Result<bool> {
+        if advice == Advice::DontNeed && self.mode == ViewMode::Cow {
+            // MADV_DONTNEED doesn't discard the changes to private
+            // mappings on FreeBSD
+            return Ok(false);
+        }
+        let advice = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::HugePage | Advice::NoHugePage => {
+                return Err(std::io::Error::from(SnapshotError::Unsupported(
+                    "Transparent huge pages are not supported on FreeBSD",
+                )))
+            }
+        };
+        let res = unsafe { libc::madvise(self.ptr.add(offset.start) as _, offset.len(), advice) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn lock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::mlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOMEM) {
+                return Err(std::io::Error::new(
+                    err.kind(),
+                    "Locking the memory region would exceed RLIMIT_MEMLOCK",
+                ));
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(super) fn unlock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::munlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn populate_write_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn flush_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res =
+            unsafe { libc::msync(self.ptr.add(offset.start) as _, offset.len(), libc::MS_SYNC) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn copy_range_impl(
+    src: RawFd,
+    src_offset: usize,
+    dst: RawFd,
+    dst_offset: usize,
+    len: usize,
+) -> std::io::Result<bool> {
+    let mut src_offset = src_offset as libc::off_t;
+    let mut dst_offset = dst_offset as libc::off_t;
+    let mut remaining = len;
+    while remaining > 0 {
+        let res = unsafe {
+            libc::copy_file_range(src, &mut src_offset, dst, &mut dst_offset, remaining, 0)
+        };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // copy_file_range is only available since FreeBSD 13, and
+                // only between regular files
+                Some(libc::ENOSYS | libc::EINVAL | libc::EBADF | libc::EOPNOTSUPP) => Ok(false),
+                _ => Err(err),
+            };
+        }
+        if res == 0 {
+            // end of the source file, the rest is zero, but the
+            // destination may not be
+            return Ok(false);
+        }
+        remaining -= res as usize;
+    }
+    Ok(true)
+}
+
+/// Returns the number of minor and major page faults of the process.
+pub(super) fn fault_counts_impl() -> (u64, u64) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } < 0 {
+        return (0, 0);
+    }
+    (usage.ru_minflt as u64, usage.ru_majflt as u64)
+}
+
+#[cfg(debug_assertions)]
+pub(super) fn backing_key(fd: RawFd, _ptr: *const u8) -> (u64, u64) {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return (u64::MAX, fd as u64);
+    }
+    (stat.st_dev as u64, u64::from(stat.st_ino))
+}
+
+impl Access {
+    fn as_posix(&self) -> libc::c_int {
+        let mut access = 0;
+        if *self == Access::NONE {
+            access = PROT_NONE;
+        } else {
+            if self.contains(Access::READ) {
+                access |= PROT_READ;
+            }
+            if self.contains(Access::WRITE) {
+                access |= PROT_WRITE | PROT_READ;
+            }
+            if self.contains(Access::EXEC) {
+                access |= PROT_EXEC | PROT_READ;
+            }
+        }
+        access
+    }
+}
+
+impl ViewMode {
+    fn as_posix(&self) -> libc::c_int {
+        match self {
+            ViewMode::Cow => MAP_PRIVATE,
+            ViewMode::Mutable => MAP_SHARED,
+        }
+    }
+}
@@ -1,29 +1,53 @@
+use std::ffi::CStr;
 use std::ops::Range;
 use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{FileExt as _, MetadataExt as _, OpenOptionsExt as _};
 use std::ptr::null_mut;
 
 use libc::{
-    MAP_FAILED, MAP_FIXED, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_NONE, PROT_READ,
-    PROT_WRITE,
+    MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_NORESERVE, MAP_POPULATE, MAP_PRIVATE, MAP_SHARED,
+    PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE,
 };
 
 pub type OwnedFileDescriptor = OwnedFd;
 pub type RawFileDescriptor = RawFd;
 
-use super::{effective_size, Access, Snapshot, View, ViewMode};
+use super::{create_temp_file, effective_size, Access, Advice, Caching, Snapshot, View, ViewMode};
 
 impl Snapshot {
     pub(super) fn from_file_impl(file: std::fs::File) -> std::io::Result<Self> {
-        let size = file.metadata()?.len() as usize;
-        let size = size.next_multiple_of(page_size::get());
+        let metadata = file.metadata()?;
+        let size = metadata.len() as usize;
+        let mut alignment = super::page_size();
+
+        let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+        if flags < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if flags & libc::O_DIRECT != 0 {
+            // Direct I/O requires the file length to be a multiple of the
+            // block size of the file, which may be larger than a page.
+            let block_size = metadata.blksize() as usize;
+            if size % block_size != 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Direct I/O file length {size} is not a multiple of its block size {block_size}"
+                    ),
+                ));
+            }
+            alignment = alignment.max(block_size);
+        }
+
+        let size = size.next_multiple_of(alignment);
         let file = file.into();
 
-        Ok(Self { file, size })
+        Ok(Self::new(file, size))
     }
 
-    pub(super) fn zeroed_impl(size: usize) -> std::io::Result<Self> {
-        let size = size.next_multiple_of(page_size::get());
-        let fd = unsafe { libc::memfd_create(c"hyperlight_snapshot".as_ptr() as _, 0) };
+    pub(super) fn zeroed_impl(size: usize, name: &CStr) -> std::io::Result<Self> {
+        let size = size.next_multiple_of(super::page_size());
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
         if fd < 0 {
             return Err(std::io::Error::last_os_error());
         }
@@ -31,21 +55,91 @@ impl Snapshot {
         file.set_len(size as u64)?;
         let file = file.into();
 
-        Ok(Self { file, size })
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn temp_file_impl(dir: &std::path::Path) -> std::io::Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).mode(0o600);
+        match options.clone().custom_flags(libc::O_TMPFILE).open(dir) {
+            Ok(file) => return Ok(file),
+            // the file system or the kernel doesn't support unnamed files
+            Err(err) if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP | libc::EISDIR)) => {}
+            Err(err) => return Err(err),
+        }
+        let (file, path) = create_temp_file(dir, &options)?;
+        std::fs::remove_file(path)?;
+        Ok(file)
+    }
+
+    pub(super) fn resize_impl(&mut self, new_size: usize) -> std::io::Result<bool> {
+        let res = unsafe { libc::ftruncate(self.file.as_raw_fd(), new_size as _) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // the backing is not a writable regular file
+                Some(libc::EBADF | libc::EINVAL | libc::EPERM) => Ok(false),
+                _ => Err(err),
+            };
+        }
+        Ok(true)
     }
 
     pub(super) fn as_raw_fd(&self) -> RawFd {
         self.file.as_raw_fd()
     }
+
+    pub(super) fn copy_to_file_impl(&self, file: &std::fs::File) -> std::io::Result<bool> {
+        copy_range_impl(self.as_raw_fd(), 0, file.as_raw_fd(), 0, self.size)
+    }
+
+    pub(super) fn clone_cow_impl(&self) -> std::io::Result<Option<Self>> {
+        // Reflinks only work within a file system, so clone a regular file
+        // into an unnamed file in its directory, and anything else into a
+        // new memfd.
+        const FICLONE: libc::c_ulong = 0x40049409;
+
+        let Ok(path) = std::fs::read_link(format!("/proc/self/fd/{}", self.as_raw_fd())) else {
+            return Ok(None);
+        };
+        let file = match path.parent() {
+            Some(dir) if path.is_absolute() && !path.starts_with("/memfd:") => {
+                match std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .custom_flags(libc::O_TMPFILE)
+                    .open(dir)
+                {
+                    Ok(file) => file,
+                    // the file system doesn't support unnamed files
+                    Err(_) => return Ok(None),
+                }
+            }
+            _ => {
+                let fd = unsafe { libc::memfd_create(c"hyperlight_snapshot".as_ptr(), 0) };
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                unsafe { std::fs::File::from_raw_fd(fd) }
+            }
+        };
+
+        let res = unsafe { libc::ioctl(file.as_raw_fd(), FICLONE as _, self.as_raw_fd()) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(
+                    libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOTTY | libc::EBADF,
+                ) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(Some(Self::new(file.into(), self.size)))
+    }
 }
 
 impl<S> View<S> {
-    pub(super) fn new(
-        snapshot: S,
-        fd: RawFd,
-        size: usize,
-        mode: ViewMode,
-    ) -> std::io::Result<Self> {
+    pub(super) fn map_impl(fd: RawFd, size: usize, mode: ViewMode) -> std::io::Result<*mut u8> {
         let ptr = unsafe {
             libc::mmap(
                 null_mut(),
@@ -60,15 +154,79 @@ impl<S> View<S> {
             return Err(std::io::Error::last_os_error());
         }
 
-        let ptr = ptr as *mut u8;
+        Ok(ptr as *mut u8)
+    }
+
+    pub(super) fn map_populated_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // Populating a writable private mapping would copy every page, so
+        // populate it read only and make it writable afterwards. The kernel
+        // maps the pages it can, and ignores the rest.
+        let len = effective_size(size);
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                len,
+                PROT_READ,
+                mode.as_posix() | MAP_NORESERVE | MAP_POPULATE,
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        if unsafe { libc::mprotect(ptr, len, PROT_READ | PROT_WRITE) } < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::munmap(ptr, len) };
+            return Err(err);
+        }
 
-        Ok(Self {
-            fd,
-            ptr,
-            size,
-            mode,
-            _snapshot: snapshot,
-        })
+        Ok(ptr as *mut u8)
+    }
+
+    pub(super) fn map_guarded_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // Reserve the address range of the view and its guard pages, and
+        // map the view over the middle of it.
+        let guard = super::page_size();
+        let len = effective_size(size);
+        let base = unsafe {
+            libc::mmap(
+                null_mut(),
+                len + 2 * guard,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if base == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                base.byte_add(guard),
+                len,
+                PROT_READ | PROT_WRITE,
+                mode.as_posix() | MAP_NORESERVE | MAP_FIXED,
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::munmap(base, len + 2 * guard) };
+            return Err(err);
+        }
+
+        Ok(ptr as *mut u8)
     }
 }
 
@@ -90,10 +248,28 @@ impl<S> View<S> {
         Ok(())
     }
 
+    pub(super) fn restore_range_impl(&mut self, offset: Range<usize>) -> std::io::Result<()> {
+        let new_ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                self.mode.as_posix() | MAP_NORESERVE | MAP_FIXED,
+                self.fd,
+                (self.offset + offset.start) as _,
+            )
+        };
+        if new_ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
     pub(super) fn protect_impl(
         &mut self,
         offset: Range<usize>,
         allow: Access,
+        _caching: Caching,
     ) -> std::io::Result<()> {
         let res = unsafe {
             libc::mprotect(
@@ -105,20 +281,330 @@ impl<S> View<S> {
         if res < 0 {
             return Err(std::io::Error::last_os_error());
         }
+        #[cfg(target_arch = "aarch64")]
+        if allow.contains(Access::EXEC) {
+            // make sure the code written to the region is executed
+            unsafe { clear_cache(self.ptr.add(offset.start), offset.len()) };
+        }
+        Ok(())
+    }
+
+    pub(super) fn dirty_pages_impl(&self) -> std::io::Result<Vec<Range<usize>>> {
+        // A written page of a private file mapping is replaced with an
+        // anonymous copy, so it's no longer reported as a file page, or it
+        // is reported as swapped if the copy was swapped out.
+        const PRESENT: u64 = 1 << 63;
+        const SWAPPED: u64 = 1 << 62;
+        const FILE_PAGE: u64 = 1 << 61;
+
+        let page = super::page_size();
+        let mut entries = vec![0u8; self.size / page * 8];
+        let pagemap = std::fs::File::open("/proc/self/pagemap")?;
+        pagemap.read_exact_at(&mut entries, (self.ptr as usize / page * 8) as u64)?;
+
+        let mut dirty: Vec<Range<usize>> = Vec::new();
+        for (idx, entry) in entries.chunks_exact(8).enumerate() {
+            let entry = u64::from_ne_bytes(entry.try_into().unwrap());
+            if entry & SWAPPED == 0 && (entry & PRESENT == 0 || entry & FILE_PAGE != 0) {
+                continue;
+            }
+            let start = idx * page;
+            match dirty.last_mut() {
+                Some(last) if last.end == start => last.end = start + page,
+                _ => dirty.push(start..start + page),
+            }
+        }
+        Ok(dirty)
+    }
+
+    pub(super) fn residency_impl(&self, offset: Range<usize>) -> std::io::Result<Vec<bool>> {
+        let mut pages = vec![0u8; offset.len() / super::page_size()];
+        let res = unsafe {
+            libc::mincore(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                pages.as_mut_ptr(),
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(pages.into_iter().map(|page| page & 1 != 0).collect())
+    }
+
+    pub(super) fn query_protection_impl(&self, offset: usize) -> std::io::Result<Access> {
+        // mincore and friends don't report the protection of a page, so
+        // look up the mapping containing it in /proc/self/maps.
+        let addr = self.ptr as usize + offset;
+        let maps = std::fs::read_to_string("/proc/self/maps")?;
+        for line in maps.lines() {
+            let mut fields = line.split_ascii_whitespace();
+            let (Some(range), Some(perms)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let Some((start, end)) = range.split_once('-') else {
+                continue;
+            };
+            let (Ok(start), Ok(end)) = (
+                usize::from_str_radix(start, 16),
+                usize::from_str_radix(end, 16),
+            ) else {
+                continue;
+            };
+            if (start..end).contains(&addr) {
+                let mut access = Access::NONE;
+                for (flag, bit) in perms
+                    .chars()
+                    .zip([Access::READ, Access::WRITE, Access::EXEC])
+                {
+                    if flag != '-' {
+                        access |= bit;
+                    }
+                }
+                return Ok(access);
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "View is not mapped",
+        ))
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn overlay_impl(
+        &mut self,
+        offset: Range<usize>,
+        snapshot: &Snapshot,
+        src_offset: usize,
+    ) -> std::io::Result<()> {
+        let ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_NORESERVE | MAP_FIXED,
+                snapshot.as_raw_fd(),
+                src_offset as _,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
         Ok(())
     }
 }
 
-impl<S> Drop for View<S> {
-    fn drop(&mut self) {
+impl<S> View<S> {
+    pub(super) fn unmap_impl(&mut self) {
+        let guard = if self.guarded { super::page_size() } else { 0 };
         unsafe {
-            libc::munmap(self.ptr as _, self.size);
+            libc::munmap(
+                self.ptr.sub(guard) as _,
+                effective_size(self.size) + 2 * guard,
+            );
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn zero_pages_impl(&mut self, offset: Range<usize>) -> std::io::Result<bool> {
+        match self.mode {
+            ViewMode::Mutable => {
+                let res = unsafe {
+                    libc::fallocate(
+                        self.fd,
+                        libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                        (self.offset + offset.start) as _,
+                        offset.len() as _,
+                    )
+                };
+                if res < 0 {
+                    let err = std::io::Error::last_os_error();
+                    if err.raw_os_error() == Some(libc::EOPNOTSUPP) {
+                        return Ok(false);
+                    }
+                    return Err(err);
+                }
+            }
+            ViewMode::Cow => {
+                let ptr = unsafe {
+                    libc::mmap(
+                        self.ptr.add(offset.start) as _,
+                        offset.len(),
+                        PROT_READ | PROT_WRITE,
+                        MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | MAP_FIXED,
+                        -1,
+                        0,
+                    )
+                };
+                if ptr == MAP_FAILED {
+                    return Err(std::io::Error::last_os_error());
+                }
+                self.overlaid = true;
+                self.reapply_protection(offset)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn advise_impl(
+        &mut self,
+        offset: Range<usize>,
+        advice: Advice,
+    ) -> std::io::Result<bool> {
+        let advice = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::HugePage => libc::MADV_HUGEPAGE,
+            Advice::NoHugePage => libc::MADV_NOHUGEPAGE,
+        };
+        let res = unsafe { libc::madvise(self.ptr.add(offset.start) as _, offset.len(), advice) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
         }
+        Ok(true)
     }
 }
 
+impl<S> View<S> {
+    pub(super) fn lock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::mlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOMEM) {
+                return Err(std::io::Error::new(
+                    err.kind(),
+                    "Locking the memory region would exceed RLIMIT_MEMLOCK",
+                ));
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(super) fn unlock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::munlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn populate_write_impl(&mut self, offset: Range<usize>) -> std::io::Result<bool> {
+        let res = unsafe {
+            libc::madvise(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                libc::MADV_POPULATE_WRITE,
+            )
+        };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            // kernels older than 5.14 don't support MADV_POPULATE_WRITE
+            if err.raw_os_error() == Some(libc::EINVAL) {
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    pub(super) fn flush_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res =
+            unsafe { libc::msync(self.ptr.add(offset.start) as _, offset.len(), libc::MS_SYNC) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Copy `len` bytes from `src` to `dst` in the kernel, without mapping
+/// them in userspace.
+/// Bytes past the end of `src` are not copied.
+/// Returns `false` if the kernel can't copy between the two descriptors.
+pub(super) fn copy_range_impl(
+    src: RawFd,
+    src_offset: usize,
+    dst: RawFd,
+    dst_offset: usize,
+    len: usize,
+) -> std::io::Result<bool> {
+    let mut src_offset = src_offset as libc::loff_t;
+    let mut dst_offset = dst_offset as libc::loff_t;
+    let mut remaining = len;
+    while remaining > 0 {
+        let res = unsafe {
+            libc::copy_file_range(src, &mut src_offset, dst, &mut dst_offset, remaining, 0)
+        };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::EXDEV | libc::ENOSYS | libc::EINVAL | libc::EOPNOTSUPP) => Ok(false),
+                _ => Err(err),
+            };
+        }
+        if res == 0 {
+            // end of the source file, the rest is zero, but the
+            // destination may not be
+            return Ok(false);
+        }
+        remaining -= res as usize;
+    }
+    Ok(true)
+}
+
+/// Returns the number of minor and major page faults of the process.
+pub(super) fn fault_counts_impl() -> (u64, u64) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } < 0 {
+        return (0, 0);
+    }
+    (usage.ru_minflt as u64, usage.ru_majflt as u64)
+}
+
+/// Make the instruction cache coherent with the data written to the
+/// `len` bytes at `ptr`, like `__builtin___clear_cache`.
+/// The memory must be readable.
+#[cfg(target_arch = "aarch64")]
+unsafe fn clear_cache(ptr: *const u8, len: usize) {
+    use std::arch::asm;
+
+    // the cache line sizes are encoded as log2 of the number of words
+    let ctr: u64;
+    asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags));
+    let dline = 4 << ((ctr >> 16) & 0xf);
+    let iline = 4 << (ctr & 0xf);
+    let (start, end) = (ptr as usize, ptr as usize + len);
+
+    // clean the data cache to the point of unification
+    for addr in (start & !(dline - 1)..end).step_by(dline) {
+        asm!("dc cvau, {}", in(reg) addr, options(nostack, preserves_flags));
+    }
+    asm!("dsb ish", options(nostack, preserves_flags));
+    // then invalidate the stale instructions
+    for addr in (start & !(iline - 1)..end).step_by(iline) {
+        asm!("ic ivau, {}", in(reg) addr, options(nostack, preserves_flags));
+    }
+    asm!("dsb ish", "isb", options(nostack, preserves_flags));
+}
+
+#[cfg(debug_assertions)]
+pub(super) fn backing_key(fd: RawFd, _ptr: *const u8) -> (u64, u64) {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return (u64::MAX, fd as u64);
+    }
+    (stat.st_dev, stat.st_ino)
+}
+
 impl Access {
-    fn as_posix(&self) -> libc::c_int {
+    pub(super) fn as_posix(&self) -> libc::c_int {
         let mut access = 0;
         if *self == Access::NONE {
             access = PROT_NONE;
@@ -130,7 +616,7 @@ impl Access {
                 access |= PROT_WRITE | PROT_READ;
             }
             if self.contains(Access::EXEC) {
-                access |= PROT_EXEC;
+                access |= PROT_EXEC | PROT_READ;
             }
         }
         access
@@ -0,0 +1,502 @@
+use std::ffi::{CStr, CString};
+use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libc::{
+    MAP_ANON, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_NONE, PROT_READ,
+    PROT_WRITE,
+};
+
+pub type OwnedFileDescriptor = OwnedFd;
+pub type RawFileDescriptor = RawFd;
+
+use super::{
+    create_temp_file, effective_size, Access, Advice, Caching, Snapshot, SnapshotError, View,
+    ViewMode,
+};
+
+impl Snapshot {
+    pub(super) fn from_file_impl(file: std::fs::File) -> std::io::Result<Self> {
+        let size = file.metadata()?.len() as usize;
+        let size = size.next_multiple_of(super::page_size());
+        let file = file.into();
+
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn zeroed_impl(size: usize, _name: &CStr) -> std::io::Result<Self> {
+        // There is no memfd_create on macOS, so create a shared memory
+        // object with a unique name and unlink it right away, so that it
+        // is only referenced by its descriptor.
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let size = size.next_multiple_of(super::page_size());
+        let name = format!(
+            "/memsnap.{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let name = CString::new(name).unwrap();
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                0o600 as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+
+        // shared memory objects can only be sized once, and mapping an
+        // object of size zero fails, so always give it at least a page
+        file.set_len(effective_size(size) as u64)?;
+        let file = file.into();
+
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn temp_file_impl(dir: &std::path::Path) -> std::io::Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).mode(0o600);
+        let (file, path) = create_temp_file(dir, &options)?;
+        std::fs::remove_file(path)?;
+        Ok(file)
+    }
+
+    pub(super) fn resize_impl(&mut self, _new_size: usize) -> std::io::Result<bool> {
+        // shared memory objects can only be sized once
+        Ok(false)
+    }
+
+    pub(super) fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    pub(super) fn copy_to_file_impl(&self, _file: &std::fs::File) -> std::io::Result<bool> {
+        // there is no copy_file_range on macOS
+        Ok(false)
+    }
+
+    pub(super) fn clone_cow_impl(&self) -> std::io::Result<Option<Self>> {
+        // clonefile works on paths, not on descriptors
+        Ok(None)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn map_impl(fd: RawFd, size: usize, mode: ViewMode) -> std::io::Result<*mut u8> {
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                effective_size(size),
+                PROT_READ | PROT_WRITE,
+                mode.as_posix(),
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(ptr as *mut u8)
+    }
+
+    pub(super) fn map_populated_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // There is no MAP_POPULATE, ask for the pages to be read ahead
+        // instead. This is only a hint, so failing to do it is not an error.
+        let ptr = Self::map_impl(fd, size, mode)?;
+        unsafe { libc::madvise(ptr as _, effective_size(size), libc::MADV_WILLNEED) };
+        Ok(ptr)
+    }
+
+    pub(super) fn map_guarded_impl(
+        fd: RawFd,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // Reserve the address range of the view and its guard pages, and
+        // map the view over the middle of it.
+        let guard = super::page_size();
+        let len = effective_size(size);
+        let base = unsafe {
+            libc::mmap(
+                null_mut(),
+                len + 2 * guard,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        if base == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                base.byte_add(guard),
+                len,
+                PROT_READ | PROT_WRITE,
+                mode.as_posix() | MAP_FIXED,
+                fd,
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::munmap(base, len + 2 * guard) };
+            return Err(err);
+        }
+
+        Ok(ptr as *mut u8)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn restore_impl(&mut self) -> std::io::Result<()> {
+        let new_ptr = unsafe {
+            libc::mmap(
+                self.ptr as _,
+                effective_size(self.size),
+                PROT_READ | PROT_WRITE,
+                self.mode.as_posix() | MAP_FIXED,
+                self.fd,
+                0,
+            )
+        };
+        if new_ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn restore_range_impl(&mut self, offset: Range<usize>) -> std::io::Result<()> {
+        let new_ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                self.mode.as_posix() | MAP_FIXED,
+                self.fd,
+                (self.offset + offset.start) as _,
+            )
+        };
+        if new_ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn protect_impl(
+        &mut self,
+        offset: Range<usize>,
+        allow: Access,
+        _caching: Caching,
+    ) -> std::io::Result<()> {
+        let res = unsafe {
+            libc::mprotect(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                allow.as_posix(),
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn dirty_pages_impl(&self) -> std::io::Result<Vec<Range<usize>>> {
+        // there is no cheap way to tell the pages that were written
+        Ok(std::iter::once(0..self.size).collect())
+    }
+
+    pub(super) fn residency_impl(&self, offset: Range<usize>) -> std::io::Result<Vec<bool>> {
+        let mut pages = vec![0 as libc::c_char; offset.len() / super::page_size()];
+        let res = unsafe {
+            libc::mincore(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                pages.as_mut_ptr(),
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(pages
+            .into_iter()
+            .map(|page| page as libc::c_int & libc::MINCORE_INCORE != 0)
+            .collect())
+    }
+
+    pub(super) fn query_protection_impl(&self, offset: usize) -> std::io::Result<Access> {
+        let addr = self.ptr as u64 + offset as u64;
+        let mut start = addr;
+        let mut size = 0;
+        let mut info = VmRegionBasicInfo64::default();
+        let mut count = VM_REGION_BASIC_INFO_COUNT_64;
+        let mut object_name = 0;
+        #[allow(deprecated)]
+        let task = unsafe { libc::mach_task_self() };
+        let res = unsafe {
+            mach_vm_region(
+                task,
+                &mut start,
+                &mut size,
+                VM_REGION_BASIC_INFO_64,
+                &mut info as *mut _ as _,
+                &mut count,
+                &mut object_name,
+            )
+        };
+        if res != 0 || start > addr {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "View is not mapped",
+            ));
+        }
+
+        let protection = info.protection;
+        let mut access = Access::NONE;
+        if protection & PROT_READ != 0 {
+            access |= Access::READ;
+        }
+        if protection & PROT_WRITE != 0 {
+            access |= Access::WRITE;
+        }
+        if protection & PROT_EXEC != 0 {
+            access |= Access::EXEC;
+        }
+        Ok(access)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn overlay_impl(
+        &mut self,
+        offset: Range<usize>,
+        snapshot: &Snapshot,
+        src_offset: usize,
+    ) -> std::io::Result<()> {
+        let ptr = unsafe {
+            libc::mmap(
+                self.ptr.add(offset.start) as _,
+                offset.len(),
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_FIXED,
+                snapshot.as_raw_fd(),
+                src_offset as _,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn unmap_impl(&mut self) {
+        let guard = if self.guarded { super::page_size() } else { 0 };
+        unsafe {
+            libc::munmap(
+                self.ptr.sub(guard) as _,
+                effective_size(self.size) + 2 * guard,
+            );
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn zero_pages_impl(&mut self, offset: Range<usize>) -> std::io::Result<bool> {
+        match self.mode {
+            // shared memory objects don't support punching holes
+            ViewMode::Mutable => Ok(false),
+            ViewMode::Cow => {
+                let ptr = unsafe {
+                    libc::mmap(
+                        self.ptr.add(offset.start) as _,
+                        offset.len(),
+                        PROT_READ | PROT_WRITE,
+                        MAP_PRIVATE | MAP_ANON | MAP_FIXED,
+                        -1,
+                        0,
+                    )
+                };
+                if ptr == MAP_FAILED {
+                    return Err(std::io::Error::last_os_error());
+                }
+                self.overlaid = true;
+                self.reapply_protection(offset)?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn advise_impl(
+        &mut self,
+        offset: Range<usize>,
+        advice: Advice,
+    ) -> std::io::Result<bool> {
+        if advice == Advice::DontNeed && self.mode == ViewMode::Cow {
+            // MADV_DONTNEED doesn't discard the changes to private
+            // mappings on macOS
+            return Ok(false);
+        }
+        let advice = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::HugePage | Advice::NoHugePage => {
+                return Err(std::io::Error::from(SnapshotError::Unsupported(
+                    "Transparent huge pages are not supported on macOS",
+                )))
+            }
+        };
+        let res = unsafe { libc::madvise(self.ptr.add(offset.start) as _, offset.len(), advice) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn lock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::mlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOMEM) {
+                return Err(std::io::Error::new(
+                    err.kind(),
+                    "Locking the memory region would exceed RLIMIT_MEMLOCK",
+                ));
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(super) fn unlock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res = unsafe { libc::munlock(self.ptr.add(offset.start) as _, offset.len()) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn populate_write_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn flush_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        let res =
+            unsafe { libc::msync(self.ptr.add(offset.start) as _, offset.len(), libc::MS_SYNC) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn copy_range_impl(
+    _src: RawFd,
+    _src_offset: usize,
+    _dst: RawFd,
+    _dst_offset: usize,
+    _len: usize,
+) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Returns the number of minor and major page faults of the process.
+pub(super) fn fault_counts_impl() -> (u64, u64) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } < 0 {
+        return (0, 0);
+    }
+    (usage.ru_minflt as u64, usage.ru_majflt as u64)
+}
+
+#[cfg(debug_assertions)]
+pub(super) fn backing_key(fd: RawFd, _ptr: *const u8) -> (u64, u64) {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return (u64::MAX, fd as u64);
+    }
+    (stat.st_dev as u64, stat.st_ino)
+}
+
+impl Access {
+    fn as_posix(&self) -> libc::c_int {
+        let mut access = 0;
+        if *self == Access::NONE {
+            access = PROT_NONE;
+        } else {
+            if self.contains(Access::READ) {
+                access |= PROT_READ;
+            }
+            if self.contains(Access::WRITE) {
+                access |= PROT_WRITE | PROT_READ;
+            }
+            if self.contains(Access::EXEC) {
+                access |= PROT_EXEC | PROT_READ;
+            }
+        }
+        access
+    }
+}
+
+impl ViewMode {
+    fn as_posix(&self) -> libc::c_int {
+        match self {
+            ViewMode::Cow => MAP_PRIVATE,
+            ViewMode::Mutable => MAP_SHARED,
+        }
+    }
+}
+
+// The mach API to query the protection of a memory region is not exposed
+// by the libc crate.
+
+const VM_REGION_BASIC_INFO_64: libc::c_int = 9;
+const VM_REGION_BASIC_INFO_COUNT_64: u32 =
+    (size_of::<VmRegionBasicInfo64>() / size_of::<libc::c_int>()) as u32;
+
+#[repr(C, packed(4))]
+#[derive(Default)]
+struct VmRegionBasicInfo64 {
+    protection: libc::c_int,
+    max_protection: libc::c_int,
+    inheritance: libc::c_uint,
+    shared: libc::c_uint,
+    reserved: libc::c_uint,
+    offset: u64,
+    behavior: libc::c_int,
+    user_wired_count: libc::c_ushort,
+}
+
+extern "C" {
+    fn mach_vm_region(
+        target_task: libc::mach_port_t,
+        address: *mut u64,
+        size: *mut u64,
+        flavor: libc::c_int,
+        info: *mut libc::c_int,
+        info_count: *mut u32,
+        object_name: *mut libc::mach_port_t,
+    ) -> libc::c_int;
+}
@@ -0,0 +1,262 @@
+//! A backend for running under Miri, which doesn't support `mmap`,
+//! `mprotect`, or `memfd_create`.
+//!
+//! The backing of a snapshot is a plain heap allocation. Mutable views
+//! point directly into it, while copy-on-write views get their own copy
+//! of it. Memory protection only exists as bookkeeping in the view, and
+//! is never enforced.
+//! Snapshots created from a file hold a copy of the content of the file,
+//! so writes to mutable views are not reflected in the file.
+
+use std::cell::UnsafeCell;
+use std::ffi::CStr;
+use std::io::{Read as _, Seek as _, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::{
+    create_temp_file, effective_size, Access, Advice, Caching, Snapshot, SnapshotError, View,
+    ViewMode,
+};
+
+pub type OwnedFileDescriptor = Arc<Backing>;
+pub type RawFileDescriptor = *const Backing;
+
+/// The heap allocated backing of a snapshot.
+pub struct Backing {
+    data: UnsafeCell<Box<[u8]>>,
+}
+
+// SAFETY: the backing is only accessed through views, which follow the
+// usual borrowing rules of the snapshot that owns the backing.
+unsafe impl Send for Backing {}
+unsafe impl Sync for Backing {}
+
+impl std::fmt::Debug for Backing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Backing").finish_non_exhaustive()
+    }
+}
+
+impl Backing {
+    fn new(data: Box<[u8]>) -> OwnedFileDescriptor {
+        Arc::new(Backing {
+            data: UnsafeCell::new(data),
+        })
+    }
+
+    /// # Safety
+    /// `fd` must point to a live backing.
+    unsafe fn ptr(fd: RawFileDescriptor) -> *mut u8 {
+        // don't go through a reference, which would invalidate the pointers
+        // of the other views of the backing
+        unsafe { (&raw mut **(*fd).data.get()).cast() }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn from_file_impl(mut file: std::fs::File) -> std::io::Result<Self> {
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+        let size = data.len().next_multiple_of(super::page_size());
+        data.resize(effective_size(size), 0);
+
+        let file = Backing::new(data.into_boxed_slice());
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn zeroed_impl(size: usize, _name: &CStr) -> std::io::Result<Self> {
+        let size = size.next_multiple_of(super::page_size());
+        let file = Backing::new(vec![0; effective_size(size)].into_boxed_slice());
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn temp_file_impl(dir: &std::path::Path) -> std::io::Result<std::fs::File> {
+        // the snapshot holds a copy of the content of the file, so the file
+        // can be removed right away on every platform
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true);
+        let (file, path) = create_temp_file(dir, &options)?;
+        std::fs::remove_file(path)?;
+        Ok(file)
+    }
+
+    pub(super) fn resize_impl(&mut self, _new_size: usize) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn as_raw_fd(&self) -> RawFileDescriptor {
+        Arc::as_ptr(&*self.file)
+    }
+
+    pub(super) fn copy_to_file_impl(&self, _file: &std::fs::File) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn clone_cow_impl(&self) -> std::io::Result<Option<Self>> {
+        Ok(None)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn map_impl(
+        fd: RawFileDescriptor,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        let backing = unsafe { Backing::ptr(fd) };
+        match mode {
+            ViewMode::Mutable => Ok(backing),
+            ViewMode::Cow => {
+                let len = effective_size(size);
+                let copy = unsafe { std::slice::from_raw_parts(backing, len) };
+                Ok(Box::into_raw(Box::<[u8]>::from(copy)) as *mut u8)
+            }
+        }
+    }
+
+    pub(super) fn map_populated_impl(
+        fd: RawFileDescriptor,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // the memory of a view is always resident under Miri
+        Self::map_impl(fd, size, mode)
+    }
+
+    pub(super) fn map_guarded_impl(
+        fd: RawFileDescriptor,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // out of bounds accesses are already undefined behavior under Miri
+        Self::map_impl(fd, size, mode)
+    }
+
+    pub(super) fn unmap_impl(&mut self) {
+        if self.mode == ViewMode::Cow {
+            let len = effective_size(self.size);
+            let copy = std::ptr::slice_from_raw_parts_mut(self.ptr, len);
+            drop(unsafe { Box::from_raw(copy) });
+        }
+    }
+
+    pub(super) fn restore_impl(&mut self) -> std::io::Result<()> {
+        let backing = unsafe { Backing::ptr(self.fd) };
+        unsafe { std::ptr::copy_nonoverlapping(backing, self.ptr, effective_size(self.size)) };
+        Ok(())
+    }
+
+    pub(super) fn restore_range_impl(&mut self, offset: Range<usize>) -> std::io::Result<()> {
+        let backing = unsafe { Backing::ptr(self.fd) };
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                backing.add(self.offset + offset.start),
+                self.ptr.add(offset.start),
+                offset.len(),
+            )
+        };
+        Ok(())
+    }
+
+    pub(super) fn protect_impl(
+        &mut self,
+        _offset: Range<usize>,
+        _allow: Access,
+        _caching: Caching,
+    ) -> std::io::Result<()> {
+        // memory protection is only tracked by the view
+        Ok(())
+    }
+
+    pub(super) fn dirty_pages_impl(&self) -> std::io::Result<Vec<Range<usize>>> {
+        // there is no cheap way to tell the pages that were written
+        Ok(std::iter::once(0..self.size).collect())
+    }
+
+    pub(super) fn residency_impl(&self, offset: Range<usize>) -> std::io::Result<Vec<bool>> {
+        // the memory of a view is a heap allocation, which is never paged out
+        Ok(vec![true; offset.len() / super::page_size()])
+    }
+
+    pub(super) fn query_protection_impl(&self, offset: usize) -> std::io::Result<Access> {
+        let default = Access::READ | Access::WRITE;
+        Ok(self.protection.get(offset).unwrap_or(default).implied())
+    }
+
+    pub(super) fn overlay_impl(
+        &mut self,
+        offset: Range<usize>,
+        snapshot: &Snapshot,
+        src_offset: usize,
+    ) -> std::io::Result<()> {
+        if self.mode == ViewMode::Mutable {
+            return Err(std::io::Error::from(SnapshotError::Unsupported(
+                "Overlaying pages on a mutable view is not supported under miri",
+            )));
+        }
+        let src = snapshot.view()?;
+        let len = offset.len();
+        self.as_full_mut_slice()[offset]
+            .copy_from_slice(&src.as_full_slice()[src_offset..src_offset + len]);
+        Ok(())
+    }
+
+    pub(super) fn zero_pages_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn advise_impl(
+        &mut self,
+        _offset: Range<usize>,
+        _advice: Advice,
+    ) -> std::io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn lock_impl(&self, _offset: Range<usize>) -> std::io::Result<()> {
+        // the backing is a heap allocation, there is nothing to page out
+        Ok(())
+    }
+
+    pub(super) fn unlock_impl(&self, _offset: Range<usize>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    pub(super) fn populate_write_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        // the memory of a view is always resident under Miri
+        Ok(true)
+    }
+
+    pub(super) fn flush_impl(&self, _offset: Range<usize>) -> std::io::Result<()> {
+        // writes to mutable views go directly to the backing
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+pub(super) fn copy_range_impl(
+    _src: RawFileDescriptor,
+    _src_offset: usize,
+    _dst: RawFileDescriptor,
+    _dst_offset: usize,
+    _len: usize,
+) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Returns the number of minor and major page faults of the process.
+pub(super) fn fault_counts_impl() -> (u64, u64) {
+    // there are no page faults without mmap
+    (0, 0)
+}
+
+#[cfg(debug_assertions)]
+pub(super) fn backing_key(fd: RawFileDescriptor, _ptr: *const u8) -> (u64, u64) {
+    (0, fd as usize as u64)
+}
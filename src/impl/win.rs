@@ -1,26 +1,41 @@
+use std::ffi::CStr;
 use std::fs::File;
+use std::hash::{Hash as _, Hasher as _};
 use std::ops::Range;
+use std::os::windows::fs::OpenOptionsExt as _;
 use std::os::windows::io::{AsRawHandle as _, FromRawHandle as _, OwnedHandle, RawHandle};
 
 use windows::core::PCSTR;
 use windows::Wdk::Foundation::{NtQueryObject, ObjectBasicInformation};
-use windows::Win32::Foundation::{HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::Foundation::{GENERIC_READ, GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE};
 use windows::Win32::Storage::FileSystem::{
-    FILE_GENERIC_EXECUTE, FILE_GENERIC_READ, FILE_GENERIC_WRITE,
+    DELETE, FILE_ATTRIBUTE_TEMPORARY, FILE_FLAG_DELETE_ON_CLOSE, FILE_GENERIC_EXECUTE,
+    FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
 };
+use windows::Win32::System::Diagnostics::Debug::FlushInstructionCache;
 use windows::Win32::System::Memory::{
-    CreateFileMappingA, MapViewOfFile3, UnmapViewOfFile, UnmapViewOfFileEx, VirtualAlloc2,
-    VirtualProtect, MEMORY_MAPPED_VIEW_ADDRESS, MEM_PRESERVE_PLACEHOLDER, MEM_REPLACE_PLACEHOLDER,
-    MEM_RESERVE, MEM_RESERVE_PLACEHOLDER, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
-    PAGE_EXECUTE_WRITECOPY, PAGE_NOACCESS, PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE,
-    PAGE_WRITECOPY,
+    CreateFileMappingA, FlushViewOfFile, MapViewOfFile3, PrefetchVirtualMemory, UnmapViewOfFile,
+    UnmapViewOfFileEx, VirtualAlloc2, VirtualFree, VirtualLock, VirtualProtect, VirtualQuery,
+    VirtualUnlock, MEMORY_BASIC_INFORMATION, MEMORY_MAPPED_VIEW_ADDRESS, MEM_PRESERVE_PLACEHOLDER,
+    MEM_RELEASE, MEM_REPLACE_PLACEHOLDER, MEM_RESERVE, MEM_RESERVE_PLACEHOLDER, PAGE_EXECUTE,
+    PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_NOACCESS, PAGE_NOCACHE,
+    PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOMBINE, PAGE_WRITECOPY,
+    VIRTUAL_ALLOCATION_TYPE, VIRTUAL_FREE_TYPE, WIN32_MEMORY_RANGE_ENTRY,
 };
+use windows::Win32::System::ProcessStatus::{
+    GetMappedFileNameW, GetProcessMemoryInfo, QueryWorkingSetEx, PROCESS_MEMORY_COUNTERS,
+    PSAPI_WORKING_SET_EX_INFORMATION,
+};
+use windows::Win32::System::Threading::GetCurrentProcess;
 use windows::Win32::System::WindowsProgramming::PUBLIC_OBJECT_BASIC_INFORMATION;
 
 pub type OwnedFileDescriptor = OwnedHandle;
 pub type RawFileDescriptor = RawHandle;
 
-use super::{effective_size, Access, Snapshot, View, ViewMode};
+use super::{
+    create_temp_file, effective_size, Access, AccessMap, Advice, Caching, Snapshot, SnapshotError,
+    View, ViewMode,
+};
 
 impl Snapshot {
     pub(super) fn from_file_impl(file: std::fs::File) -> std::io::Result<Self> {
@@ -30,7 +45,7 @@ impl Snapshot {
         // the size into high and low parts
         const _: () = assert!(std::mem::size_of::<usize>() == 8);
 
-        let size = size.next_multiple_of(page_size::get() as _);
+        let size = size.next_multiple_of(super::page_size() as _);
         let (size_low, size_high) = split_size(effective_size(size));
 
         let access = get_file_page_access(&file)?;
@@ -48,15 +63,30 @@ impl Snapshot {
 
         let file = unsafe { OwnedFileDescriptor::from_raw_handle(handle.0) };
 
-        Ok(Self { file, size })
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn temp_file_impl(dir: &std::path::Path) -> std::io::Result<std::fs::File> {
+        // Open files can't be removed on Windows, so have the file deleted
+        // once its last handle, the one of the file mapping, is closed.
+        let mut options = std::fs::OpenOptions::new();
+        options
+            .read(true)
+            .write(true)
+            .access_mode(GENERIC_READ.0 | GENERIC_WRITE.0 | DELETE.0)
+            .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0 | FILE_SHARE_DELETE.0)
+            .custom_flags(FILE_FLAG_DELETE_ON_CLOSE.0)
+            .attributes(FILE_ATTRIBUTE_TEMPORARY.0);
+        let (file, _) = create_temp_file(dir, &options)?;
+        Ok(file)
     }
 
-    pub(super) fn zeroed_impl(size: usize) -> std::io::Result<Self> {
+    pub(super) fn zeroed_impl(size: usize, _name: &CStr) -> std::io::Result<Self> {
         // we need usize to be 8 bytes on Windows so that we can split
         // the size into high and low parts
         const _: () = assert!(std::mem::size_of::<usize>() == 8);
 
-        let size = size.next_multiple_of(page_size::get() as _);
+        let size = size.next_multiple_of(super::page_size() as _);
         let (size_low, size_high) = split_size(effective_size(size));
 
         let handle = unsafe {
@@ -72,26 +102,58 @@ impl Snapshot {
 
         let file = unsafe { OwnedFileDescriptor::from_raw_handle(handle.0) };
 
-        Ok(Self { file, size })
+        Ok(Self::new(file, size))
+    }
+
+    pub(super) fn resize_impl(&mut self, _new_size: usize) -> std::io::Result<bool> {
+        // file mappings can't be resized
+        Ok(false)
     }
 
     pub(super) fn as_raw_fd(&self) -> RawHandle {
         self.file.as_raw_handle()
     }
+
+    pub(super) fn copy_to_file_impl(&self, _file: &std::fs::File) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn clone_cow_impl(&self) -> std::io::Result<Option<Self>> {
+        Ok(None)
+    }
 }
 
 impl<S> View<S> {
-    pub(super) fn new(
-        snapshot: S,
-        fd: RawHandle,
-        size: usize,
-        mode: ViewMode,
-    ) -> std::io::Result<Self> {
+    pub(super) fn map_impl(fd: RawHandle, size: usize, mode: ViewMode) -> std::io::Result<*mut u8> {
+        let len = effective_size(size);
+        if mode == ViewMode::Mutable {
+            // Restoring a mutable view is a no-op, so it never needs to be
+            // remapped at the same address, and can skip the placeholder.
+            let ptr = unsafe {
+                MapViewOfFile3(
+                    HANDLE(fd),
+                    None,
+                    None,
+                    0,
+                    len,
+                    VIRTUAL_ALLOCATION_TYPE(0),
+                    mode.as_winapi().0,
+                    None,
+                )
+            };
+            if ptr.Value.is_null() {
+                return Err(std::io::Error::last_os_error())?;
+            }
+            return Ok(ptr.Value as _);
+        }
+        // Map copy-on-write views into a placeholder, so that `restore_impl`
+        // can swap the mapping for the placeholder and back without ever
+        // releasing the address range.
         let placeholder = unsafe {
             VirtualAlloc2(
                 None,
                 None,
-                effective_size(size),
+                len,
                 MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
                 PAGE_NOACCESS.0,
                 None,
@@ -106,34 +168,96 @@ impl<S> View<S> {
                 None,
                 Some(placeholder as *const _),
                 0,
-                effective_size(size),
+                len,
                 MEM_REPLACE_PLACEHOLDER,
                 mode.as_winapi().0,
                 None,
             )
         };
         if ptr.Value.is_null() {
-            return Err(std::io::Error::last_os_error())?;
+            let err = std::io::Error::last_os_error();
+            let _ = unsafe { VirtualFree(placeholder, 0, MEM_RELEASE) };
+            return Err(err);
+        }
+        Ok(placeholder as _)
+    }
+
+    pub(super) fn map_populated_impl(
+        fd: RawHandle,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        let ptr = Self::map_impl(fd, size, mode)?;
+        let range = WIN32_MEMORY_RANGE_ENTRY {
+            VirtualAddress: ptr as _,
+            NumberOfBytes: effective_size(size),
+        };
+        // Prefetching is best effort, the pages that are not prefetched are
+        // mapped on first access as usual.
+        let _ = unsafe { PrefetchVirtualMemory(GetCurrentProcess(), &[range], 0) };
+        Ok(ptr)
+    }
+
+    pub(super) fn map_guarded_impl(
+        fd: RawHandle,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<*mut u8> {
+        // Reserve a placeholder for the view and its guard pages, split it
+        // in three, and map the view into the middle one. The guard pages
+        // stay as inaccessible placeholders.
+        let guard = super::page_size();
+        let len = effective_size(size);
+        let base = unsafe {
+            VirtualAlloc2(
+                None,
+                None,
+                len + 2 * guard,
+                MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
+                PAGE_NOACCESS.0,
+                None,
+            )
+        };
+        if base.is_null() {
+            return Err(std::io::Error::last_os_error());
         }
-        if ptr.Value != placeholder {
-            return Err(std::io::Error::other(format!(
-                "Snapshot mapping failed: pointer mismatch, received {:?}, expected {:?}",
-                ptr.Value, placeholder
-            )))?;
+        let ptr = unsafe { base.byte_add(guard) };
+        let split = VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_PRESERVE_PLACEHOLDER.0);
+        let res = unsafe { VirtualFree(base, guard, split) }
+            .and_then(|_| unsafe { VirtualFree(ptr, len, split) });
+        if let Err(err) = res {
+            let _ = unsafe { VirtualFree(base, 0, MEM_RELEASE) };
+            return Err(err.into());
+        }
+        let view = unsafe {
+            MapViewOfFile3(
+                HANDLE(fd),
+                None,
+                Some(ptr as *const _),
+                0,
+                len,
+                MEM_REPLACE_PLACEHOLDER,
+                mode.as_winapi().0,
+                None,
+            )
+        };
+        if view.Value.is_null() {
+            let err = std::io::Error::last_os_error();
+            unsafe {
+                let _ = VirtualFree(base, 0, MEM_RELEASE);
+                let _ = VirtualFree(ptr, 0, MEM_RELEASE);
+                let _ = VirtualFree(ptr.byte_add(len), 0, MEM_RELEASE);
+            }
+            return Err(err);
         }
-        let ptr = ptr.Value as _;
-        Ok(Self {
-            fd,
-            ptr,
-            size,
-            mode,
-            _snapshot: snapshot,
-        })
+        Ok(ptr as _)
     }
 }
 
 impl<S> View<S> {
     pub(super) fn restore_impl(&mut self) -> std::io::Result<()> {
+        // Copy-on-write views are always mapped into a placeholder, see
+        // `map_impl`, which is preserved while the view is unmapped.
         unsafe {
             UnmapViewOfFileEx(
                 MEMORY_MAPPED_VIEW_ADDRESS {
@@ -155,16 +279,46 @@ impl<S> View<S> {
             )
         };
         if new_ptr.Value.is_null() {
-            println!("trying to map to {:?}", self.ptr);
-            return Err(std::io::Error::last_os_error())?;
+            let err = std::io::Error::last_os_error();
+            self.invalidate();
+            return Err(err);
         }
-        let new_ptr: *mut u8 = new_ptr.Value as _;
-        if new_ptr != self.ptr {
-            return Err(std::io::Error::other(format!(
-                "Snapshot restore failed: pointer mismatch, received {:?}, expected {:?}",
-                new_ptr, self.ptr
-            )))?;
+        Ok(())
+    }
+
+    /// Release what's left of the mapping of a view that couldn't be
+    /// remapped, and leave the view empty, so that it can't be used to
+    /// access the address range any more.
+    fn invalidate(&mut self) {
+        unsafe {
+            let _ = VirtualFree(self.ptr as _, 0, MEM_RELEASE);
+            if self.guarded {
+                let guard = super::page_size();
+                let _ = VirtualFree(self.ptr.sub(guard) as _, 0, MEM_RELEASE);
+                let _ = VirtualFree(self.ptr.add(effective_size(self.size)) as _, 0, MEM_RELEASE);
+            }
         }
+        self.ptr = std::ptr::NonNull::dangling().as_ptr();
+        self.size = 0;
+        self.len = 0;
+        self.guarded = false;
+        self.logical.clear();
+        self.protection.clear();
+        self.caching.clear();
+    }
+
+    pub(super) fn restore_range_impl(&mut self, offset: Range<usize>) -> std::io::Result<()> {
+        // Splitting a mapped view into several views would break `restore`,
+        // so copy the content of the root snapshot into the view instead,
+        // after making the region writable again.
+        self.protect_impl(
+            offset.clone(),
+            Access::READ | Access::WRITE,
+            Caching::Default,
+        )?;
+        let src = View::new((), self.fd, self.offset + self.size, ViewMode::Cow)?;
+        let src_offset = self.offset + offset.start..self.offset + offset.end;
+        self.as_full_mut_slice()[offset].copy_from_slice(&src.as_full_slice()[src_offset]);
         Ok(())
     }
 
@@ -172,32 +326,270 @@ impl<S> View<S> {
         &mut self,
         offset: Range<usize>,
         allow: Access,
+        caching: Caching,
     ) -> std::io::Result<()> {
         let mut old: PAGE_PROTECTION_FLAGS = PAGE_PROTECTION_FLAGS(0);
 
+        let mut protection = allow.as_winapi(self.mode);
+        if protection != PAGE_NOACCESS {
+            protection |= caching.as_winapi();
+        }
+
         unsafe {
             VirtualProtect(
                 self.ptr.add(offset.start) as _,
                 offset.len(),
-                allow.as_winapi(self.mode),
+                protection,
                 &mut old as *mut _,
             )
         }?;
 
+        if allow.contains(Access::EXEC) {
+            // make sure the code written to the region is executed
+            unsafe {
+                FlushInstructionCache(
+                    GetCurrentProcess(),
+                    Some(self.ptr.add(offset.start) as _),
+                    offset.len(),
+                )
+            }?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn dirty_pages_impl(&self) -> std::io::Result<Vec<Range<usize>>> {
+        // A written page of a copy-on-write view changes its protection
+        // from PAGE_WRITECOPY to PAGE_READWRITE once it's copied.
+        let mut dirty = AccessMap::default();
+        let mut offset = 0;
+        while offset < self.size {
+            let mut info = MEMORY_BASIC_INFORMATION::default();
+            let len = unsafe {
+                VirtualQuery(
+                    Some(self.ptr.add(offset) as _),
+                    &mut info,
+                    std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+                )
+            };
+            if len == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let end =
+                (info.BaseAddress as usize + info.RegionSize - self.ptr as usize).min(self.size);
+            let protection = PAGE_PROTECTION_FLAGS(info.Protect.0 & 0xff);
+            if protection == PAGE_READWRITE || protection == PAGE_EXECUTE_READWRITE {
+                dirty.insert(offset..end, Access::all());
+            }
+            offset = end;
+        }
+
+        // pages with any other protection can't be told apart
+        let default = Access::READ | Access::WRITE;
+        for (region, _) in self.regions().filter(|(_, allow)| *allow != default) {
+            dirty.insert(region, Access::all());
+        }
+
+        Ok(dirty
+            .regions(self.size, Access::NONE)
+            .filter(|(_, access)| *access == Access::all())
+            .map(|(region, _)| region)
+            .collect())
+    }
+
+    pub(super) fn residency_impl(&self, offset: Range<usize>) -> std::io::Result<Vec<bool>> {
+        let page = super::page_size();
+        let mut pages: Vec<_> = offset
+            .step_by(page)
+            .map(|start| PSAPI_WORKING_SET_EX_INFORMATION {
+                VirtualAddress: unsafe { self.ptr.add(start) } as _,
+                ..Default::default()
+            })
+            .collect();
+        unsafe {
+            QueryWorkingSetEx(
+                GetCurrentProcess(),
+                pages.as_mut_ptr() as _,
+                std::mem::size_of_val(pages.as_slice()) as u32,
+            )
+        }?;
+        // the first bit of the attributes tells if the page is valid, i.e.,
+        // in the working set of the process
+        Ok(pages
+            .into_iter()
+            .map(|page| unsafe { page.VirtualAttributes.Flags } & 1 != 0)
+            .collect())
+    }
+
+    pub(super) fn query_protection_impl(&self, offset: usize) -> std::io::Result<Access> {
+        let mut info = MEMORY_BASIC_INFORMATION::default();
+        let len = unsafe {
+            VirtualQuery(
+                Some(self.ptr.add(offset) as _),
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if len == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // ignore the caching and guard modifiers
+        let protection = PAGE_PROTECTION_FLAGS(info.Protect.0 & 0xff);
+        let access = match protection {
+            PAGE_EXECUTE => Access::EXEC,
+            PAGE_EXECUTE_READ => Access::READ | Access::EXEC,
+            PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY => Access::all(),
+            PAGE_READONLY => Access::READ,
+            PAGE_READWRITE | PAGE_WRITECOPY => Access::READ | Access::WRITE,
+            _ => Access::NONE,
+        };
+        Ok(access)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn overlay_impl(
+        &mut self,
+        offset: Range<usize>,
+        snapshot: &Snapshot,
+        src_offset: usize,
+    ) -> std::io::Result<()> {
+        // Splitting a mapped view into several views would break `restore`,
+        // so copy the content of the snapshot into the view instead.
+        // For a mutable view that would write to the root snapshot, which
+        // an overlay must not do.
+        if self.mode == ViewMode::Mutable {
+            return Err(std::io::Error::from(SnapshotError::Unsupported(
+                "Overlaying pages on a mutable view is not supported on Windows",
+            )));
+        }
+        let src = snapshot.view()?;
+        let len = offset.len();
+        self.as_full_mut_slice()[offset]
+            .copy_from_slice(&src.as_full_slice()[src_offset..src_offset + len]);
         Ok(())
     }
 }
 
-impl<S> Drop for View<S> {
-    fn drop(&mut self) {
+impl<S> View<S> {
+    pub(super) fn unmap_impl(&mut self) {
         let _ = unsafe {
             UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
                 Value: self.ptr as _,
             })
         };
+        if self.guarded {
+            let guard = super::page_size();
+            unsafe {
+                let _ = VirtualFree(self.ptr.sub(guard) as _, 0, MEM_RELEASE);
+                let _ = VirtualFree(self.ptr.add(effective_size(self.size)) as _, 0, MEM_RELEASE);
+            }
+        }
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn zero_pages_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        // There is no way to discard the pages of a mapped view and have
+        // them read back as zero, so the caller has to write the zeros.
+        Ok(false)
+    }
+}
+
+impl<S> View<S> {
+    pub(super) fn advise_impl(
+        &mut self,
+        offset: Range<usize>,
+        advice: Advice,
+    ) -> std::io::Result<bool> {
+        let ptr = unsafe { self.ptr.add(offset.start) };
+        match advice {
+            Advice::WillNeed => {
+                let range = WIN32_MEMORY_RANGE_ENTRY {
+                    VirtualAddress: ptr as _,
+                    NumberOfBytes: offset.len(),
+                };
+                unsafe { PrefetchVirtualMemory(GetCurrentProcess(), &[range], 0) }?;
+                Ok(true)
+            }
+            Advice::DontNeed if self.mode == ViewMode::Mutable => {
+                // Unlocking pages that are not locked removes them from the
+                // working set of the process, and reports an error.
+                let _ = unsafe { VirtualUnlock(ptr as _, offset.len()) };
+                Ok(true)
+            }
+            Advice::HugePage | Advice::NoHugePage => Err(std::io::Error::from(
+                SnapshotError::Unsupported("Transparent huge pages are not supported on Windows"),
+            )),
+            // There is no way to discard the changes to a copy-on-write view
+            // without remapping it, and no equivalent to the other hints.
+            _ => Ok(false),
+        }
     }
 }
 
+impl<S> View<S> {
+    pub(super) fn lock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        unsafe { VirtualLock(self.ptr.add(offset.start) as _, offset.len()) }?;
+        Ok(())
+    }
+
+    pub(super) fn unlock_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        unsafe { VirtualUnlock(self.ptr.add(offset.start) as _, offset.len()) }?;
+        Ok(())
+    }
+
+    pub(super) fn populate_write_impl(&mut self, _offset: Range<usize>) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn flush_impl(&self, offset: Range<usize>) -> std::io::Result<()> {
+        unsafe { FlushViewOfFile(self.ptr.add(offset.start) as _, offset.len()) }?;
+        Ok(())
+    }
+}
+
+/// Windows can't copy between file mappings in the kernel, so the caller
+/// has to copy the bytes through a view.
+pub(super) fn copy_range_impl(
+    _src: RawHandle,
+    _src_offset: usize,
+    _dst: RawHandle,
+    _dst_offset: usize,
+    _len: usize,
+) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Returns the number of minor and major page faults of the process.
+pub(super) fn fault_counts_impl() -> (u64, u64) {
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    let cb = std::mem::size_of_val(&counters) as u32;
+    if unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, cb) }.is_err() {
+        return (0, 0);
+    }
+    // soft and hard faults are counted together
+    (counters.PageFaultCount as u64, 0)
+}
+
+#[cfg(debug_assertions)]
+pub(super) fn backing_key(fd: RawHandle, ptr: *const u8) -> (u64, u64) {
+    // A file mapping handle doesn't expose a stable identity for the
+    // underlying section, and every snapshot created from a file has its
+    // own section, so identify the file mapped at `ptr` by its name.
+    // Sections backed by the paging file have no name, so fall back to the
+    // handle itself.
+    let mut name = [0u16; 1024];
+    let len = unsafe { GetMappedFileNameW(GetCurrentProcess(), ptr as _, &mut name) };
+    if len == 0 {
+        return (0, fd as u64);
+    }
+    let mut hasher = std::hash::DefaultHasher::new();
+    name[..len as usize].hash(&mut hasher);
+    (1, hasher.finish())
+}
+
 impl Access {
     fn as_winapi(&self, mode: ViewMode) -> PAGE_PROTECTION_FLAGS {
         if *self == Access::NONE {
@@ -213,8 +605,7 @@ impl Access {
             // with exec
             (_, true, true, true) => PAGE_EXECUTE_READWRITE,
             (_, true, true, false) => PAGE_EXECUTE_WRITECOPY,
-            (true, _, true, _) => PAGE_EXECUTE_READ,
-            (_, _, true, _) => PAGE_EXECUTE,
+            (_, _, true, _) => PAGE_EXECUTE_READ,
             // without exec
             (_, true, false, true) => PAGE_READWRITE,
             (_, true, false, false) => PAGE_WRITECOPY,
@@ -224,6 +615,16 @@ impl Access {
     }
 }
 
+impl Caching {
+    fn as_winapi(&self) -> PAGE_PROTECTION_FLAGS {
+        match self {
+            Caching::Default => PAGE_PROTECTION_FLAGS(0),
+            Caching::Uncached => PAGE_NOCACHE,
+            Caching::WriteCombined => PAGE_WRITECOMBINE,
+        }
+    }
+}
+
 impl ViewMode {
     fn as_winapi(&self) -> PAGE_PROTECTION_FLAGS {
         match self {
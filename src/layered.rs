@@ -0,0 +1,159 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::{Snapshot, SnapshotError, View, ViewMode};
+
+/// A copy-on-write view into the content of a [`LayeredSnapshot`].
+/// See [`View`] for more details.
+pub type LayeredView<'a> = View<&'a LayeredSnapshot>;
+
+/// A snapshot made of a base [`Snapshot`] and a stack of layers on top
+/// of it, where each layer overrides some pages of the layers below.
+///
+/// This models incremental snapshots, where each layer is a delta over
+/// its parent (e.g., `base <- v1 <- v2`), like the pages written to a view
+/// of the layered snapshot, captured with
+/// [`capture_layer`](View::capture_layer).
+/// Reading a page of a [`LayeredView`] returns the content of the topmost
+/// layer that overrides that page, falling through to the base snapshot
+/// for pages that no layer overrides.
+///
+/// All the layers must have the same size as the base snapshot, and
+/// pages are identified by their index, using the system page size.
+#[derive(Debug)]
+pub struct LayeredSnapshot {
+    base: Arc<Snapshot>,
+    layers: Vec<(Arc<Snapshot>, BTreeSet<usize>)>,
+}
+
+impl LayeredSnapshot {
+    /// Create a new layered snapshot with no layers on top of `base`.
+    pub fn new(base: Arc<Snapshot>) -> Self {
+        Self {
+            base,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Push a new layer on top of the existing ones.
+    /// The layer overrides the pages with the given indices, and only
+    /// those pages of `layer` are ever read.
+    /// Returns an `InvalidInput` error if the size of `layer` doesn't match
+    /// the size of the base snapshot, or if any of the pages is out of
+    /// bounds.
+    pub fn push_layer(
+        &mut self,
+        layer: Arc<Snapshot>,
+        pages: impl IntoIterator<Item = usize>,
+    ) -> std::io::Result<()> {
+        if layer.size != self.base.size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Layer size does not match the base snapshot size",
+            ));
+        }
+        let page_count = self.base.size / super::page_size();
+        let pages: BTreeSet<usize> = pages.into_iter().collect();
+        if pages.last().is_some_and(|&page| page >= page_count) {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        self.layers.push((layer, pages));
+        Ok(())
+    }
+
+    /// Returns the number of layers on top of the base snapshot.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Create a copy-on-write view into the composed content of the layers.
+    /// Changes to this view do not affect any of the layers.
+    ///
+    /// On Linux and macOS, the pages of each layer are mapped over the base
+    /// snapshot, so creating the view doesn't copy any content.
+    /// On Windows, the pages of each layer are copied into the view.
+    ///
+    /// Restoring the view, or a region of it, restores the composed content
+    /// of the layers, mapping the pages of each layer over the base
+    /// snapshot again.
+    pub fn view(&self) -> std::io::Result<LayeredView<'_>> {
+        let page = super::page_size();
+        let mut view = View::new(self, self.base.as_raw_fd(), self.base.size, ViewMode::Cow)?;
+        for (layer, pages) in &self.layers {
+            let mut pages = pages.iter().copied().peekable();
+            while let Some(first) = pages.next() {
+                let mut last = first;
+                while pages.next_if_eq(&(last + 1)).is_some() {
+                    last += 1;
+                }
+                view.layers
+                    .push((first * page..(last + 1) * page, layer.clone()));
+            }
+        }
+        view.remap_layers(0..view.size)?;
+        Ok(view)
+    }
+}
+
+impl LayeredView<'_> {
+    /// Copy the pages of this view that differ from the composed content of
+    /// the layers into a new snapshot, and return it together with the
+    /// indices of those pages, so that it can be pushed as a new layer with
+    /// [`push_layer`](LayeredSnapshot::push_layer) once the view is dropped.
+    ///
+    /// This builds a chain of layers that each only hold the pages written
+    /// on top of their parents. The new snapshot has the size of the base
+    /// snapshot, but only the pages that are captured are written to it.
+    /// The view must be readable, since its pages are compared with the
+    /// content of the layers.
+    ///
+    /// On Linux only the pages written to, found with
+    /// `/proc/self/pagemap`, are compared. On other platforms every page of
+    /// the view may be compared.
+    pub fn capture_layer(&self) -> std::io::Result<(Snapshot, Vec<usize>)> {
+        let page = super::page_size();
+        let layers = self._snapshot.view()?;
+        let (current, original) = (self.as_full_slice(), layers.as_full_slice());
+        let mut pages = Vec::new();
+        for run in self.dirty_pages_impl()? {
+            for start in run.step_by(page) {
+                if current[start..start + page] != original[start..start + page] {
+                    pages.push(start / page);
+                }
+            }
+        }
+
+        let mut layer = Snapshot::zeroed(self.size)?;
+        let mut view = layer.view_mut()?;
+        for &idx in &pages {
+            let range = idx * page..(idx + 1) * page;
+            view.as_full_mut_slice()[range.clone()].copy_from_slice(&current[range]);
+        }
+        drop(view);
+        Ok((layer, pages))
+    }
+}
+
+impl<S> View<S> {
+    /// Map the pages of the layers of a layered view that fall in `region`
+    /// of the view over it, in order, after the region was remapped from
+    /// the base snapshot.
+    pub(super) fn remap_layers(&mut self, region: Range<usize>) -> std::io::Result<()> {
+        let layers = std::mem::take(&mut self.layers);
+        let res = layers.iter().try_for_each(|(pages, layer)| {
+            // the pages of the layer, relative to this view, and clamped
+            // to the region
+            let start = pages.start.saturating_sub(self.offset).max(region.start);
+            let end = pages.end.saturating_sub(self.offset).min(region.end);
+            if start >= end {
+                return Ok(());
+            }
+            self.overlay_impl(start..end, layer, self.offset + start)?;
+            self.overlaid = true;
+            Ok(())
+        });
+        self.layers = layers;
+        res
+    }
+}
@@ -0,0 +1,305 @@
+//! Lazily populated views using `userfaultfd`.
+//!
+//! The view is an anonymous private mapping registered with a
+//! `userfaultfd`, so that the first access to each page faults and is
+//! reported to a handler thread, which asks the page provider for the
+//! content of the page and resolves the fault with `UFFDIO_COPY`.
+//! The handler thread is the only one resolving faults, so concurrent
+//! faults on the same page are serialized, and the provider is called
+//! once for each page.
+
+use super::{CowView, Snapshot};
+
+impl Snapshot {
+    /// Create a copy-on-write view of this snapshot whose pages are
+    /// populated on first access by `provider`, instead of with the
+    /// content of the snapshot.
+    ///
+    /// The provider is called with the offset of a page in the view, and
+    /// a buffer of the system page size to fill with the content of the
+    /// page. It's called at most once for each page, from a thread
+    /// managed by the view, which is stopped when the view is dropped.
+    /// If the provider panics, the page is left zeroed.
+    ///
+    /// The snapshot still gives the view its size, and restoring the view,
+    /// or a region of it, maps the content of the snapshot instead, which
+    /// is then no longer populated by the provider. The view reports every
+    /// page as [dirty](crate::View::dirty_pages).
+    ///
+    /// This is only supported on Linux on x86_64 and aarch64, an
+    /// `Unsupported` error is returned on other platforms. Creating a
+    /// `userfaultfd` may also require privileges, depending on the
+    /// `vm.unprivileged_userfaultfd` sysctl.
+    ///
+    /// Since Linux 5.11, the `userfaultfd` only handles faults from user
+    /// mode, which doesn't need privileges. The kernel then can't populate
+    /// pages on behalf of system calls: a system call that accesses a page
+    /// of the view that wasn't populated yet fails with `EFAULT`, e.g.,
+    /// `read(2)` into the view, or writing the view to a socket with
+    /// [`Write::write_all`](std::io::Write::write_all). Access those pages
+    /// from user code first, e.g., by reading a byte of each page.
+    pub fn view_lazy(
+        &self,
+        provider: impl FnMut(usize, &mut [u8]) + Send + 'static,
+    ) -> std::io::Result<CowView<'_>> {
+        #[cfg(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(miri)
+        ))]
+        {
+            let mut view = self.view()?;
+            let handler = uffd::Handler::new(&mut view, provider)?;
+            view.overlaid = true;
+            Ok(view.with_cleanup(move || handler.stop()))
+        }
+        #[cfg(not(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(miri)
+        )))]
+        {
+            let _ = provider;
+            Err(std::io::Error::from(super::SnapshotError::Unsupported(
+                "Lazy views are not supported on this platform",
+            )))
+        }
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(miri)
+))]
+mod uffd {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::panic::AssertUnwindSafe;
+    use std::thread::JoinHandle;
+
+    use super::super::{effective_size, CowView};
+
+    /// The thread handling the page faults of a lazy view.
+    pub(super) struct Handler {
+        thread: JoinHandle<()>,
+        stop: OwnedFd,
+    }
+
+    impl Handler {
+        /// Replace the mapping of `view` with an anonymous mapping, register
+        /// it with a new `userfaultfd`, and start the thread handling its
+        /// page faults.
+        pub(super) fn new(
+            view: &mut CowView,
+            mut provider: impl FnMut(usize, &mut [u8]) + Send + 'static,
+        ) -> std::io::Result<Self> {
+            let base = view.ptr as usize;
+            let len = effective_size(view.size);
+
+            let ptr = unsafe {
+                libc::mmap(
+                    view.ptr as _,
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            let uffd = open()?;
+            let mut api = UffdioApi {
+                api: UFFD_API,
+                features: 0,
+                ioctls: 0,
+            };
+            ioctl(&uffd, UFFDIO_API, &mut api)?;
+            let mut register = UffdioRegister {
+                range: UffdioRange {
+                    start: base as u64,
+                    len: len as u64,
+                },
+                mode: UFFDIO_REGISTER_MODE_MISSING,
+                ioctls: 0,
+            };
+            ioctl(&uffd, UFFDIO_REGISTER, &mut register)?;
+
+            let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+            if stop < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let stop = unsafe { OwnedFd::from_raw_fd(stop) };
+            let stop_fd = stop.as_raw_fd();
+
+            let thread = std::thread::Builder::new()
+                .name("memsnap-uffd".into())
+                .spawn(move || {
+                    let page = crate::page_size();
+                    let mut buf = vec![0u8; page];
+                    while let Some(addr) = next_fault(&uffd, stop_fd) {
+                        let addr = addr / page as u64 * page as u64;
+                        let offset = addr as usize - base;
+                        buf.fill(0);
+                        let provided = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            provider(offset, &mut buf)
+                        }));
+                        if provided.is_err() {
+                            buf.fill(0);
+                        }
+                        resolve(&uffd, addr, &buf);
+                    }
+                })?;
+
+            Ok(Self { thread, stop })
+        }
+
+        /// Stop the thread and wait for it to finish.
+        pub(super) fn stop(self) {
+            let one = 1u64;
+            unsafe { libc::write(self.stop.as_raw_fd(), &one as *const u64 as _, 8) };
+            let _ = self.thread.join();
+        }
+    }
+
+    /// Wait for the next page fault, and return its address, or `None` if
+    /// the handler was stopped.
+    fn next_fault(uffd: &OwnedFd, stop: libc::c_int) -> Option<u64> {
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: uffd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: stop,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            let res = unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) };
+            if res < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return None;
+            }
+            if fds[1].revents != 0 {
+                return None;
+            }
+            if fds[0].revents & libc::POLLIN == 0 {
+                continue;
+            }
+
+            let mut msg = [0u8; UFFD_MSG_SIZE];
+            let res = unsafe { libc::read(uffd.as_raw_fd(), msg.as_mut_ptr() as _, msg.len()) };
+            if res != msg.len() as isize {
+                // the fault was already resolved, or the read was interrupted
+                continue;
+            }
+            if msg[0] == UFFD_EVENT_PAGEFAULT {
+                return Some(u64::from_ne_bytes(msg[16..24].try_into().unwrap()));
+            }
+        }
+    }
+
+    /// Populate the page at `addr` with `buf`, and wake up the threads
+    /// waiting on it.
+    fn resolve(uffd: &OwnedFd, addr: u64, buf: &[u8]) {
+        let mut copy = UffdioCopy {
+            dst: addr,
+            src: buf.as_ptr() as u64,
+            len: buf.len() as u64,
+            mode: 0,
+            copy: 0,
+        };
+        if let Err(err) = ioctl(uffd, UFFDIO_COPY, &mut copy) {
+            if err.raw_os_error() == Some(libc::EEXIST) {
+                // the page was populated by an earlier fault, but the
+                // faulting threads may still be waiting on it
+                let mut range = UffdioRange {
+                    start: addr,
+                    len: buf.len() as u64,
+                };
+                let _ = ioctl(uffd, UFFDIO_WAKE, &mut range);
+            }
+            // other errors mean the page is no longer registered, e.g.,
+            // because the view was restored, and the fault was resolved
+        }
+    }
+
+    fn open() -> std::io::Result<OwnedFd> {
+        // Handling only faults from user mode doesn't need privileges since
+        // Linux 5.11, but older kernels reject the flag.
+        let flags = libc::O_CLOEXEC | libc::O_NONBLOCK;
+        let mut fd = unsafe { libc::syscall(libc::SYS_userfaultfd, flags | UFFD_USER_MODE_ONLY) };
+        if fd < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+            fd = unsafe { libc::syscall(libc::SYS_userfaultfd, flags) };
+        }
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as _) })
+    }
+
+    fn ioctl<T>(uffd: &OwnedFd, request: libc::c_ulong, arg: &mut T) -> std::io::Result<()> {
+        let res = unsafe { libc::ioctl(uffd.as_raw_fd(), request as _, arg as *mut T) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // The userfaultfd API is not exposed by the libc crate.
+
+    const UFFD_API: u64 = 0xaa;
+    const UFFD_USER_MODE_ONLY: libc::c_int = 1;
+    const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+    const UFFD_MSG_SIZE: usize = 32;
+    const UFFDIO_REGISTER_MODE_MISSING: u64 = 1;
+
+    const UFFDIO_API: libc::c_ulong = iowr(0x3f, size_of::<UffdioApi>());
+    const UFFDIO_REGISTER: libc::c_ulong = iowr(0x00, size_of::<UffdioRegister>());
+    const UFFDIO_WAKE: libc::c_ulong = ior(0x02, size_of::<UffdioRange>());
+    const UFFDIO_COPY: libc::c_ulong = iowr(0x03, size_of::<UffdioCopy>());
+
+    const fn ior(nr: libc::c_ulong, size: usize) -> libc::c_ulong {
+        (2 << 30) | ((size as libc::c_ulong) << 16) | (0xaa << 8) | nr
+    }
+
+    const fn iowr(nr: libc::c_ulong, size: usize) -> libc::c_ulong {
+        (3 << 30) | ((size as libc::c_ulong) << 16) | (0xaa << 8) | nr
+    }
+
+    #[repr(C)]
+    struct UffdioApi {
+        api: u64,
+        features: u64,
+        ioctls: u64,
+    }
+
+    #[repr(C)]
+    struct UffdioRange {
+        start: u64,
+        len: u64,
+    }
+
+    #[repr(C)]
+    struct UffdioRegister {
+        range: UffdioRange,
+        mode: u64,
+        ioctls: u64,
+    }
+
+    #[repr(C)]
+    struct UffdioCopy {
+        dst: u64,
+        src: u64,
+        len: u64,
+        mode: u64,
+        copy: i64,
+    }
+}
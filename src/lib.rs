@@ -1,18 +1,87 @@
-use std::ops::{Bound, Index, IndexMut, RangeBounds};
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref, DerefMut, Index, IndexMut, Range, RangeBounds};
+use std::path::Path;
 use std::slice::SliceIndex;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use bitflags::bitflags;
 
-#[cfg_attr(target_os = "linux", path = "impl/linux.rs")]
-#[cfg_attr(target_os = "windows", path = "impl/win.rs")]
+mod access_map;
+mod advice;
+#[cfg(debug_assertions)]
+mod alias;
+mod baseline;
+#[cfg(feature = "async")]
+mod blocking;
+mod checkpoint;
+mod cursor;
+mod debug;
+#[cfg(feature = "digest")]
+mod digest;
+mod error;
+mod exec;
+mod guard;
+mod layered;
+mod lazy;
+mod lock;
+#[cfg(unix)]
+mod named;
+mod numa;
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(miri)))]
+mod pkey;
+mod pod;
+mod pool;
+mod readonly;
+mod region;
+mod session;
+#[cfg(not(miri))]
+mod shared;
+mod sparse;
+mod stats;
+mod subview;
+mod zeroize;
+
+#[cfg_attr(all(target_os = "linux", not(miri)), path = "impl/linux.rs")]
+#[cfg_attr(all(target_os = "windows", not(miri)), path = "impl/win.rs")]
+#[cfg_attr(all(target_os = "macos", not(miri)), path = "impl/mac.rs")]
+#[cfg_attr(all(target_os = "freebsd", not(miri)), path = "impl/freebsd.rs")]
+#[cfg_attr(miri, path = "impl/miri.rs")]
 mod r#impl;
 
+use access_map::AccessMap;
+pub use advice::Advice;
+pub use checkpoint::CheckpointId;
+pub use cursor::{ViewCursor, ViewCursorMut};
+#[cfg(feature = "digest")]
+pub use digest::DigestAlgo;
+pub use error::SnapshotError;
+pub use exec::ExecView;
+pub use guard::ProtectionGuard;
+pub use layered::{LayeredSnapshot, LayeredView};
+pub use lock::LockGuard;
+#[cfg(unix)]
+pub use named::NamedSnapshot;
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(miri)))]
+pub use pkey::ThreadLocalProtection;
+pub use pod::Pod;
+pub use pool::{PooledSnapshot, SnapshotPool};
 use r#impl::{OwnedFileDescriptor, RawFileDescriptor};
+pub use readonly::{ReadOnlyView, SharedView};
+pub use region::RegionRef;
+pub use session::{SessionView, SnapshotSession};
+pub use stats::ViewStats;
+pub use subview::{Borrowed, SubView};
+pub use zeroize::ZeroizeOnDrop;
 
 /// A copy-on-write view into the content of a [`Snapshot`],
 /// similar to [`CowView`] but with `'static` lifetime.
 /// See [`View`] for more details.
+///
+/// An `ArcView` is `Send`, `'static` and implements `AsRef<[u8]>`, so it
+/// can be the owner of a `bytes::Bytes` without copying its content,
+/// with `Bytes::from_owner(view)`. The view is unmapped when the last
+/// `Bytes` sliced from it is dropped.
 pub type ArcView = View<Arc<Snapshot>>;
 
 /// A copy-on-write view into the content of a [`Snapshot`].
@@ -35,10 +104,26 @@ pub type MutView<'a> = View<&'a mut Snapshot>;
 /// * [`view_arc`](Snapshot::view_arc): A copy-on-write view
 ///   with `'static` lifetime where changes do not affect the
 ///   root snapshot.
-#[derive(Debug)]
 pub struct Snapshot {
-    file: OwnedFileDescriptor,
+    /// The backing of the snapshot, shared with the snapshots taken from
+    /// unmodified views, see [`View::take_snapshot`].
+    file: Arc<OwnedFileDescriptor>,
     size: usize,
+    /// The logical length of the snapshot, at most `size`, see
+    /// [`Snapshot::set_logical_len`].
+    len: usize,
+    /// The temporary file backing the snapshot, deleted when the
+    /// snapshot is dropped, after `file` is closed.
+    #[cfg(feature = "tempfile")]
+    temp_path: Option<tempfile::TempPath>,
+    /// The checkpoints of the snapshot, see [`Snapshot::checkpoint`].
+    checkpoints: Option<Box<checkpoint::Checkpoints>>,
+    /// The metadata attached to the snapshot, see
+    /// [`Snapshot::set_metadata`].
+    metadata: Option<Arc<[u8]>>,
+    /// Whether the backing was created by a [`SnapshotPool`], which can
+    /// then recycle it.
+    pooled: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,6 +132,18 @@ enum ViewMode {
     Mutable,
 }
 
+/// How the memory of a view is mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mapping {
+    /// The pages are mapped lazily, on first access.
+    Lazy,
+    /// Surrounded by guard pages, see [`Snapshot::view_guarded`].
+    Guarded,
+    /// The pages are made resident up front, see
+    /// [`Snapshot::view_populated`].
+    Populated,
+}
+
 /// A view into the content of a [`Snapshot`] that can be used to
 /// read or write into it.
 ///
@@ -65,37 +162,276 @@ enum ViewMode {
 /// A third type of view [`ArcView`] is similar to [`CowView`]
 /// but must be created from a reference-counted [`Arc<Snapshot>`].
 /// Unlike [`CowView`], it has no lifetime requirements.
-#[derive(Debug)]
 pub struct View<S> {
     fd: RawFileDescriptor,
     ptr: *mut u8,
     size: usize,
+    /// The logical length of the view, at most `size`, see
+    /// [`Snapshot::set_logical_len`].
+    len: usize,
+    /// The offset of the view in its root snapshot, non-zero for sub-views.
+    offset: usize,
+    /// Whether the view is a sub-view that shares the mapping of another
+    /// view, and so must not unmap it.
+    borrowed: bool,
     mode: ViewMode,
+    logical: AccessMap,
+    protection: AccessMap,
+    /// The caching attributes set with
+    /// [`protect_with_caching`](View::protect_with_caching), for the
+    /// regions that don't have the default caching.
+    caching: AccessMap<Caching>,
+    /// Whether pages of other snapshots, or anonymous zero pages, were
+    /// mapped over the view. Those can't be told apart from clean pages.
+    overlaid: bool,
+    /// The pages of the layers of a [`LayeredSnapshot`] mapped over the
+    /// view, as regions of the root snapshot, which restoring the view maps
+    /// again.
+    layers: Vec<(Range<usize>, Arc<Snapshot>)>,
+    /// Whether the view is surrounded by inaccessible guard pages, see
+    /// [`Snapshot::view_guarded`].
+    #[cfg_attr(miri, allow(dead_code))]
+    guarded: bool,
+    #[cfg(feature = "protection-history")]
+    history: std::collections::VecDeque<ProtectionEvent>,
+    /// The registration of a mutable view in the count of the mutable
+    /// views of its backing, used to detect aliasing.
+    #[cfg(debug_assertions)]
+    alias: Option<alias::MutableView>,
+    cleanup: Cleanup,
+    /// The backing of the root snapshot of a copy-on-write view, to share
+    /// it with the snapshots taken from the view while it's unmodified.
+    root: Option<Arc<OwnedFileDescriptor>>,
+    /// The offset of the next write through the `Write` implementation.
+    position: usize,
+    /// Whether the view is a mutable view mapped as copy-on-write, whose
+    /// changes are written back to the snapshot when it's dropped, see
+    /// [`Snapshot::checkpoint`].
+    write_back: bool,
+    journal: Option<Box<checkpoint::Journal>>,
+    /// The minor and major page faults of the process when the view was
+    /// created, see [`View::stats`].
+    faults: (u64, u64),
+    /// The content a mutable view can be reverted to, see
+    /// [`View::checkpoint_baseline`].
+    baseline: Option<Box<Snapshot>>,
+    /// The metadata of the root snapshot, given to the snapshots taken
+    /// from the view.
+    metadata: Option<Arc<[u8]>>,
     _snapshot: S,
 }
 
-unsafe impl<S> Send for View<S> {}
-unsafe impl<S> Sync for View<S> {}
+/// Callbacks to run after a view is unmapped, see [`View::with_cleanup`].
+#[derive(Default)]
+struct Cleanup(Vec<Box<dyn FnOnce() + Send>>);
+
+impl std::fmt::Debug for Cleanup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Cleanup").field(&self.0.len()).finish()
+    }
+}
+
+// SAFETY: the mapping of a view is only accessed through the view itself,
+// following the usual borrowing rules, so a view is `Send` and `Sync` as
+// long as the snapshot handle it holds is.
+// The cleanup callbacks are `Send`, and they are only accessed when the view
+// is dropped, so they never need to be `Sync`.
+unsafe impl<S: Send> Send for View<S> {}
+unsafe impl<S: Sync> Sync for View<S> {}
 
 impl Snapshot {
+    fn new(file: OwnedFileDescriptor, size: usize) -> Self {
+        Self::shared(Arc::new(file), size)
+    }
+
+    fn shared(file: Arc<OwnedFileDescriptor>, size: usize) -> Self {
+        Self {
+            file,
+            size,
+            len: size,
+            #[cfg(feature = "tempfile")]
+            temp_path: None,
+            checkpoints: None,
+            metadata: None,
+            pooled: false,
+        }
+    }
+
     /// Create a new snapshot from a file.
     /// The snapshot is populated with the content of the file.
+    ///
+    /// On Linux, if the file was opened with `O_DIRECT`, its length must be
+    /// a multiple of its block size, and the snapshot size is rounded up to
+    /// a multiple of both the block size and the system page size.
+    /// An `InvalidInput` error is returned otherwise.
     pub fn from_file(file: std::fs::File) -> std::io::Result<Self> {
         Self::from_file_impl(file)
     }
 
+    /// Create a new snapshot from a temporary file, taking ownership of it.
+    /// The snapshot is populated with the content of the file, as with
+    /// [`Snapshot::from_file`].
+    ///
+    /// The file is deleted from disk when the snapshot is dropped.
+    /// Since every view borrows or holds a reference to its snapshot, this
+    /// only happens once all the views of the snapshot have been dropped,
+    /// so the file is never deleted while it's still mapped.
+    #[cfg(feature = "tempfile")]
+    pub fn from_temp_file(file: tempfile::NamedTempFile) -> std::io::Result<Self> {
+        let (file, path) = file.into_parts();
+        let mut this = Self::from_file(file)?;
+        this.temp_path = Some(path);
+        Ok(this)
+    }
+
     /// Create a new snapshot with zeroed content of the given size.
     /// The actual snapshot size will be rounded up to the next system page size.
     pub fn zeroed(size: usize) -> std::io::Result<Self> {
-        Self::zeroed_impl(size)
+        Self::zeroed_impl(size, c"hyperlight_snapshot")
+    }
+
+    /// Create a new snapshot with zeroed content of the given size, like
+    /// [`zeroed`](Snapshot::zeroed), with a name to tell it apart when
+    /// debugging.
+    ///
+    /// On Linux the name is given to the backing memfd, and shows up in
+    /// `/proc/<pid>/fd` and `/proc/<pid>/maps` as `/memfd:<name>`. Names
+    /// longer than the limit of the kernel, 249 bytes, are truncated.
+    /// On other platforms the name is ignored.
+    ///
+    /// Returns an `InvalidInput` error if the name contains a nul byte.
+    pub fn zeroed_named(size: usize, name: &str) -> std::io::Result<Self> {
+        const MAX_NAME_LEN: usize = 249;
+
+        let mut len = name.len().min(MAX_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let name = std::ffi::CString::new(&name[..len]).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Snapshot name contains a nul byte",
+            )
+        })?;
+        Self::zeroed_impl(size, &name)
+    }
+
+    /// Create a new snapshot with zeroed content of the given size, like
+    /// [`zeroed`](Snapshot::zeroed), backed by a temporary file in `dir`
+    /// instead of anonymous memory.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    ///
+    /// The file is removed from `dir` as soon as it's created, so it's never
+    /// left behind, even if the process crashes. On Linux the file is
+    /// created unnamed with `O_TMPFILE` when the file system supports it.
+    /// On Windows the file can't be removed while it's open, so it's
+    /// deleted when the snapshot and its views are dropped instead.
+    pub fn zeroed_in(size: usize, dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let size = size.next_multiple_of(page_size());
+        let file = Self::temp_file_impl(dir.as_ref())?;
+        file.set_len(size as u64)?;
+        Self::from_file(file)
+    }
+
+    /// Create a new snapshot of the given size with every byte set to `byte`.
+    /// The actual snapshot size will be rounded up to the next system page size,
+    /// and the whole snapshot is filled.
+    ///
+    /// Filling with `0` is the same as [`Snapshot::zeroed`]. Any other value
+    /// is written to every page, so the backing becomes fully resident.
+    pub fn filled(size: usize, byte: u8) -> std::io::Result<Self> {
+        let mut this = Self::zeroed(size)?;
+        if byte != 0 {
+            this.view_mut()?.as_mut_slice().fill(byte);
+        }
+        Ok(this)
     }
 
     /// Create a new snapshot from a byte slice.
     /// The snapshot is populated with the content of the slice.
-    /// The actual snapshot size will be rounded up to the next system page size.
+    /// The actual snapshot size will be rounded up to the next system page size,
+    /// but the [logical length](Snapshot::set_logical_len) of the snapshot,
+    /// and so the length of its views, is the length of the slice.
+    ///
+    /// Pages of `buf` that are entirely zero are not written to the
+    /// snapshot, so that they don't take up any memory in its backing.
+    /// The content of the snapshot is the same either way.
     pub fn from_slice(buf: &[u8]) -> std::io::Result<Self> {
         let mut this = Self::zeroed(buf.len())?;
-        this.view_mut()?.as_mut_slice()[0..buf.len()].copy_from_slice(buf);
+        if !is_zero(buf) {
+            copy_sparse(this.view_mut()?.as_mut_slice(), buf);
+        }
+        this.len = buf.len();
+        Ok(this)
+    }
+
+    /// Create a new snapshot of the given size from the content read from
+    /// a reader.
+    /// Exactly `size` bytes are read, and an `UnexpectedEof` error is
+    /// returned if the reader ends before that.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    ///
+    /// As with [`Snapshot::from_slice`], pages that are entirely zero are not
+    /// written to the snapshot.
+    pub fn read_from<R: std::io::Read>(r: &mut R, size: usize) -> std::io::Result<Self> {
+        let mut this = Self::zeroed(size)?;
+        let mut view = this.view_mut()?;
+        let mut buf = vec![0; page_size()];
+        for dst in view.as_mut_slice()[..size].chunks_mut(page_size()) {
+            let buf = &mut buf[..dst.len()];
+            r.read_exact(buf)?;
+            if !is_zero(buf) {
+                dst.copy_from_slice(buf);
+            }
+        }
+        drop(view);
+        Ok(this)
+    }
+
+    /// Create a new snapshot from the content read from a reader until it
+    /// ends, for when the size of the content is not known in advance,
+    /// unlike with [`read_from`](Snapshot::read_from).
+    /// The snapshot is [grown](Snapshot::grow) as the content is read,
+    /// doubling its size each time, and is truncated to the content once
+    /// the reader ends. The logical length of the snapshot is the number
+    /// of bytes read, see [`set_logical_len`](Snapshot::set_logical_len).
+    /// A reader that ends right away gives an empty snapshot.
+    ///
+    /// As with [`Snapshot::from_slice`], pages that are entirely zero are not
+    /// written to the snapshot.
+    pub fn from_reader<R: std::io::Read>(mut r: R) -> std::io::Result<Self> {
+        let page = page_size();
+        let mut this = Self::zeroed(page)?;
+        let mut buf = vec![0; page];
+        let mut len = 0;
+        let mut done = false;
+        while !done {
+            if len == this.size {
+                this.grow(2 * this.size)?;
+            }
+            let mut view = this.view_mut()?;
+            for dst in view.as_full_mut_slice()[len..].chunks_mut(page) {
+                let mut filled = 0;
+                while filled < page {
+                    match r.read(&mut buf[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                if !is_zero(&buf[..filled]) {
+                    dst[..filled].copy_from_slice(&buf[..filled]);
+                }
+                len += filled;
+                if filled < page {
+                    done = true;
+                    break;
+                }
+            }
+        }
+        this.truncate(len)?;
+        this.set_logical_len(len)?;
         Ok(this)
     }
 
@@ -107,7 +443,359 @@ impl Snapshot {
     /// Note: This method copies the entire content of the snapshot and
     /// depending on its size, it can be slow.
     pub fn try_clone(&self) -> std::io::Result<Self> {
-        Self::from_slice(self.view()?.as_slice())
+        let mut clone = Self::from_slice(self.view()?.as_full_slice())?;
+        clone.len = self.len;
+        clone.metadata = self.metadata.clone();
+        Ok(clone)
+    }
+
+    /// Create a new snapshot cloned from this snapshot, like
+    /// [`try_clone`](Snapshot::try_clone), sharing the physical pages of
+    /// this snapshot until either snapshot is modified, when the file
+    /// system of the backing supports reflinks.
+    /// The two snapshots are independent either way.
+    ///
+    /// Returns the new snapshot, and whether it was created with a
+    /// reflink. If not, the content was copied. Reflinks are only
+    /// supported on Linux, with the `FICLONE` ioctl, for snapshots created
+    /// from a file on a file system that supports them (e.g., Btrfs or
+    /// XFS). The backing of zeroed snapshots lives in tmpfs, which doesn't.
+    pub fn try_clone_cow(&self) -> std::io::Result<(Self, bool)> {
+        if let Some(mut clone) = self.clone_cow_impl()? {
+            clone.len = self.len;
+            clone.metadata = self.metadata.clone();
+            return Ok((clone, true));
+        }
+        Ok((self.try_clone()?, false))
+    }
+
+    /// Create a new snapshot sharing the backing of this one, deferring the
+    /// copy of its content until either snapshot is modified.
+    /// The new snapshot starts with the content of this snapshot, and the
+    /// two are independent, as with [`try_clone`](Snapshot::try_clone).
+    ///
+    /// Both snapshots share the same backing until either of them is
+    /// modified through a [`MutView`], which first gives that snapshot a
+    /// backing of its own with a copy of the whole content, cloned with a
+    /// reflink where the backing supports it, and copied otherwise.
+    /// Copy-on-write views of either snapshot, e.g., with
+    /// [`view`](Snapshot::view), never copy the backing.
+    /// Shared snapshots can be stacked, and
+    /// [`take_snapshot`](View::take_snapshot) on a view of any of them
+    /// returns a snapshot with the same content.
+    ///
+    /// For overlays that only hold the pages they change, see
+    /// [`LayeredSnapshot`] and [`capture_layer`](View::capture_layer).
+    pub fn try_clone_shared(&self) -> std::io::Result<Self> {
+        let mut layer = Self::shared(self.file.clone(), self.size);
+        layer.len = self.len;
+        layer.metadata = self.metadata.clone();
+        Ok(layer)
+    }
+
+    /// Create a new snapshot by joining the content of several snapshots,
+    /// laid end to end in the given order.
+    /// Since the size of every snapshot is a multiple of the system page
+    /// size, each part starts at a page-aligned offset: the sum of the
+    /// sizes of the parts before it.
+    /// Returns the new snapshot together with the offset of each part.
+    ///
+    /// On Linux the content is copied by the kernel when possible, without
+    /// mapping the parts.
+    ///
+    /// Note: This method copies the entire content of the parts and
+    /// depending on their size, it can be slow.
+    pub fn concat(parts: &[&Snapshot]) -> std::io::Result<(Self, Vec<usize>)> {
+        let offsets: Vec<usize> = parts
+            .iter()
+            .scan(0, |offset, part| {
+                let start = *offset;
+                *offset += part.size;
+                Some(start)
+            })
+            .collect();
+        let size = parts.iter().map(|part| part.size).sum();
+
+        let mut this = Self::zeroed(size)?;
+        for (part, &offset) in parts.iter().zip(&offsets) {
+            let copied =
+                r#impl::copy_range_impl(part.as_raw_fd(), 0, this.as_raw_fd(), offset, part.size)?;
+            if !copied {
+                let mut view = this.view_mut()?;
+                let dst = &mut view.as_mut_slice()[offset..offset + part.size];
+                copy_sparse(dst, part.view()?.as_full_slice());
+            }
+        }
+        Ok((this, offsets))
+    }
+
+    /// Create `n` new snapshots cloned from this snapshot.
+    /// Each of the new snapshots is independent of this snapshot and of
+    /// each other.
+    ///
+    /// Each clone is created with a reflink where the backing supports
+    /// it, like [`try_clone_cow`](Snapshot::try_clone_cow). Otherwise
+    /// this is equivalent to calling [`try_clone`](Snapshot::try_clone)
+    /// `n` times, but the content of this snapshot is only mapped once
+    /// for all the clones.
+    ///
+    /// Note: Without reflinks, this method copies the entire content of
+    /// the snapshot for each clone and depending on its size, it can be
+    /// slow.
+    pub fn try_clone_n(&self, n: usize) -> std::io::Result<Vec<Self>> {
+        let mut view = None;
+        (0..n)
+            .map(|_| {
+                // once a reflink fails, the backing doesn't support them
+                let reflink = match view {
+                    None => self.clone_cow_impl()?,
+                    Some(_) => None,
+                };
+                let mut clone = match reflink {
+                    Some(clone) => clone,
+                    None => {
+                        if view.is_none() {
+                            view = Some(self.view()?);
+                        }
+                        Self::from_slice(view.as_ref().unwrap().as_full_slice())?
+                    }
+                };
+                clone.len = self.len;
+                clone.metadata = self.metadata.clone();
+                Ok(clone)
+            })
+            .collect()
+    }
+
+    /// Returns whether this snapshot has the same size and content as
+    /// another one.
+    /// The comparison stops at the first page that differs, and empty
+    /// snapshots are equal without mapping them.
+    pub fn content_eq(&self, other: &Snapshot) -> std::io::Result<bool> {
+        if self.size != other.size || self.len != other.len {
+            return Ok(false);
+        }
+        if self.size == 0 {
+            return Ok(true);
+        }
+        let (this, other) = (self.view()?, other.view()?);
+        let page = page_size();
+        Ok(this
+            .as_full_slice()
+            .chunks(page)
+            .zip(other.as_full_slice().chunks(page))
+            .all(|(a, b)| a == b))
+    }
+
+    /// Returns whether the first `logical_len` bytes of this snapshot are
+    /// the same as those of another one, like
+    /// [`content_eq`](Snapshot::content_eq), ignoring the sizes and
+    /// logical lengths of the snapshots, and the bytes past `logical_len`,
+    /// e.g., the padding up to the page size.
+    ///
+    /// Returns an `InvalidInput` error wrapping a
+    /// [`SnapshotError::RangeOutOfBounds`] if `logical_len` is larger than
+    /// the size of either snapshot.
+    pub fn content_eq_logical(
+        &self,
+        other: &Snapshot,
+        logical_len: usize,
+    ) -> std::io::Result<bool> {
+        if logical_len > self.size || logical_len > other.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        if logical_len == 0 {
+            return Ok(true);
+        }
+        let (this, other) = (self.view()?, other.view()?);
+        let page = page_size();
+        Ok(this.as_full_slice()[..logical_len]
+            .chunks(page)
+            .zip(other.as_full_slice()[..logical_len].chunks(page))
+            .all(|(a, b)| a == b))
+    }
+
+    /// Write the whole content of the snapshot to a writer, one page at a
+    /// time, without copying it.
+    /// This writes as many bytes as the [capacity](View::capacity) of a
+    /// view of the snapshot, ignoring its logical length.
+    pub fn write_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let view = self.view()?;
+        for chunk in view.as_full_slice().chunks(page_size()) {
+            w.write_all(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Save the whole content of the snapshot to a file at `path`, creating
+    /// it or truncating it if it already exists.
+    /// The file has the same length as the [capacity](View::capacity) of a
+    /// view of the snapshot.
+    ///
+    /// On Linux the content is copied by the kernel with
+    /// `copy_file_range`, without copying it through userspace, when the
+    /// file system of `path` supports it. Otherwise the content is written
+    /// to the file, skipping the pages that are entirely zero.
+    /// Use [`open_path`](Snapshot::open_path) to load it back.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        use std::io::{Seek as _, SeekFrom, Write as _};
+
+        let mut file = std::fs::File::create(path)?;
+        file.set_len(self.size as u64)?;
+        if self.copy_to_file_impl(&file)? {
+            return Ok(());
+        }
+
+        let view = self.view()?;
+        let page = page_size();
+        for (idx, chunk) in view.as_full_slice().chunks(page).enumerate() {
+            if !is_zero(chunk) {
+                file.seek(SeekFrom::Start((idx * page) as u64))?;
+                file.write_all(chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a new snapshot from the file at `path`, like
+    /// [`from_file`](Snapshot::from_file), e.g., to load a snapshot saved
+    /// with [`save_to_path`](Snapshot::save_to_path).
+    /// The file is opened read-only, so mutable views of the snapshot
+    /// can't be created, and it shouldn't be modified while the snapshot
+    /// is alive.
+    pub fn open_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::from_file(std::fs::File::open(path)?)
+    }
+
+    /// Grow the snapshot to at least `new_size` bytes, preserving its
+    /// content. The new bytes are zeroed.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    /// Calling this method with a size smaller than the current size of
+    /// the snapshot has no effect.
+    ///
+    /// On Linux the backing of the snapshot is extended in place, so for a
+    /// snapshot created from a file, the file is extended as well.
+    /// On other platforms, or if the backing can't be extended (e.g., a
+    /// file opened as read-only), the content of the snapshot is copied
+    /// into a new backing, which can be slow depending on its size.
+    pub fn grow(&mut self, new_size: usize) -> std::io::Result<()> {
+        let new_size = new_size.next_multiple_of(page_size());
+        if new_size <= self.size {
+            return Ok(());
+        }
+        // a shared backing can't be resized in place
+        if Arc::get_mut(&mut self.file).is_some() && self.resize_impl(new_size)? {
+            self.size = new_size;
+            self.len = new_size;
+            return Ok(());
+        }
+        let mut snapshot = Self::zeroed(new_size)?;
+        copy_sparse(
+            snapshot.view_mut()?.as_mut_slice(),
+            self.view()?.as_full_slice(),
+        );
+        std::mem::swap(&mut self.file, &mut snapshot.file);
+        self.size = new_size;
+        self.len = new_size;
+        Ok(())
+    }
+
+    /// Shrink the snapshot to `new_size` bytes, discarding the content past
+    /// that size and releasing its backing memory.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    /// Calling this method with a size larger than the current size of
+    /// the snapshot has no effect.
+    ///
+    /// On Linux the backing of the snapshot is truncated in place, so for a
+    /// snapshot created from a file, the file is truncated as well.
+    /// On other platforms, or if the backing can't be truncated (e.g., a
+    /// file opened as read-only), the remaining content of the snapshot is
+    /// copied into a new backing, which can be slow depending on its size.
+    pub fn truncate(&mut self, new_size: usize) -> std::io::Result<()> {
+        let new_size = new_size.next_multiple_of(page_size());
+        if new_size >= self.size {
+            return Ok(());
+        }
+        // a shared backing can't be resized in place
+        if Arc::get_mut(&mut self.file).is_some() && self.resize_impl(new_size)? {
+            self.size = new_size;
+            self.len = new_size;
+            return Ok(());
+        }
+        let mut snapshot = Self::zeroed(new_size)?;
+        copy_sparse(
+            snapshot.view_mut()?.as_mut_slice(),
+            &self.view()?.as_full_slice()[..new_size],
+        );
+        std::mem::swap(&mut self.file, &mut snapshot.file);
+        self.size = new_size;
+        self.len = new_size;
+        Ok(())
+    }
+
+    /// Set the logical length of the snapshot, the [length](View::len) of
+    /// the slices of its views, without changing its size or remapping it.
+    /// This is useful when the meaningful content of the snapshot is
+    /// shorter than its size, which is a multiple of the system page size.
+    /// By default it's the size of the snapshot, except for snapshots
+    /// created with [`from_slice`](Snapshot::from_slice), whose logical
+    /// length is the length of the slice.
+    ///
+    /// The bytes past the logical length are still mapped, and accessible
+    /// with [`as_full_slice`](View::as_full_slice). Page-based methods of
+    /// the views, and the methods of the snapshot that copy or save its
+    /// content, still cover its whole size.
+    /// Resizing the snapshot resets the logical length to its new size.
+    ///
+    /// Returns an `InvalidInput` error if `len` is larger than the size of
+    /// the snapshot.
+    pub fn set_logical_len(&mut self, len: usize) -> std::io::Result<()> {
+        if len > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    /// Returns the logical length of the snapshot, see
+    /// [`set_logical_len`](Snapshot::set_logical_len).
+    pub fn logical_len(&self) -> usize {
+        self.len
+    }
+
+    /// Attach `metadata` to this snapshot, e.g., a label or a generation
+    /// number, replacing any metadata attached before.
+    /// The metadata is kept out of the content of the snapshot, so it
+    /// doesn't change its size or the content of its views.
+    ///
+    /// The metadata is carried over to the snapshots created from this
+    /// one with [`try_clone`](Snapshot::try_clone),
+    /// [`try_clone_cow`](Snapshot::try_clone_cow),
+    /// [`try_clone_n`](Snapshot::try_clone_n), [`try_clone_shared`](Snapshot::try_clone_shared),
+    /// and [`take_snapshot`](View::take_snapshot) on any of its views.
+    pub fn set_metadata(&mut self, metadata: Vec<u8>) {
+        self.metadata = (!metadata.is_empty()).then(|| metadata.into());
+    }
+
+    /// Returns the metadata attached to this snapshot with
+    /// [`set_metadata`](Snapshot::set_metadata), empty by default.
+    pub fn metadata(&self) -> &[u8] {
+        self.metadata.as_deref().unwrap_or_default()
+    }
+
+    /// Swap the content of this snapshot with that of `other`, without
+    /// copying it, e.g., to flip the buffers of a double-buffering scheme.
+    /// This swaps the backings of the snapshots, so it's cheap whatever
+    /// their size.
+    ///
+    /// The snapshots don't need to have the same size: the size, the
+    /// logical length and the [checkpoints](Snapshot::checkpoint) of the
+    /// snapshots are swapped along with their content.
+    /// Views borrow their snapshot, so there can't be any views of either
+    /// snapshot while they are swapped. Views created through an [`Arc`]
+    /// keep the snapshot they were created from.
+    pub fn swap(&mut self, other: &mut Snapshot) {
+        std::mem::swap(self, other);
     }
 }
 
@@ -117,7 +805,37 @@ impl Snapshot {
     /// The view holds an immutable borrow of the snapshot, and has a
     /// lifetime tied to this borrow.
     pub fn view(&self) -> std::io::Result<CowView> {
-        CowView::new(self, self.as_raw_fd(), self.size, ViewMode::Cow)
+        self.cow_view(self, Mapping::Lazy)
+    }
+
+    /// Create a copy-on-write view into the content of this snapshot, like
+    /// [`view`](Snapshot::view), surrounded by inaccessible guard pages.
+    /// Accessing the page right before or right after the view raises a
+    /// segmentation fault (an access violation on Windows), instead of
+    /// silently accessing whatever memory is mapped there.
+    ///
+    /// The guard pages are not part of the view, and [`len`](View::len)
+    /// doesn't include them. They are kept when the view is restored.
+    /// Under Miri there are no guard pages, since out of bounds accesses
+    /// are already detected.
+    pub fn view_guarded(&self) -> std::io::Result<CowView> {
+        self.cow_view(self, Mapping::Guarded)
+    }
+
+    /// Create a copy-on-write view into the content of this snapshot, like
+    /// [`view`](Snapshot::view), with all of its pages made resident when
+    /// it's created, so that accessing them for the first time doesn't
+    /// take a page fault.
+    /// This is useful for latency sensitive code, but wasteful if only a
+    /// few pages of the view are accessed.
+    ///
+    /// This uses `MAP_POPULATE` on Linux and `PrefetchVirtualMemory` on
+    /// Windows, and is a hint on other platforms. The pages are populated
+    /// on a best effort basis: if the system can't populate them, they are
+    /// mapped lazily, as in any other view.
+    /// Pages are not populated again when the view is restored.
+    pub fn view_populated(&self) -> std::io::Result<CowView> {
+        self.cow_view(self, Mapping::Populated)
     }
 
     /// Create a mutable view into the content of this snapshot.
@@ -125,8 +843,19 @@ impl Snapshot {
     /// The view holds a mutable borrow of the snapshot, and has a
     /// lifetime tied to this borrow.
     /// Only one mutable view can exist at a time.
+    ///
+    /// If the snapshot has [checkpoints](Snapshot::checkpoint), the changes
+    /// are only written to the snapshot when the view is dropped.
     pub fn view_mut(&mut self) -> std::io::Result<MutView> {
-        MutView::new(self, self.as_raw_fd(), self.size, ViewMode::Mutable)
+        self.make_unique()?;
+        let (len, metadata) = (self.len, self.metadata.clone());
+        let mut view = match self.checkpoints {
+            Some(_) => self.journaled_view_mut()?.unwrap(),
+            None => MutView::new(self, self.as_raw_fd(), self.size, ViewMode::Mutable)?,
+        };
+        view.len = len;
+        view.metadata = metadata;
+        Ok(view)
     }
 
     /// Create a copy-on-write view into the content of this snapshot
@@ -134,55 +863,500 @@ impl Snapshot {
     /// Changes to this view do not affect the snapshot.
     /// The view has no lifetime requirements.
     pub fn view_arc(self: &Arc<Self>) -> std::io::Result<ArcView> {
-        ArcView::new(self.clone(), self.as_raw_fd(), self.size, ViewMode::Cow)
+        self.cow_view(self.clone(), Mapping::Lazy)
+    }
+
+    /// Create a copy-on-write view into the content of a snapshot through
+    /// a [`Weak`] reference, like [`view_arc`](Snapshot::view_arc).
+    /// Returns `None` if the snapshot has already been dropped.
+    /// The view holds a strong reference to the snapshot, so it keeps the
+    /// snapshot alive while the view exists.
+    ///
+    /// This is useful for caches of snapshots that should not prevent
+    /// the snapshots from being dropped.
+    pub fn view_weak(weak: &Weak<Self>) -> Option<std::io::Result<ArcView>> {
+        weak.upgrade().map(|this| this.view_arc())
+    }
+
+    fn cow_view<S>(&self, snapshot: S, mapping: Mapping) -> std::io::Result<View<S>> {
+        let mut view = View::map(
+            snapshot,
+            self.as_raw_fd(),
+            self.size,
+            ViewMode::Cow,
+            mapping,
+        )?;
+        view.root = Some(self.file.clone());
+        view.len = self.len;
+        view.metadata = self.metadata.clone();
+        Ok(view)
+    }
+
+    /// Give this snapshot a backing of its own if it shares it with other
+    /// snapshots, so that modifying it doesn't modify them.
+    fn make_unique(&mut self) -> std::io::Result<()> {
+        if Arc::get_mut(&mut self.file).is_some() {
+            return Ok(());
+        }
+        if let Some(mut snapshot) = self.clone_cow_impl()? {
+            std::mem::swap(&mut self.file, &mut snapshot.file);
+            return Ok(());
+        }
+        let mut snapshot = Self::zeroed(self.size)?;
+        if !r#impl::copy_range_impl(self.as_raw_fd(), 0, snapshot.as_raw_fd(), 0, self.size)? {
+            copy_sparse(
+                snapshot.view_mut()?.as_mut_slice(),
+                self.view()?.as_full_slice(),
+            );
+        }
+        std::mem::swap(&mut self.file, &mut snapshot.file);
+        Ok(())
     }
 }
 
 impl<S> View<S> {
+    fn new(
+        snapshot: S,
+        fd: RawFileDescriptor,
+        size: usize,
+        mode: ViewMode,
+    ) -> std::io::Result<Self> {
+        Self::map(snapshot, fd, size, mode, Mapping::Lazy)
+    }
+
+    fn map(
+        snapshot: S,
+        fd: RawFileDescriptor,
+        size: usize,
+        mode: ViewMode,
+        mapping: Mapping,
+    ) -> std::io::Result<Self> {
+        let ptr = match mapping {
+            Mapping::Lazy => Self::map_impl(fd, size, mode)?,
+            Mapping::Guarded => Self::map_guarded_impl(fd, size, mode)?,
+            Mapping::Populated => Self::map_populated_impl(fd, size, mode)?,
+        };
+        let guarded = mapping == Mapping::Guarded;
+        Ok(Self {
+            fd,
+            ptr,
+            size,
+            len: size,
+            offset: 0,
+            borrowed: false,
+            mode,
+            logical: AccessMap::default(),
+            protection: AccessMap::default(),
+            caching: AccessMap::default(),
+            overlaid: false,
+            layers: Vec::new(),
+            guarded,
+            #[cfg(feature = "protection-history")]
+            history: Default::default(),
+            #[cfg(debug_assertions)]
+            alias: (mode == ViewMode::Mutable).then(|| alias::MutableView::acquire(fd, ptr)),
+            cleanup: Cleanup::default(),
+            root: None,
+            position: 0,
+            write_back: false,
+            journal: None,
+            faults: r#impl::fault_counts_impl(),
+            baseline: None,
+            metadata: None,
+            _snapshot: snapshot,
+        })
+    }
+
+    /// Attach a callback to this view, to be called when the view is
+    /// dropped, after its memory has been unmapped.
+    /// Several callbacks can be attached to the same view, and they are
+    /// called in the order they were attached.
+    pub fn with_cleanup(mut self, f: impl FnOnce() + Send + 'static) -> Self {
+        self.cleanup.0.push(Box::new(f));
+        self
+    }
+
     /// Returns the length of the view in bytes.
+    /// This is the [logical length](Snapshot::set_logical_len) of its
+    /// snapshot, which may be shorter than its [capacity](View::capacity).
     pub fn len(&self) -> usize {
-        self.size
+        self.len
     }
 
     /// Returns `true` if the view is empty.
     pub fn is_empty(&self) -> bool {
-        self.size == 0
+        self.len == 0
+    }
+
+    /// Returns the number of bytes mapped by the view, a multiple of the
+    /// system page size except for sub-views.
+    /// This is the same as [`len`](View::len), unless the snapshot of the
+    /// view has a shorter [logical length](Snapshot::set_logical_len).
+    /// Page-based methods, like [`protect`](View::protect) or
+    /// [`restore_range`](View::restore_range), apply to the whole capacity.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of pages of the view, i.e., its length in
+    /// units of the system page size, rounded up for sub-views whose
+    /// length is not a multiple of the page size.
+    pub fn page_count(&self) -> usize {
+        self.size.div_ceil(page_size())
+    }
+
+    /// Returns an iterator over the pages of the view, as slices of the
+    /// system page size, up to the [capacity](View::capacity) of the view.
+    /// The last slice of a sub-view may be shorter.
+    pub fn pages(&self) -> std::slice::Chunks<'_, u8> {
+        self.as_full_slice().chunks(page_size())
+    }
+
+    /// Returns an iterator over the pages of the view, as mutable slices
+    /// of the system page size, up to the [capacity](View::capacity) of
+    /// the view.
+    /// The last slice of a sub-view may be shorter.
+    pub fn pages_mut(&mut self) -> std::slice::ChunksMut<'_, u8> {
+        self.as_full_mut_slice().chunks_mut(page_size())
     }
 
     /// Returns a slice containing the entire view.
     /// This is equicalent to `&view[..]`,
     pub fn as_slice(&self) -> &[u8] {
-        unsafe { std::slice::from_raw_parts(self.ptr, self.size) }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
     }
 
     /// Returns a mutable slice containing the entire view.
     /// This is equicalent to `&mut view[..]`,
+    ///
+    /// In debug builds, this panics if this is a mutable view and other
+    /// mutable views of the same snapshot backing are alive, since
+    /// mutating aliased views concurrently is a data race.
     pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.check_alias();
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    /// Returns a slice containing the whole [capacity](View::capacity) of
+    /// the view, including the bytes past its [length](View::len).
+    pub fn as_full_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.size) }
+    }
+
+    /// Returns a mutable slice containing the whole
+    /// [capacity](View::capacity) of the view, including the bytes past
+    /// its [length](View::len).
+    /// Like [`as_mut_slice`](View::as_mut_slice), in debug builds this
+    /// panics if this mutable view is aliased.
+    pub fn as_full_mut_slice(&mut self) -> &mut [u8] {
+        self.check_alias();
         unsafe { std::slice::from_raw_parts_mut(self.ptr, self.size) }
     }
 
+    /// Returns a reference to a byte or a subslice of the view, or `None`
+    /// if the index is out of bounds, like [`slice::get`].
+    pub fn get<I: SliceIndex<[u8]>>(&self, index: I) -> Option<&I::Output> {
+        self.as_slice().get(index)
+    }
+
+    /// Returns the bytes of `range` of the view, like `&view[range]`, but
+    /// returns an error rather than panicking if the range is out of
+    /// bounds, e.g., for untrusted offsets.
+    ///
+    /// Returns an `InvalidInput` error wrapping a
+    /// [`SnapshotError::SliceOutOfBounds`] with the range and the length
+    /// of the view if the range is out of bounds.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> std::io::Result<&[u8]> {
+        let range = self.slice_range(range)?;
+        Ok(&self.as_slice()[range])
+    }
+
+    /// Returns the bytes of `range` of the view mutably, like
+    /// [`slice`](View::slice).
+    pub fn slice_mut(&mut self, range: impl RangeBounds<usize>) -> std::io::Result<&mut [u8]> {
+        let range = self.slice_range(range)?;
+        Ok(&mut self.as_mut_slice()[range])
+    }
+
+    /// Returns `range` as a `Range`, if it's within the bounds of the view.
+    fn slice_range(&self, range: impl RangeBounds<usize>) -> std::io::Result<Range<usize>> {
+        let range = to_range(range, self.len)?;
+        if range.start > range.end || range.end > self.len {
+            let len = self.len;
+            return Err(SnapshotError::SliceOutOfBounds { range, len }.into());
+        }
+        Ok(range)
+    }
+
+    /// Copy the bytes of this view starting at `offset` into `buf`, and
+    /// return the number of bytes copied, which is less than the length
+    /// of `buf` if the view ends before, and `0` if `offset` is past the
+    /// end of the view.
+    pub fn read_into(&self, offset: usize, buf: &mut [u8]) -> std::io::Result<usize> {
+        let src = self.as_slice().get(offset..).unwrap_or_default();
+        let len = src.len().min(buf.len());
+        buf[..len].copy_from_slice(&src[..len]);
+        Ok(len)
+    }
+
+    /// Copy the bytes of `buf` into this view starting at `offset`, like
+    /// [`read_into`](View::read_into) copies them out, and return the
+    /// number of bytes copied.
+    /// For copy-on-write views the changes stay local to the view.
+    pub fn write_from(&mut self, offset: usize, buf: &[u8]) -> std::io::Result<usize> {
+        let dst = self.as_mut_slice().get_mut(offset..).unwrap_or_default();
+        let len = dst.len().min(buf.len());
+        dst[..len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Returns a mutable reference to a byte or a subslice of the view, or
+    /// `None` if the index is out of bounds, like [`slice::get_mut`].
+    /// Like [`as_mut_slice`](View::as_mut_slice), in debug builds this
+    /// panics if this mutable view is aliased.
+    pub fn get_mut<I: SliceIndex<[u8]>>(&mut self, index: I) -> Option<&mut I::Output> {
+        self.as_mut_slice().get_mut(index)
+    }
+
+    /// Returns the offset of the first occurrence of `needle` in the view,
+    /// or `None` if it's not found.
+    /// An empty needle is found at offset `0`.
+    ///
+    /// The search uses SIMD instructions when they are available.
+    pub fn find(&self, needle: &[u8]) -> Option<usize> {
+        memchr::memmem::find(self.as_slice(), needle)
+    }
+
+    /// Returns the offsets of all the non-overlapping occurrences of
+    /// `needle` in the view, in increasing order, like
+    /// [`str::match_indices`].
+    pub fn find_all(&self, needle: &[u8]) -> Vec<usize> {
+        memchr::memmem::find_iter(self.as_slice(), needle).collect()
+    }
+
     /// Returns the base pointer of the view.
     pub fn as_ptr(&self) -> *const u8 {
         self.ptr
     }
 
     /// Returns the base mutable pointer of the view.
+    /// Like [`as_mut_slice`](View::as_mut_slice), in debug builds this
+    /// panics if this mutable view is aliased.
     pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.check_alias();
         self.ptr
     }
 
+    #[inline]
+    fn check_alias(&self) {
+        #[cfg(debug_assertions)]
+        if let Some(alias) = &self.alias {
+            alias.check();
+        }
+    }
+
+    /// Returns a 64-bit hash of the content of this view.
+    /// Unlike the [`Hash`] implementation, the hash only depends on the
+    /// content and its length, so it's the same across runs, platforms and
+    /// for snapshots built in different ways, and it can be used to key a
+    /// cache of snapshots.
+    /// The whole [capacity](View::capacity) of the view is hashed,
+    /// including the zero padding up to the system page size, and the
+    /// capacity is part of the hash, but the logical length is not.
+    ///
+    /// This is a non-cryptographic hash (a variant of FNV-1a that processes
+    /// 8 bytes at a time), so equal hashes don't guarantee equal content.
+    pub fn content_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        // the last word of a sub-view may be shorter, pad it with zeros
+        let mut hash = FNV_OFFSET_BASIS;
+        for chunk in self.as_full_slice().chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            hash ^= u64::from_le_bytes(word);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        hash ^= self.size as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+
+        // FNV only propagates changes to the higher bits, so mix the bits
+        // with the finalizer of splitmix64
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^ (hash >> 31)
+    }
+
     /// Creates a new snapshot from the current content of this view,
     /// including any changes made to it.
     ///
-    /// Note: This method copies the entire content of the view and
-    /// depending on the size of the snapshot, it can be slow.
+    /// If this is a copy-on-write view of a snapshot with no
+    /// [dirty pages](View::dirty_pages), the new snapshot shares the
+    /// backing of the root snapshot instead of copying it.
+    /// The backing is copied later, only if either snapshot is modified
+    /// through a mutable view or resized.
+    /// A view is unmodified when [`dirty_pages`](View::dirty_pages) reports
+    /// no dirty page, which only happens on Linux and Windows: on macOS
+    /// and FreeBSD every page is reported as dirty, so the content is
+    /// always copied there.
+    /// Views of layered snapshots or with pages mapped from other
+    /// snapshots are never considered unmodified.
+    ///
+    /// Note: Otherwise this method copies the entire content of the view
+    /// and depending on the size of the snapshot, it can be slow.
     pub fn take_snapshot(&self) -> std::io::Result<Snapshot> {
-        // TODO: be clever in the case where the memory hasn't been modified
-        // and just return the root snapshot.
-        // This would probably require different implementations for each
-        // View alias, since the optimization doesn't work for
-        // MutView.
-        Snapshot::from_slice(self.as_slice())
+        if let Some(root) = &self.root {
+            if !self.overlaid && self.dirty_pages()?.is_empty() {
+                let mut snapshot = Snapshot::shared(root.clone(), self.size);
+                snapshot.len = self.len;
+                snapshot.metadata = self.metadata.clone();
+                return Ok(snapshot);
+            }
+        }
+        let mut snapshot = Snapshot::from_slice(self.as_full_slice())?;
+        snapshot.len = self.len;
+        snapshot.metadata = self.metadata.clone();
+        Ok(snapshot)
+    }
+
+    /// Set all the bytes of a memory region of this view to `value`.
+    /// This is equivalent to `view[region].fill(value)`, but it returns an
+    /// error instead of panicking if the region is out of bounds.
+    /// For copy-on-write views the changes stay local to the view.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn fill(&mut self, region: impl RangeBounds<usize>, value: u8) -> std::io::Result<()> {
+        let Range { start, end } = to_range(region, self.len)?;
+        if end < start || end > self.len {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        self.as_mut_slice()[start..end].fill(value);
+        Ok(())
+    }
+
+    /// Set all the bytes of a memory region of this view to `value`, like
+    /// [`fill`](View::fill), but using non-temporal stores that bypass the
+    /// cache, so that filling a large region doesn't evict the data that
+    /// is in use, e.g., when initializing a huge snapshot.
+    ///
+    /// Non-temporal stores are only used on x86 and x86_64 with SSE2, and
+    /// only for regions large enough to make them worth it, otherwise this
+    /// is the same as [`fill`](View::fill). The content of the region is
+    /// the same either way.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn fill_nontemporal(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        value: u8,
+    ) -> std::io::Result<()> {
+        let Range { start, end } = to_range(region, self.len)?;
+        if end < start || end > self.len {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        fill_streaming(&mut self.as_mut_slice()[start..end], value);
+        Ok(())
+    }
+
+    /// Copy the bytes of the `src` region of this view to `dest`, like
+    /// [`slice::copy_within`]. The two regions can overlap.
+    ///
+    /// Returns an `InvalidInput` error, instead of panicking, if either the
+    /// source region or the destination region is out of bounds.
+    pub fn copy_within(
+        &mut self,
+        src: impl RangeBounds<usize>,
+        dest: usize,
+    ) -> std::io::Result<()> {
+        let Range { start, end } = to_range(src, self.len)?;
+        if end < start
+            || end > self.len
+            || dest
+                .checked_add(end - start)
+                .is_none_or(|dest_end| dest_end > self.len)
+        {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        self.as_mut_slice().copy_within(start..end, dest);
+        Ok(())
+    }
+
+    /// Set all the bytes of a memory region of this view to zero, like
+    /// [`fill`](View::fill) with a value of `0`.
+    /// See [`zero_fast`](View::zero_fast) to zero large regions without
+    /// writing to every page.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn zero(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        self.fill(region, 0)
+    }
+
+    /// Set all the bytes of a memory region of this view to zero.
+    /// This is equivalent to `view[region].fill(0)`, but the pages fully
+    /// contained in the region are zeroed without writing to them.
+    ///
+    /// On Linux, for a mutable view this punches a hole in the backing of
+    /// the snapshot, which frees the memory of those pages and modifies
+    /// the root snapshot like any other write to the view.
+    /// On Linux and macOS, for a copy-on-write view, those pages are
+    /// replaced with private zero pages, and the root snapshot is not
+    /// modified.
+    /// On other platforms, or if the backing doesn't support punching holes,
+    /// this falls back to writing the zeros.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn zero_fast(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let Range { start, end } = to_range(region, self.len)?;
+        if end < start || end > self.len {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+
+        // the pages are aligned in the root snapshot, which matters for
+        // sub-views that don't start at a page boundary
+        let page = page_size();
+        let first = (self.offset + start).next_multiple_of(page);
+        let last = (self.offset + end) / page * page;
+        if last <= first {
+            self.as_mut_slice()[start..end].fill(0);
+            return Ok(());
+        }
+        let pages = first - self.offset..last - self.offset;
+
+        self.check_alias();
+        // anonymous zero pages can't be told apart from clean pages when
+        // writing the changes back
+        if self.write_back || !self.zero_pages_impl(pages.clone())? {
+            self.as_mut_slice()[pages.clone()].fill(0);
+        }
+        self.as_mut_slice()[start..pages.start].fill(0);
+        self.as_mut_slice()[pages.end..end].fill(0);
+        Ok(())
+    }
+
+    /// Apply again the memory protection recorded for the bytes in
+    /// `region`, after the backend replaced the mapping of that region.
+    fn reapply_protection(&mut self, region: Range<usize>) -> std::io::Result<()> {
+        for (run, allow, caching) in self.protected_runs(region) {
+            self.protect_impl(run, allow, caching)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the runs of `region` with the same recorded access
+    /// permissions and caching attributes, covering the whole region.
+    fn protection_runs(&self, region: Range<usize>) -> Vec<(Range<usize>, Access, Caching)> {
+        protection_runs(&self.protection, &self.caching, self.size, region)
+    }
+
+    /// Returns the runs of `region` that don't have the default access
+    /// permissions and caching attributes of the view, see
+    /// [`protection_runs`](View::protection_runs).
+    fn protected_runs(&self, region: Range<usize>) -> Vec<(Range<usize>, Access, Caching)> {
+        let default = Access::READ | Access::WRITE;
+        let mut runs = self.protection_runs(region);
+        runs.retain(|(_, allow, caching)| *allow != default || *caching != Caching::Default);
+        runs
     }
 
     /// Restrict the access permissions of a memory region on this view.
@@ -190,53 +1364,646 @@ impl<S> View<S> {
     /// and the `allow` parameter specifies the access permissions to allow.
     /// The range must be page-aligned and within the bounds of the view.
     /// The access permissions can be combined using bitwise OR.
-    #[allow(dead_code)] // this feature is still experimental
-    pub(crate) fn protect(
+    /// Accessing the region in a way that is not allowed raises a
+    /// segmentation fault (an access violation on Windows).
+    /// Returns an `InvalidInput` error if the range is empty, out of bounds
+    /// or not page-aligned.
+    ///
+    /// To restrict the access permissions only temporarily, use
+    /// [`protect_scoped`](View::protect_scoped).
+    pub fn protect(
         &mut self,
         region: impl RangeBounds<usize>,
         allow: Access,
     ) -> std::io::Result<()> {
-        let start = match region.start_bound() {
-            Bound::Included(&s) => s,
-            Bound::Excluded(&s) => s + 1,
-            Bound::Unbounded => 0,
-        };
-        let end = match region.end_bound() {
-            Bound::Included(&s) => s + 1,
-            Bound::Excluded(&s) => s,
-            Bound::Unbounded => self.size,
-        };
+        self.protect_with_caching(region, allow, Caching::Default)
+    }
 
-        if end <= start || end > self.size {
+    /// Restrict the access permissions of a memory region on this view,
+    /// like [`protect`](View::protect), and also set the caching
+    /// attributes of the region.
+    /// See [`Caching`] for the platform support of each attribute.
+    pub fn protect_with_caching(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        allow: Access,
+        caching: Caching,
+    ) -> std::io::Result<()> {
+        let Range { start, end } = protection_range(region, self.size)?;
+
+        self.set_protection(start..end, allow, caching)
+    }
+
+    /// Restrict the access permissions of several memory regions of this
+    /// view, like calling [`protect`](View::protect) for each of them, but
+    /// with a single system call for each run of adjacent regions with
+    /// the same access permissions.
+    ///
+    /// All the regions are validated before any of them is protected, so
+    /// if any region is invalid, none is protected. Returns an
+    /// `InvalidInput` error if any region is empty, out of bounds or not
+    /// page-aligned, or if any two regions overlap.
+    pub fn protect_many(&mut self, regions: &[(Range<usize>, Access)]) -> std::io::Result<()> {
+        let mut sorted = regions
+            .iter()
+            .map(|(region, allow)| Ok((protection_range(region.clone(), self.size)?, *allow)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        sorted.sort_by_key(|(region, _)| region.start);
+        if sorted
+            .windows(2)
+            .any(|pair| pair[0].0.end > pair[1].0.start)
+        {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
-                "Invalid range for memory protection",
+                "Regions to protect must not overlap",
             ));
         }
+        let mut runs: Vec<(Range<usize>, Access)> = Vec::new();
+        for (region, allow) in sorted {
+            match runs.last_mut() {
+                Some((run, run_allow)) if run.end == region.start && *run_allow == allow => {
+                    run.end = region.end;
+                }
+                _ => runs.push((region, allow)),
+            }
+        }
+        for (run, allow) in runs {
+            self.set_protection(run, allow, Caching::Default)?;
+        }
+        Ok(())
+    }
 
-        if start != start.next_multiple_of(page_size::get())
-            || end != end.next_multiple_of(page_size::get())
-        {
+    /// Reset the memory protection of the whole view back to the view's
+    /// default read and write access, undoing any previous call to
+    /// [`protect`](View::protect), including the caching attributes set
+    /// with [`protect_with_caching`](View::protect_with_caching).
+    /// Only the regions that don't already have the default access are
+    /// changed.
+    /// Unlike [`restore`](View::restore), this doesn't discard any
+    /// changes made to the content of the view.
+    pub fn reset_protections(&mut self) -> std::io::Result<()> {
+        let default = Access::READ | Access::WRITE;
+        for (region, _, _) in self.protected_runs(0..self.size) {
+            self.set_protection(region, default, Caching::Default)?;
+        }
+        self.protection.clear();
+        self.caching.clear();
+        Ok(())
+    }
+
+    /// Change the memory protection of `region` and record the change.
+    fn set_protection(
+        &mut self,
+        region: Range<usize>,
+        allow: Access,
+        caching: Caching,
+    ) -> std::io::Result<()> {
+        self.protect_impl(region.clone(), allow, caching)?;
+        #[cfg(feature = "protection-history")]
+        self.record_protection(region.clone(), allow.implied());
+        match caching {
+            Caching::Default => self.caching.remove(region.clone()),
+            _ => self.caching.insert(region.clone(), caching),
+        }
+        self.protection.insert(region, allow.implied());
+        Ok(())
+    }
+
+    #[cfg(feature = "protection-history")]
+    fn record_protection(&mut self, region: Range<usize>, new: Access) {
+        let time = std::time::Instant::now();
+        let default = Access::READ | Access::WRITE;
+        let events: Vec<_> = self
+            .protection
+            .regions(self.size, default)
+            .filter(|(run, _)| run.start < region.end && region.start < run.end)
+            .map(|(run, old)| ProtectionEvent {
+                range: run.start.max(region.start)..run.end.min(region.end),
+                old,
+                new,
+                time,
+            })
+            .collect();
+        for event in events {
+            if self.history.len() == PROTECTION_HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(event);
+        }
+    }
+
+    /// Returns the recorded changes to the memory protection of the page
+    /// containing `offset`, from oldest to newest.
+    /// Only the last changes to the view are kept, up to a fixed limit.
+    /// Restoring the view is recorded as a change back to the default
+    /// read and write access.
+    ///
+    /// This method is only available with the `protection-history` feature.
+    ///
+    /// ```
+    /// # use memsnap::{Access, Snapshot};
+    /// let snapshot = Snapshot::zeroed(2 * memsnap::page_size())?;
+    /// let mut view = snapshot.view()?;
+    /// view.protect(..memsnap::page_size(), Access::READ)?;
+    /// let history = view.protection_history(0);
+    /// assert_eq!(history.len(), 1);
+    /// assert_eq!(history[0].old, Access::READ | Access::WRITE);
+    /// assert_eq!(history[0].new, Access::READ);
+    /// assert!(view.protection_history(memsnap::page_size()).is_empty());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "protection-history")]
+    pub fn protection_history(&self, offset: usize) -> Vec<ProtectionEvent> {
+        let page = offset / page_size() * page_size();
+        let page = page..page + page_size();
+        self.history
+            .iter()
+            .filter(|event| event.range.start < page.end && page.start < event.range.end)
+            .cloned()
+            .collect()
+    }
+
+    /// Capture the memory protection of the whole view, so that it can
+    /// later be applied again with
+    /// [`apply_protections`](View::apply_protections).
+    pub fn capture_protections(&self) -> ProtectionSnapshot {
+        ProtectionSnapshot {
+            size: self.size,
+            protection: self.protection.clone(),
+        }
+    }
+
+    /// Apply a memory protection layout previously captured with
+    /// [`capture_protections`](View::capture_protections), on this or
+    /// another view of the same size.
+    /// Only the regions whose access differs from the current protection
+    /// of the view are changed.
+    /// Returns an `InvalidInput` error if the layout was captured from a view
+    /// with a different size.
+    pub fn apply_protections(&mut self, layout: &ProtectionSnapshot) -> std::io::Result<()> {
+        if layout.size != self.size {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
-                "Memory protection range must be page-aligned",
+                "Protection layout size does not match the view size",
             ));
         }
+        let default = Access::READ | Access::WRITE;
+        for (region, allow) in self.protection.diff(&layout.protection, self.size, default) {
+            self.set_protection(region, allow, Caching::Default)?;
+        }
+        Ok(())
+    }
+
+    /// Set the memory protection of every page of this view at once, from
+    /// a slice with the access permissions of each page, in order.
+    /// Consecutive pages with the same access are protected together, and
+    /// only the pages whose access differs from the current protection of
+    /// the view are changed, so that the minimal number of system calls is
+    /// issued.
+    /// This is the counterpart of [`protect_many`](View::protect_many) for
+    /// a layout with one access per page, e.g., the page table of an
+    /// emulated MMU.
+    /// Returns an `InvalidInput` error if the length of `perms` is not the
+    /// [`page_count`](View::page_count) of the view.
+    pub fn apply_permission_bitmap(&mut self, perms: &[Access]) -> std::io::Result<()> {
+        if perms.len() != self.page_count() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Permission bitmap length does not match the view page count",
+            ));
+        }
+        let page = page_size();
+        let mut layout = AccessMap::default();
+        let mut start = 0;
+        for run in perms.chunk_by(|a, b| a.implied() == b.implied()) {
+            let end = start + run.len() * page;
+            layout.insert(start..end, run[0].implied());
+            start = end;
+        }
+        let default = Access::READ | Access::WRITE;
+        for (region, allow) in self.protection.diff(&layout, self.size, default) {
+            self.set_protection(region, allow, Caching::Default)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the memory protection that the operating system reports
+    /// for the page containing the byte at `offset`.
+    /// Unlike [`regions`](View::regions), this doesn't rely on the
+    /// permissions recorded by the view, and unlike accessing the memory,
+    /// it never faults.
+    /// Returns an `InvalidInput` error if the offset is out of bounds.
+    pub fn query_protection(&self, offset: usize) -> std::io::Result<Access> {
+        if offset >= self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        self.query_protection_impl(offset)
+    }
+
+    /// Returns the memory protection in effect for the bytes of `region`,
+    /// as reported by the operating system, e.g., to assert that a region
+    /// of code is never writable.
+    /// The region doesn't need to be page-aligned.
+    ///
+    /// The access is the one passed to [`protect`](View::protect), together
+    /// with the access it implies: write and execute access imply read.
+    /// On Linux this is read from `/proc/self/maps`, on Windows with
+    /// `VirtualQuery`, with `mach_vm_region` on macOS, and with
+    /// `kinfo_getvmmap` on FreeBSD.
+    ///
+    /// Returns an `InvalidInput` error if the region is empty or out of
+    /// bounds, or if its pages don't all have the same protection.
+    pub fn current_access(&self, region: impl RangeBounds<usize>) -> std::io::Result<Access> {
+        let Range { start, end } = to_range(region, self.size)?;
+        if end <= start || end > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        // every change of protection goes through the view, so the pages
+        // of a run recorded by the view have the same protection
+        let mut current = None;
+        for (run, _) in self.regions() {
+            if run.end <= start || end <= run.start {
+                continue;
+            }
+            let access = self.query_protection(run.start.max(start))?;
+            if current.is_some_and(|current| current != access) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "The pages of the region have different protection",
+                ));
+            }
+            current = Some(access);
+        }
+        Ok(current.unwrap())
+    }
+
+    /// Returns an iterator over the memory protection of this view, as
+    /// coalesced runs of bytes with the same access permissions set with
+    /// [`protect`](View::protect).
+    /// The runs cover the whole view, in increasing order of offset.
+    /// Bytes that were never protected have the view's default access,
+    /// which is read and write access.
+    pub fn regions(&self) -> impl Iterator<Item = (Range<usize>, Access)> + '_ {
+        self.protection
+            .regions(self.size, Access::READ | Access::WRITE)
+    }
+
+    /// Record advisory access permissions for a byte range of this view.
+    /// Unlike [`protect`](View::protect), the range doesn't need to be
+    /// page-aligned, and the permissions are not enforced by the MMU:
+    /// they are only bookkeeping that instrumented code can consult with
+    /// [`check_access`](View::check_access) before accessing the view.
+    /// Later calls override the permissions recorded by earlier calls for
+    /// the overlapping bytes.
+    /// Bytes without recorded permissions allow any access.
+    pub fn logical_protect(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        allow: Access,
+    ) -> std::io::Result<()> {
+        let Range { start, end } = to_range(region, self.size)?;
+
+        if end <= start || end > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+
+        self.logical.insert(start..end, allow);
+        Ok(())
+    }
 
-        self.protect_impl(start..end, allow)
+    /// Returns `true` if the advisory permissions recorded with
+    /// [`logical_protect`](View::logical_protect) allow an access of
+    /// type `kind` to the byte at `offset`.
+    /// As with [`protect`](View::protect), write and execute access
+    /// imply read access.
+    /// Offsets outside of the view never allow any access.
+    pub fn check_access(&self, offset: usize, kind: Access) -> bool {
+        if offset >= self.size {
+            return false;
+        }
+        match self.logical.get(offset) {
+            Some(allow) => allow.implied().contains(kind),
+            None => true,
+        }
     }
 
     /// Discard any changes made to this copy-on-write view, restoring
     /// it to the original content of the root snapshot.
-    /// Restoring a view also reverts any memory protection applied to the view.
+    /// Restoring a view also reverts any memory protection applied to the view,
+    /// including the advisory permissions recorded with `logical_protect`.
     /// Restoring a view does not change its address.
+    ///
+    /// On Windows, if the view can't be remapped, an error is returned and
+    /// the view is left empty, rather than moving it to a different address.
+    ///
+    /// On Linux only the [`dirty_pages`](View::dirty_pages) of the view are
+    /// remapped, so the pages that were only read don't need to be faulted
+    /// in again.
     pub fn restore(&mut self) -> std::io::Result<()> {
-        if self.mode == ViewMode::Mutable {
+        if self.mode == ViewMode::Mutable || self.write_back {
             // For mutable views, restoring is a no-op since they always
             // reflect the root snapshot.
             return Ok(());
         }
-        self.restore_impl()
+        if self.borrowed {
+            self.restore_subview()?;
+        } else {
+            self.restore_whole()?;
+        }
+        self.remap_layers(0..self.size)?;
+        self.logical.clear();
+        #[cfg(feature = "protection-history")]
+        self.record_protection(0..self.size, Access::READ | Access::WRITE);
+        self.protection.clear();
+        self.caching.clear();
+        Ok(())
+    }
+
+    /// Restore this view, like [`restore`](View::restore), only if the
+    /// content of its `check` region is still the content of the root
+    /// snapshot there, and return whether the view was restored.
+    /// This is useful to detect interference, e.g., by checking a sentinel
+    /// region before reverting the view.
+    ///
+    /// When the region has no [dirty pages](View::dirty_pages), it's known
+    /// to be unchanged without comparing it. Otherwise the region is
+    /// compared with the backing of the root snapshot, so it must be
+    /// readable.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn restore_if_unchanged(
+        &mut self,
+        check: impl RangeBounds<usize>,
+    ) -> std::io::Result<bool> {
+        let Range { start, end } = to_range(check, self.len)?;
+        if start > end || end > self.len {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        let dirty = self
+            .dirty_pages()?
+            .into_iter()
+            .any(|pages| pages.start < end && start < pages.end);
+        if dirty || self.write_back {
+            let root = View::new((), self.fd, self.offset + self.size, ViewMode::Cow)?;
+            let root = &root.as_full_slice()[self.offset + start..self.offset + end];
+            if root != &self.as_full_slice()[start..end] {
+                return Ok(false);
+            }
+        }
+        self.restore()?;
+        Ok(true)
+    }
+
+    /// Remap a view that owns its mapping.
+    fn restore_whole(&mut self) -> std::io::Result<()> {
+        // On Linux, remapping only the dirty pages avoids faulting in the
+        // rest of the view again. Pages mapped from other snapshots can't
+        // be told apart from clean pages, so those views are remapped whole.
+        let partial = cfg!(all(target_os = "linux", not(miri))) && !self.overlaid;
+        match partial.then(|| self.dirty_pages_impl()) {
+            Some(Ok(dirty)) => {
+                for region in dirty {
+                    self.restore_range_impl(region)?;
+                }
+                let default = Access::READ | Access::WRITE;
+                for (region, _, _) in self.protected_runs(0..self.size) {
+                    self.protect_impl(region, default, Caching::Default)?;
+                }
+            }
+            _ => {
+                self.restore_impl()?;
+                self.overlaid = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remap the region of a sub-view, without touching the rest of the
+    /// mapping it shares with other views.
+    fn restore_subview(&mut self) -> std::io::Result<()> {
+        let page = page_size();
+        if self.offset % page != 0 || self.size % page != 0 {
+            return Err(SnapshotError::NotPageAligned.into());
+        }
+        if self.size > 0 {
+            self.restore_range_impl(0..self.size)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the page-aligned regions of this copy-on-write view whose
+    /// content has diverged from the root snapshot, as coalesced ranges in
+    /// increasing order.
+    /// A page diverges when it's written to, even if the value written is
+    /// the value it already had, or when it's zeroed with
+    /// [`zero_fast`](View::zero_fast).
+    /// Restoring the view, or the region of a page, makes it clean again.
+    /// Mutable views always reflect the root snapshot, so they never have
+    /// dirty pages.
+    ///
+    /// On Linux this is backed by `/proc/self/pagemap`.
+    /// On Windows, pages whose protection was changed with
+    /// [`protect`](View::protect) can't be told apart, and are always
+    /// reported as dirty.
+    /// On other platforms every page is reported as dirty.
+    /// On Linux and macOS, once pages of other snapshots are mapped over
+    /// the view, or pages are zeroed with [`zero_fast`](View::zero_fast),
+    /// every page is reported as dirty until the whole view is restored.
+    pub fn dirty_pages(&self) -> std::io::Result<Vec<Range<usize>>> {
+        if self.mode == ViewMode::Mutable || self.write_back || self.size == 0 {
+            return Ok(Vec::new());
+        }
+        if self.overlaid && cfg!(any(target_os = "linux", target_os = "macos")) {
+            return Ok(std::iter::once(0..self.size).collect());
+        }
+        self.dirty_pages_impl()
+    }
+
+    /// Returns whether each page of a memory region of this view is
+    /// resident in RAM, e.g., to tell how much of the view was accessed.
+    /// The region doesn't need to be page-aligned, every page it overlaps
+    /// is reported, in order, so a region ending in the middle of a page
+    /// includes that page.
+    ///
+    /// This uses `mincore` on Linux and macOS, and `QueryWorkingSetEx` on
+    /// Windows. On Linux and macOS a page that was not accessed through
+    /// the view is still reported as resident if its content is in the
+    /// page cache, e.g., because it was accessed through another view of
+    /// the same snapshot. On Windows only the pages in the working set of
+    /// the process are reported as resident.
+    ///
+    /// Returns an `InvalidInput` error if the region is out of bounds.
+    pub fn residency(&self, region: impl RangeBounds<usize>) -> std::io::Result<Vec<bool>> {
+        let Range { start, end } = to_range(region, self.size)?;
+        if end < start || end > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        if start == end {
+            return Ok(Vec::new());
+        }
+        let page = page_size();
+        self.residency_impl(start / page * page..end.next_multiple_of(page))
+    }
+
+    /// Discard any changes made to a memory region of this copy-on-write
+    /// view, restoring it to the original content of the root snapshot,
+    /// like [`restore`](View::restore) does for the whole view.
+    /// Changes outside of the region are kept.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter.
+    /// Restoring a region also reverts any memory protection applied to it,
+    /// including the advisory permissions recorded with `logical_protect`.
+    ///
+    /// On Windows a mapped view can't be partially remapped, so the content
+    /// of the region is copied from the root snapshot instead.
+    pub fn restore_range(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        if self.mode == ViewMode::Mutable || self.write_back {
+            // As with restore, this is a no-op for mutable views.
+            return Ok(());
+        }
+        self.restore_range_impl(region.clone())?;
+        self.remap_layers(region.clone())?;
+        self.logical.remove(region.clone());
+        #[cfg(feature = "protection-history")]
+        self.record_protection(region.clone(), Access::READ | Access::WRITE);
+        self.caching.remove(region.clone());
+        self.protection.remove(region);
+        Ok(())
+    }
+
+    /// Copy the bytes of `src_range` of `src` into this view, starting at
+    /// `dst_offset`, and return the number of bytes copied.
+    ///
+    /// On Linux, when this is a mutable view, the range is page-aligned in
+    /// both snapshots, and the bytes of `src` are those of its root
+    /// snapshot (i.e., `src` is a mutable view, or a copy-on-write view
+    /// with no dirty pages in the range), the bytes are copied between the
+    /// backings of the snapshots by the kernel with `copy_file_range`.
+    /// Views with pages mapped over them, e.g. with
+    /// [`map_page_from`](MutView::map_page_from), are never copied by the
+    /// kernel, neither as source nor as destination.
+    /// Otherwise, or if the backings don't support it, they are copied
+    /// through memory.
+    ///
+    /// Returns an `InvalidInput` error if either range is out of bounds.
+    pub fn copy_from<T>(
+        &mut self,
+        src: &View<T>,
+        src_range: impl RangeBounds<usize>,
+        dst_offset: usize,
+    ) -> std::io::Result<usize> {
+        let Range { start, end } = to_range(src_range, src.len)?;
+        let len = end.wrapping_sub(start);
+        if end < start
+            || end > src.len
+            || dst_offset.checked_add(len).is_none_or(|end| end > self.len)
+        {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+
+        let page = page_size();
+        let (src_start, dst_start) = (src.offset + start, self.offset + dst_offset);
+        let aligned = src_start % page == 0 && dst_start % page == 0 && len % page == 0;
+        // the changes of a view that writes them back are not in its backing
+        // nor are the pages mapped over a view from other snapshots, which
+        // the kernel would neither read from `src` nor write to `self`
+        let clean = || {
+            !src.write_back
+                && !src.overlaid
+                && (src.mode == ViewMode::Mutable
+                    || src.dirty_pages().is_ok_and(|dirty| {
+                        dirty.iter().all(|run| run.end <= start || end <= run.start)
+                    }))
+        };
+        self.check_alias();
+        if self.mode == ViewMode::Mutable
+            && !self.overlaid
+            && aligned
+            && len > 0
+            && clean()
+            && r#impl::copy_range_impl(src.fd, src_start, self.fd, dst_start, len)?
+        {
+            return Ok(len);
+        }
+        self.as_mut_slice()[dst_offset..dst_offset + len].copy_from_slice(&src[start..end]);
+        Ok(len)
+    }
+}
+
+impl MutView<'_> {
+    /// Map the page at index `src_page` of `src` over the page at index
+    /// `page_index` of this view, as copy-on-write.
+    /// Reads from that page of the view see the content of `src`, and
+    /// writes to it are private to this view: neither `src` nor the root
+    /// snapshot of this view are modified by them.
+    /// The memory protection of the page is preserved.
+    ///
+    /// The page keeps mapping `src` until the view is dropped or restored, and
+    /// other views of the root snapshot don't see its content.
+    ///
+    /// Returns an `InvalidInput` error if either page index is out of
+    /// bounds of its snapshot.
+    /// This is only supported on Linux and macOS, an `Unsupported` error is
+    /// returned on other platforms.
+    pub fn map_page_from(
+        &mut self,
+        page_index: usize,
+        src: &Snapshot,
+        src_page: usize,
+    ) -> std::io::Result<()> {
+        let page = page_size();
+        if page_index >= self.size / page || src_page >= src.size / page {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+
+        self.check_alias();
+        let offset = page_index * page..(page_index + 1) * page;
+        self.overlay_impl(offset.clone(), src, src_page * page)?;
+        self.overlaid = true;
+        self.reapply_protection(offset)
+    }
+
+    /// Flush the writes to a memory region of this view to the backing of
+    /// the snapshot, and wait for them to complete, e.g., before handing
+    /// the backing to another process.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter, use `..` to flush the whole view.
+    ///
+    /// For snapshots created with [`from_file`](Snapshot::from_file), this
+    /// writes the modified pages to the file, and on Linux, macOS and
+    /// FreeBSD waits until they reach the disk. On Windows the pages are
+    /// written to the file, but they may not have reached the disk yet.
+    /// For other snapshots, whose backing is memory, writes to the view
+    /// are already visible in the backing, so this is mostly a barrier.
+    ///
+    /// This uses `msync` with `MS_SYNC` on Unix, and `FlushViewOfFile` on
+    /// Windows.
+    /// Returns an `Unsupported` error if the snapshot has
+    /// [checkpoints](Snapshot::checkpoint), since the writes to the view
+    /// are then only written back to the snapshot when it's dropped.
+    pub fn flush(&self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        if self.write_back {
+            return Err(std::io::Error::from(SnapshotError::Unsupported(
+                "Flushing a view of a snapshot with checkpoints is not supported",
+            )));
+        }
+        self.flush_impl(region)
+    }
+}
+
+impl<S> Drop for View<S> {
+    fn drop(&mut self) {
+        if let Some(mut journal) = self.journal.take() {
+            journal.commit(self);
+        }
+        if !self.borrowed {
+            self.unmap_impl();
+        }
+        for f in std::mem::take(&mut self.cleanup.0) {
+            f();
+        }
     }
 }
 
@@ -256,13 +2023,160 @@ impl<I: SliceIndex<[u8]>, S> IndexMut<I> for View<S> {
     }
 }
 
+impl<S> Borrow<[u8]> for View<S> {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<S> AsRef<[u8]> for View<S> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<S> AsMut<[u8]> for View<S> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// Views dereference to their content, so they can be used wherever a
+/// `[u8]` slice is expected.
+/// Mutating a copy-on-write view through [`DerefMut`] only changes the
+/// view, like [`as_mut_slice`](View::as_mut_slice).
+impl<S> Deref for View<S> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<S> DerefMut for View<S> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// Views are compared by content.
+impl<S, T> PartialEq<View<T>> for View<S> {
+    #[inline]
+    fn eq(&self, other: &View<T>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<S> PartialEq<[u8]> for View<S> {
+    #[inline]
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<S> Eq for View<S> {}
+
+/// Views are hashed by content, consistently with the hash of the
+/// equivalent `[u8]` slice, so that a view can be looked up by slice
+/// in a hashed collection.
+impl<S> Hash for View<S> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl ArcView {
+    /// Create a new view of the snapshot of this view, like [`Clone`],
+    /// but returning an error instead of panicking if the snapshot can't
+    /// be mapped.
+    pub fn try_clone_view(&self) -> std::io::Result<ArcView> {
+        self._snapshot.view_arc()
+    }
+}
+
+/// Cloning a view maps the snapshot again, like
+/// [`view_arc`](Snapshot::view_arc), rather than aliasing the mapping of
+/// the view, so the clone has a different address, its own copy-on-write
+/// pages, and none of the changes or memory protection of the view.
+/// This is convenient to create a scratch view for each task from a
+/// shared snapshot.
+impl Clone for ArcView {
+    /// Map the snapshot of this view again.
+    ///
+    /// # Panics
+    /// Panics if the snapshot can't be mapped, e.g., if the process runs
+    /// out of address space. Use [`try_clone_view`](ArcView::try_clone_view)
+    /// to handle the error instead.
+    fn clone(&self) -> Self {
+        self.try_clone_view().expect("failed to map the snapshot")
+    }
+}
+
+/// Snapshots are compared by content, not by identity, with
+/// [`Snapshot::content_eq`], which maps both snapshots and is O(size).
+///
+/// # Panics
+/// Panics if either snapshot can't be mapped.
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.content_eq(other)
+            .expect("failed to map the snapshots to compare them")
+    }
+}
+
+impl Eq for Snapshot {}
+
+/// Snapshots are hashed by content, not by identity, consistently with
+/// their [`PartialEq`] implementation, so that they can key a hashed
+/// collection, e.g., a cache of artifacts built from them.
+/// Hashing a snapshot maps it and hashes its whole content with
+/// [`View::content_hash`], so it's O(size).
+///
+/// # Panics
+/// Panics if the snapshot can't be mapped.
+impl Hash for Snapshot {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        if self.size == 0 {
+            // empty snapshots are equal without mapping them
+            return;
+        }
+        let view = self.view().expect("failed to map the snapshot to hash it");
+        view.content_hash().hash(state);
+    }
+}
+
+/// Creates a snapshot with [`Snapshot::from_slice`].
+impl TryFrom<&[u8]> for Snapshot {
+    type Error = std::io::Error;
+
+    fn try_from(buf: &[u8]) -> std::io::Result<Self> {
+        Self::from_slice(buf)
+    }
+}
+
+/// Creates a snapshot with [`Snapshot::from_file`].
+impl TryFrom<std::fs::File> for Snapshot {
+    type Error = std::io::Error;
+
+    fn try_from(file: std::fs::File) -> std::io::Result<Self> {
+        Self::from_file(file)
+    }
+}
+
 bitflags! {
     /// Access permissions for a memory region.
     /// These flags can be used to control the type of access allowed
     /// to regions of a view with the [`protect`](View::protect) method.
     /// The flags can be combined using bitwise OR.
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub(crate) struct Access: u8 {
+    pub struct Access: u8 {
         /// No access is allowed to the memory region.
         const NONE = 0x00;
 
@@ -277,14 +2191,245 @@ bitflags! {
     }
 }
 
+/// The memory protection layout of a whole view, captured with
+/// [`capture_protections`](View::capture_protections).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectionSnapshot {
+    size: usize,
+    protection: AccessMap,
+}
+
+/// The maximum number of protection changes recorded for a view.
+#[cfg(feature = "protection-history")]
+const PROTECTION_HISTORY_LEN: usize = 1024;
+
+/// A change to the memory protection of a region of a view, as returned
+/// by [`protection_history`](View::protection_history).
+#[cfg(feature = "protection-history")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectionEvent {
+    /// The range of bytes of the view whose protection changed.
+    pub range: Range<usize>,
+    /// The access permissions of the range before the change.
+    pub old: Access,
+    /// The access permissions of the range after the change.
+    pub new: Access,
+    /// When the change happened.
+    pub time: std::time::Instant,
+}
+
+/// Caching attributes for a memory region.
+/// These can be set on regions of a view with the
+/// [`protect_with_caching`](View::protect_with_caching) method.
+///
+/// On Windows these map to the `PAGE_NOCACHE` and `PAGE_WRITECOMBINE`
+/// protection modifiers.
+/// On Linux there is no way to change the caching attributes of a
+/// memory mapped file from userspace, and the attributes are ignored.
+/// Caching attributes are also ignored for regions without access.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Caching {
+    /// Use the default caching behavior of the system.
+    #[default]
+    Default,
+
+    /// Do not cache accesses to the memory region.
+    Uncached,
+
+    /// Combine writes to the memory region before they reach memory.
+    /// Useful for memory that is only written in large sequential bursts,
+    /// like framebuffers.
+    WriteCombined,
+}
+
+impl Access {
+    /// Returns these permissions including the permissions they imply,
+    /// i.e., write or execute access imply read access.
+    fn implied(self) -> Access {
+        if self.intersects(Access::WRITE | Access::EXEC) {
+            self | Access::READ
+        } else {
+            self
+        }
+    }
+}
+
 /// Returns the system page size in bytes.
 /// This is the granularity at which memory allocation is done on the system.
 pub fn page_size() -> usize {
+    #[cfg(test)]
+    if let Some(size) = PAGE_SIZE_OVERRIDE.get() {
+        return size;
+    }
     page_size::get()
 }
 
-fn effective_size(size: usize) -> usize {
-    size.max(page_size::get())
+#[cfg(test)]
+thread_local! {
+    /// Overrides the page size returned by [`page_size()`] in the current
+    /// thread, to test the alignment policy with other page sizes.
+    static PAGE_SIZE_OVERRIDE: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+/// Returns the number of bytes mapped for the content of a snapshot of
+/// `size` bytes.
+/// Snapshots are a whole number of pages, so this is `size` rounded up
+/// to the next multiple of the [page size](page_size()), the size of the
+/// snapshot. Empty snapshots still map a page, so that their views have
+/// a valid address, but the page is not part of the view.
+pub fn effective_size(size: usize) -> usize {
+    let page = page_size();
+    size.next_multiple_of(page).max(page)
+}
+
+/// Create a new file with a unique name in `dir`, returning it and its path.
+fn create_temp_file(
+    dir: &Path,
+    options: &std::fs::OpenOptions,
+) -> std::io::Result<(std::fs::File, std::path::PathBuf)> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    loop {
+        let name = format!(
+            ".memsnap.{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        match options.clone().create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns `true` if all the bytes in `buf` are zero.
+fn is_zero(buf: &[u8]) -> bool {
+    // SAFETY: any bit pattern is a valid u128
+    let (prefix, words, suffix) = unsafe { buf.align_to::<u128>() };
+    prefix.iter().all(|&b| b == 0)
+        && words.iter().all(|&w| w == 0)
+        && suffix.iter().all(|&b| b == 0)
+}
+
+/// Copy `src` into the beginning of `dst`, skipping the pages of `src`
+/// that are entirely zero.
+/// Those pages in `dst` are expected to already be zero.
+fn copy_sparse(dst: &mut [u8], src: &[u8]) {
+    let pages = dst.chunks_mut(page_size());
+    for (dst, src) in pages.zip(src.chunks(page_size())) {
+        if !is_zero(src) {
+            dst[..src.len()].copy_from_slice(src);
+        }
+    }
+}
+
+/// Fill `buf` with `value` using non-temporal stores where supported, and
+/// if `buf` is large enough for them to pay off.
+fn fill_streaming(buf: &mut [u8], value: u8) {
+    #[cfg(all(
+        any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse2")
+        ),
+        not(miri)
+    ))]
+    {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::{__m128i, _mm_set1_epi8, _mm_sfence, _mm_stream_si128};
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::{__m128i, _mm_set1_epi8, _mm_sfence, _mm_stream_si128};
+
+        // smaller fills fit in the cache, and the fence after the stores
+        // costs more than they save
+        const MIN_LEN: usize = 256 * 1024;
+
+        if buf.len() >= MIN_LEN {
+            // SAFETY: any bit pattern is a valid `__m128i`, and SSE2 is
+            // available on every x86_64 target, and on x86 with `sse2`
+            let (prefix, chunks, suffix) = unsafe { buf.align_to_mut::<__m128i>() };
+            prefix.fill(value);
+            suffix.fill(value);
+            unsafe {
+                let pattern = _mm_set1_epi8(value as i8);
+                for chunk in chunks {
+                    _mm_stream_si128(chunk, pattern);
+                }
+                // non-temporal stores are weakly ordered, make them visible
+                // before any later store
+                _mm_sfence();
+            }
+            return;
+        }
+    }
+    buf.fill(value);
+}
+
+/// Returns the runs of `region` of a view of length `len` with the same
+/// access permissions in `protection` and caching attributes in `caching`,
+/// covering the whole region.
+fn protection_runs(
+    protection: &AccessMap,
+    caching: &AccessMap<Caching>,
+    len: usize,
+    region: Range<usize>,
+) -> Vec<(Range<usize>, Access, Caching)> {
+    let mut caching = caching.regions(len, Caching::Default).peekable();
+    let mut runs = Vec::new();
+    for (run, allow) in protection.regions(len, Access::READ | Access::WRITE) {
+        let mut start = run.start;
+        while start < run.end {
+            let (cur, mode) = caching.peek().cloned().unwrap();
+            let end = cur.end.min(run.end);
+            if start < region.end && region.start < end {
+                runs.push((start.max(region.start)..end.min(region.end), allow, mode));
+            }
+            if cur.end <= run.end {
+                caching.next();
+            }
+            start = end;
+        }
+    }
+    runs
+}
+
+/// Resolve `region` into a range of a view of length `len`, validating
+/// that it's non-empty, within bounds, and page-aligned.
+fn protection_range(region: impl RangeBounds<usize>, len: usize) -> std::io::Result<Range<usize>> {
+    let Range { start, end } = to_range(region, len)?;
+
+    if end <= start || end > len {
+        return Err(SnapshotError::RangeOutOfBounds.into());
+    }
+
+    if start != start.next_multiple_of(page_size()) || end != end.next_multiple_of(page_size()) {
+        return Err(SnapshotError::NotPageAligned.into());
+    }
+
+    Ok(start..end)
+}
+
+/// Resolve `region` into a range of a view of length `len`, without
+/// validating it against `len`.
+/// Returns a `SliceOutOfBounds` error if the region extends past
+/// `usize::MAX`, e.g., `..=usize::MAX`.
+fn to_range(region: impl RangeBounds<usize>, len: usize) -> Result<Range<usize>, SnapshotError> {
+    let overflow = |start| SnapshotError::SliceOutOfBounds {
+        range: start..usize::MAX,
+        len,
+    };
+    let start = match region.start_bound() {
+        Bound::Included(&s) => s,
+        Bound::Excluded(&s) => s.checked_add(1).ok_or_else(|| overflow(s))?,
+        Bound::Unbounded => 0,
+    };
+    let end = match region.end_bound() {
+        Bound::Included(&s) => s.checked_add(1).ok_or_else(|| overflow(start))?,
+        Bound::Excluded(&s) => s,
+        Bound::Unbounded => len,
+    };
+    Ok(start..end)
 }
 
 #[cfg(test)]
@@ -0,0 +1,110 @@
+use std::ops::{Deref, Range, RangeBounds};
+
+use super::{protection_range, Access, View};
+
+/// A guard that keeps a memory region of a [`View`] locked in RAM while
+/// it's alive, created with [`lock_scoped`](View::lock_scoped).
+///
+/// When the guard is dropped, the region is unlocked.
+/// The guard dereferences to its view, so the view can still be read
+/// while the guard is alive.
+#[derive(Debug)]
+pub struct LockGuard<'a, S> {
+    view: &'a View<S>,
+    region: Range<usize>,
+}
+
+impl<S> View<S> {
+    /// Lock a memory region of this view in RAM, so that accessing it
+    /// never causes a page fault that needs to read from disk or swap.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter.
+    ///
+    /// This uses `mlock` on Linux and macOS, and `VirtualLock` on Windows.
+    /// The amount of memory a process can lock is limited, by
+    /// `RLIMIT_MEMLOCK` on Linux and macOS, and by the working set size of
+    /// the process on Windows. Exceeding the limit returns an error.
+    ///
+    /// Locks don't nest, a single [`unlock`](View::unlock) unlocks the
+    /// region however many times it was locked.
+    /// Remapping a region of the view, e.g., with [`restore`](View::restore),
+    /// also unlocks it.
+    pub fn lock(&self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        self.lock_impl(region)
+    }
+
+    /// Unlock a memory region of this view previously locked with
+    /// [`lock`](View::lock), so that it can be paged out again.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter.
+    pub fn unlock(&self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        self.unlock_impl(region)
+    }
+
+    /// Populate a memory region of this view for writing and lock it in
+    /// RAM, so that accessing it never causes a page fault, e.g., before
+    /// a critical section that must run in a deterministic time.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter, and the region must be writable.
+    ///
+    /// For copy-on-write views this gives every page of the region a
+    /// private copy, without changing its content.
+    /// This uses `MADV_POPULATE_WRITE` on Linux, where available, and
+    /// otherwise writes to every page the value it already has. Then the
+    /// region is locked like with [`lock`](View::lock), so calling this
+    /// again has no further effect.
+    ///
+    /// Returns a `PermissionDenied` error if the region is not writable.
+    pub fn commit(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        let writable = self
+            .protection
+            .regions(self.size, Access::READ | Access::WRITE)
+            .filter(|(range, _)| range.start < region.end && region.start < range.end)
+            .all(|(_, allow)| allow.contains(Access::READ | Access::WRITE));
+        if !writable {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "The region to commit must be readable and writable",
+            ));
+        }
+        if !self.populate_write_impl(region.clone())? {
+            for offset in region.clone().step_by(super::page_size()) {
+                // SAFETY: the region is in bounds and writable
+                unsafe {
+                    let ptr = self.ptr.add(offset);
+                    ptr.write_volatile(ptr.read_volatile());
+                }
+            }
+        }
+        self.lock_impl(region)
+    }
+
+    /// Lock a memory region of this view in RAM, like
+    /// [`lock`](View::lock), until the returned guard is dropped.
+    /// Errors unlocking the region when the guard is dropped are ignored.
+    pub fn lock_scoped(
+        &self,
+        region: impl RangeBounds<usize>,
+    ) -> std::io::Result<LockGuard<'_, S>> {
+        let region = protection_range(region, self.size)?;
+        self.lock_impl(region.clone())?;
+        Ok(LockGuard { view: self, region })
+    }
+}
+
+impl<S> Deref for LockGuard<'_, S> {
+    type Target = View<S>;
+
+    fn deref(&self) -> &View<S> {
+        self.view
+    }
+}
+
+impl<S> Drop for LockGuard<'_, S> {
+    fn drop(&mut self) {
+        let _ = self.view.unlock_impl(self.region.clone());
+    }
+}
@@ -0,0 +1,157 @@
+use std::ffi::CString;
+use std::ops::{Deref, DerefMut};
+use std::os::fd::FromRawFd as _;
+
+use super::Snapshot;
+
+/// A snapshot backed by a named shared memory segment, created with
+/// `shm_open`, that other processes can open by its name.
+///
+/// Dropping a named snapshot never destroys the memory of the segment
+/// while it's still mapped or open, neither in this process nor in
+/// others. What [`unlink_on_drop`](NamedSnapshot::set_unlink_on_drop)
+/// controls is whether the name is removed, with `shm_unlink`, when the
+/// named snapshot is dropped:
+/// * If the name is kept, which is the default, the segment and its
+///   content outlive every process that has it open, until some process
+///   unlinks it. Forgetting to unlink it leaks the memory of the segment
+///   until the system is restarted.
+/// * If the name is removed, processes that already opened the segment
+///   keep using it, but it can no longer be opened by its name, and its
+///   memory is released once the last process closes it.
+///
+/// Only one of the processes sharing a segment should unlink it, usually
+/// the last one to need it opened by name.
+///
+/// The wrapper dereferences to its snapshot, so views of the snapshot
+/// can be created as usual. Mutable views see the writes of the other
+/// processes, while copy-on-write views only see them in the pages they
+/// haven't written to.
+///
+/// This is only available on Unix.
+#[derive(Debug)]
+pub struct NamedSnapshot {
+    snapshot: Snapshot,
+    name: CString,
+    unlink_on_drop: bool,
+}
+
+impl NamedSnapshot {
+    /// Create a new named shared memory segment with zeroed content of the
+    /// given size.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    ///
+    /// The name should start with a `/` and contain no other `/`.
+    /// Returns an `AlreadyExists` error if a segment with that name exists.
+    pub fn create(name: &str, size: usize) -> std::io::Result<Self> {
+        let name = shm_name(name)?;
+        // `shm_open` is variadic on Apple targets, where the mode is promoted
+        #[cfg(target_vendor = "apple")]
+        let mode = 0o600 as libc::c_uint;
+        #[cfg(not(target_vendor = "apple"))]
+        let mode = 0o600 as libc::mode_t;
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                mode,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        let page = super::page_size();
+        let size = size.next_multiple_of(page).max(page);
+        if let Err(err) = file.set_len(size as u64) {
+            unsafe { libc::shm_unlink(name.as_ptr()) };
+            return Err(err);
+        }
+        Self::from_parts(file, name)
+    }
+
+    /// Open an existing named shared memory segment, created with
+    /// [`create`](NamedSnapshot::create), possibly by another process.
+    ///
+    /// Returns a `NotFound` error if there is no segment with that name.
+    pub fn open(name: &str) -> std::io::Result<Self> {
+        let name = shm_name(name)?;
+        let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR, 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Self::from_parts(file, name)
+    }
+
+    fn from_parts(file: std::fs::File, name: CString) -> std::io::Result<Self> {
+        Ok(Self {
+            snapshot: Snapshot::from_file(file)?,
+            name,
+            unlink_on_drop: false,
+        })
+    }
+
+    /// Returns the name of the shared memory segment.
+    pub fn name(&self) -> &str {
+        // the name was created from a `&str`
+        self.name.to_str().unwrap()
+    }
+
+    /// Set whether the name of the segment is removed when this snapshot
+    /// is dropped. See [`NamedSnapshot`] for the semantics.
+    /// The default is to keep it.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        self.unlink_on_drop = unlink;
+    }
+
+    /// Returns whether the name of the segment is removed when this
+    /// snapshot is dropped.
+    pub fn unlink_on_drop(&self) -> bool {
+        self.unlink_on_drop
+    }
+
+    /// Take the snapshot out of the wrapper.
+    /// The name of the segment is kept, regardless of
+    /// [`unlink_on_drop`](NamedSnapshot::unlink_on_drop).
+    pub fn into_inner(self) -> Snapshot {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        // SAFETY: the wrapper is not dropped, so each field is taken once
+        unsafe {
+            std::ptr::drop_in_place(&mut this.name);
+            std::ptr::read(&this.snapshot)
+        }
+    }
+}
+
+fn shm_name(name: &str) -> std::io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Shared memory names can't contain nul bytes",
+        )
+    })
+}
+
+impl Deref for NamedSnapshot {
+    type Target = Snapshot;
+
+    fn deref(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+impl DerefMut for NamedSnapshot {
+    fn deref_mut(&mut self) -> &mut Snapshot {
+        &mut self.snapshot
+    }
+}
+
+impl Drop for NamedSnapshot {
+    fn drop(&mut self) {
+        if self.unlink_on_drop {
+            // another process may have unlinked it already
+            unsafe { libc::shm_unlink(self.name.as_ptr()) };
+        }
+    }
+}
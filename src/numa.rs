@@ -0,0 +1,66 @@
+use std::ops::RangeBounds;
+
+use super::{protection_range, SnapshotError, View};
+
+impl<S> View<S> {
+    /// Bind the memory of a region of this view to the NUMA node `node`,
+    /// so that the pages backing it are allocated on that node.
+    /// The same restrictions as for [`protect`](View::protect) apply to the
+    /// `region` parameter, use `..` to bind the whole view.
+    ///
+    /// The binding only affects pages allocated after it, and views are
+    /// mapped lazily, so the region should be bound before it's first
+    /// accessed. Pages that are already resident are not moved.
+    /// For copy-on-write views, pages that were not written are shared
+    /// with the root snapshot, and are allocated according to the policy
+    /// of the snapshot rather than of the view.
+    /// Remapping a region of the view, e.g., with [`restore`](View::restore),
+    /// removes the binding.
+    ///
+    /// This uses `mbind` with `MPOL_BIND` on Linux. An `Unsupported` error
+    /// is returned on other platforms, or if the kernel was built without
+    /// NUMA support, and an `InvalidInput` error if `node` is not a node
+    /// of the system.
+    pub fn bind_numa(&self, region: impl RangeBounds<usize>, node: u32) -> std::io::Result<()> {
+        let region = protection_range(region, self.size)?;
+        #[cfg(all(target_os = "linux", not(miri)))]
+        {
+            const MPOL_BIND: libc::c_int = 2;
+
+            let bits = libc::c_ulong::BITS as usize;
+            let node = node as usize;
+            let mut nodemask = vec![0 as libc::c_ulong; node / bits + 1];
+            nodemask[node / bits] |= 1 << (node % bits);
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_mbind,
+                    self.ptr.add(region.start),
+                    region.len(),
+                    MPOL_BIND,
+                    nodemask.as_ptr(),
+                    // the kernel ignores the last bit of the mask
+                    nodemask.len() * bits + 1,
+                    0,
+                )
+            };
+            if res < 0 {
+                let err = std::io::Error::last_os_error();
+                return Err(match err.raw_os_error() {
+                    Some(libc::ENOSYS) => std::io::Error::from(SnapshotError::Unsupported(
+                        "NUMA binding is not supported by the kernel",
+                    )),
+                    Some(libc::EINVAL) => std::io::Error::from(SnapshotError::RangeOutOfBounds),
+                    _ => err,
+                });
+            }
+            Ok(())
+        }
+        #[cfg(not(all(target_os = "linux", not(miri))))]
+        {
+            let _ = (region, node);
+            Err(std::io::Error::from(SnapshotError::Unsupported(
+                "NUMA binding is not supported on this platform",
+            )))
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-thread memory protection using memory protection keys (MPK).
+//!
+//! Memory protection keys let a process tag pages with a key, and let each
+//! thread restrict its own access to the pages tagged with each key by
+//! writing to its PKRU register, without any system call.
+//! This allows several threads to have different access to the same
+//! shared mapping, which is impossible with `mprotect` alone since page
+//! protection is process-wide.
+//!
+//! This requires Linux 4.9 or later, and an x86_64 CPU with protection
+//! keys support (`pku` and `ospke` in `/proc/cpuinfo`).
+
+use std::arch::asm;
+use std::ops::RangeBounds;
+
+use super::{protection_range, Access, Caching, View};
+
+/// A memory protection key, that can be assigned to regions of views and
+/// whose access can be set independently by each thread.
+///
+/// The access a thread has to a region is the intersection of the
+/// protection of the region, set with [`protect`](View::protect), and the
+/// access the thread set for the key of the region with
+/// [`set_thread_access`](ThreadLocalProtection::set_thread_access).
+/// Protection keys don't restrict execute access.
+///
+/// The access of each thread is stored in its PKRU register, which the
+/// kernel saves and restores on context switches, so it's preserved while
+/// the thread is scheduled out.
+/// New threads inherit the access of the thread that creates them, and
+/// signal handlers run with the default access of the process, which on
+/// Linux allows no access to pages with a key other than the default one.
+/// Leaving a signal handler without returning from it, e.g., to recover
+/// from a fault, keeps the access of the signal handler.
+/// Threads that didn't create the key may also start with no access to
+/// it, so every thread should set its access before touching the region.
+///
+/// Remapping a region of a view, e.g., with [`restore`](View::restore),
+/// removes the key from the region. Dropping the key doesn't, and the key
+/// may then be allocated again, so it should outlive the views using it.
+///
+/// This is only available on Linux on x86_64.
+#[derive(Debug)]
+pub struct ThreadLocalProtection {
+    key: libc::c_int,
+}
+
+impl ThreadLocalProtection {
+    /// Allocate a new protection key.
+    /// The calling thread starts with read and write access to the key.
+    /// Returns an error if the system doesn't support protection keys, or
+    /// if all the keys are already in use.
+    pub fn new() -> std::io::Result<Self> {
+        let key = unsafe { libc::syscall(libc::SYS_pkey_alloc, 0, 0) };
+        if key < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { key: key as _ })
+    }
+
+    /// Protect a memory region of a view like [`protect`](View::protect),
+    /// and assign this key to it.
+    /// The range must be page-aligned and within the bounds of the view.
+    pub fn assign<S>(
+        &self,
+        view: &mut View<S>,
+        region: impl RangeBounds<usize>,
+        allow: Access,
+    ) -> std::io::Result<()> {
+        let region = protection_range(region, view.size)?;
+        view.set_protection(region.clone(), allow, Caching::Default)?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_pkey_mprotect,
+                view.ptr.add(region.start),
+                region.len(),
+                allow.as_posix(),
+                self.key,
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Set the access of the calling thread to the regions with this key.
+    /// Write access implies read access, and execute access is ignored.
+    /// Other threads are not affected.
+    pub fn set_thread_access(&self, access: Access) {
+        let shift = 2 * self.key as u32;
+        let access = access.implied();
+        let bits = if access.contains(Access::WRITE) {
+            0
+        } else if access.contains(Access::READ) {
+            PKEY_DISABLE_WRITE
+        } else {
+            PKEY_DISABLE_ACCESS
+        };
+        let pkru = rdpkru() & !(PKEY_DISABLE_MASK << shift);
+        wrpkru(pkru | (bits << shift));
+    }
+
+    /// Returns the access of the calling thread to the regions with this key.
+    pub fn thread_access(&self) -> Access {
+        let bits = (rdpkru() >> (2 * self.key as u32)) & PKEY_DISABLE_MASK;
+        if bits & PKEY_DISABLE_ACCESS != 0 {
+            Access::NONE
+        } else if bits & PKEY_DISABLE_WRITE != 0 {
+            Access::READ
+        } else {
+            Access::READ | Access::WRITE
+        }
+    }
+}
+
+impl Drop for ThreadLocalProtection {
+    fn drop(&mut self) {
+        unsafe {
+            libc::syscall(libc::SYS_pkey_free, self.key);
+        }
+    }
+}
+
+const PKEY_DISABLE_ACCESS: u32 = 0x1;
+const PKEY_DISABLE_WRITE: u32 = 0x2;
+const PKEY_DISABLE_MASK: u32 = PKEY_DISABLE_ACCESS | PKEY_DISABLE_WRITE;
+
+fn rdpkru() -> u32 {
+    let pkru: u32;
+    unsafe {
+        asm!("rdpkru", in("ecx") 0, out("eax") pkru, out("edx") _, options(nomem, nostack));
+    }
+    pkru
+}
+
+fn wrpkru(pkru: u32) {
+    unsafe {
+        asm!("wrpkru", in("eax") pkru, in("ecx") 0, in("edx") 0, options(nostack));
+    }
+}
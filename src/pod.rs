@@ -0,0 +1,61 @@
+use super::{SnapshotError, View};
+
+/// Plain-old-data types that can be read from and written to a view as
+/// raw bytes, with [`read_at`](View::read_at) and
+/// [`write_at`](View::write_at).
+///
+/// This is implemented for the primitive integer and floating point
+/// types, and for arrays of them.
+///
+/// # Safety
+/// Every bit pattern of the size of the type must be a valid value of the
+/// type, and the type must not have any padding bytes, e.g., a
+/// `#[repr(C)]` struct of fields that implement `Pod` and need no padding.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+impl<S> View<S> {
+    /// Read a value of type `T` from the bytes of this view at `offset`.
+    /// The offset doesn't need to be aligned for `T`, since the bytes are
+    /// copied out of the view.
+    /// The value is read in native byte order, e.g., use [`u32::from_le`]
+    /// to read a little-endian `u32`.
+    ///
+    /// Returns an `InvalidInput` error if the value would extend past the
+    /// end of the view.
+    pub fn read_at<T: Pod>(&self, offset: usize) -> std::io::Result<T> {
+        let bytes = pod_range::<T>(offset, self.len())
+            .map(|range| &self.as_slice()[range])
+            .ok_or(SnapshotError::RangeOutOfBounds)?;
+        // SAFETY: `bytes` is as large as `T`, and any bytes are a valid `T`
+        Ok(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+    }
+
+    /// Write `value` to the bytes of this view at `offset`, like
+    /// [`read_at`](View::read_at) reads it.
+    ///
+    /// Returns an `InvalidInput` error if the value would extend past the
+    /// end of the view.
+    pub fn write_at<T: Pod>(&mut self, offset: usize, value: T) -> std::io::Result<()> {
+        let range = pod_range::<T>(offset, self.len()).ok_or(SnapshotError::RangeOutOfBounds)?;
+        let bytes = &mut self.as_mut_slice()[range];
+        // SAFETY: `bytes` is as large as `T`
+        unsafe { bytes.as_mut_ptr().cast::<T>().write_unaligned(value) };
+        Ok(())
+    }
+}
+
+/// Returns the range of the bytes of a `T` at `offset`, if it's within `len`.
+fn pod_range<T>(offset: usize, len: usize) -> Option<std::ops::Range<usize>> {
+    let end = offset.checked_add(size_of::<T>())?;
+    (end <= len).then_some(offset..end)
+}
@@ -0,0 +1,147 @@
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use super::Snapshot;
+
+/// A pool of zeroed snapshots of a fixed size, that recycles the backing
+/// of the snapshots dropped, to avoid creating and destroying backings in
+/// hot loops, e.g., when fuzzing.
+///
+/// The pool is cheap to clone, and the clones share the same snapshots.
+#[derive(Debug, Clone)]
+pub struct SnapshotPool {
+    inner: Arc<Pool>,
+}
+
+#[derive(Debug)]
+struct Pool {
+    size: usize,
+    max_idle: usize,
+    idle: Mutex<Vec<Snapshot>>,
+}
+
+/// A snapshot acquired from a [`SnapshotPool`], returned to the pool when
+/// it's dropped.
+///
+/// The wrapper dereferences to its snapshot, so views of the snapshot
+/// can be created as usual.
+#[derive(Debug)]
+pub struct PooledSnapshot {
+    snapshot: ManuallyDrop<Snapshot>,
+    pool: Arc<Pool>,
+}
+
+impl SnapshotPool {
+    /// Create a pool of snapshots of `size` bytes, that keeps at most
+    /// `max_idle` snapshots that are not in use.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    pub fn new(size: usize, max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(Pool {
+                size: size.next_multiple_of(super::page_size()),
+                max_idle,
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Returns a zeroed snapshot from the pool, or a new one if the pool
+    /// has no idle snapshots.
+    pub fn acquire(&self) -> std::io::Result<PooledSnapshot> {
+        let idle = self.inner.idle.lock().unwrap().pop();
+        let snapshot = match idle {
+            Some(snapshot) => snapshot,
+            None => Snapshot {
+                pooled: true,
+                ..Snapshot::zeroed(self.inner.size)?
+            },
+        };
+        Ok(PooledSnapshot {
+            snapshot: ManuallyDrop::new(snapshot),
+            pool: self.inner.clone(),
+        })
+    }
+
+    /// Returns the size of the snapshots of this pool.
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Returns the number of idle snapshots kept by this pool.
+    pub fn idle_count(&self) -> usize {
+        self.inner.idle.lock().unwrap().len()
+    }
+}
+
+impl Pool {
+    /// Zero `snapshot` and keep it for reuse, or drop it if it can't be
+    /// reused, or if the pool already keeps enough idle snapshots.
+    fn recycle(&self, mut snapshot: Snapshot) {
+        // snapshots replaced through `DerefMut`, which may be backed by a
+        // file of the user, resized snapshots, and snapshots sharing their
+        // backing with other snapshots, can't be reused
+        if !snapshot.pooled
+            || snapshot.size != self.size
+            || Arc::get_mut(&mut snapshot.file).is_none()
+        {
+            return;
+        }
+        #[cfg(feature = "tempfile")]
+        if snapshot.temp_path.is_some() {
+            return;
+        }
+        if self.idle.lock().unwrap().len() >= self.max_idle {
+            return;
+        }
+        snapshot.len = snapshot.size;
+        snapshot.checkpoints = None;
+        snapshot.metadata = None;
+        // on Linux this punches a hole in the backing rather than writing
+        // the zeros, which also frees the memory of the idle snapshot
+        let zeroed = snapshot.view_mut().and_then(|mut view| view.zero_fast(..));
+        if zeroed.is_err() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(snapshot);
+        }
+    }
+}
+
+impl PooledSnapshot {
+    /// Take the snapshot out of its pool, so that it's not returned to the
+    /// pool when it's dropped.
+    pub fn into_inner(self) -> Snapshot {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: the wrapper is not dropped, so the snapshot is taken once
+        // and the reference to the pool is dropped here
+        unsafe {
+            std::ptr::drop_in_place(&mut this.pool);
+            ManuallyDrop::take(&mut this.snapshot)
+        }
+    }
+}
+
+impl Deref for PooledSnapshot {
+    type Target = Snapshot;
+
+    fn deref(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+impl DerefMut for PooledSnapshot {
+    fn deref_mut(&mut self) -> &mut Snapshot {
+        &mut self.snapshot
+    }
+}
+
+impl Drop for PooledSnapshot {
+    fn drop(&mut self) {
+        // SAFETY: the snapshot is not used after this
+        let snapshot = unsafe { ManuallyDrop::take(&mut self.snapshot) };
+        self.pool.recycle(snapshot);
+    }
+}
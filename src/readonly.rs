@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::ops::{Deref, Index};
+use std::slice::SliceIndex;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{Access, ArcView, CowView, Snapshot};
+
+/// A read-only view into the content of a [`Snapshot`], created with
+/// [`view_readonly`](Snapshot::view_readonly).
+///
+/// The memory of the view is protected as read-only, so writing to it,
+/// e.g., through a raw pointer, raises a segmentation fault (an access
+/// violation on Windows) instead of silently changing a private copy of
+/// the page, as with a copy-on-write view.
+/// The view can't be written through this wrapper either.
+#[derive(Debug)]
+pub struct ReadOnlyView<'a> {
+    view: CowView<'a>,
+}
+
+impl Snapshot {
+    /// Create a read-only view into the content of this snapshot, for
+    /// consumers that must not modify it.
+    /// Unlike with [`view`](Snapshot::view), writes to the view are not
+    /// just kept private to it, they fault.
+    pub fn view_readonly(&self) -> std::io::Result<ReadOnlyView<'_>> {
+        let mut view = self.view()?;
+        if view.size > 0 {
+            view.protect(.., Access::READ)?;
+        }
+        Ok(ReadOnlyView { view })
+    }
+}
+
+impl ReadOnlyView<'_> {
+    /// Returns the content of the view as a slice.
+    pub fn as_slice(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+
+    /// Returns a pointer to the start of the view.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.view.as_ptr()
+    }
+}
+
+impl Deref for ReadOnlyView<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+}
+
+impl<I: SliceIndex<[u8]>> Index<I> for ReadOnlyView<'_> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.view.as_slice()[index]
+    }
+}
+
+impl AsRef<[u8]> for ReadOnlyView<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+}
+
+/// A read-only view into the content of a [`Snapshot`], created with
+/// [`shared_view`](Snapshot::shared_view), that shares its mapping with
+/// the other shared views of the same snapshot.
+///
+/// Like a [`ReadOnlyView`], writing to the memory of the view faults.
+/// Cloning a shared view is cheap, the clone aliases the same mapping,
+/// which is unmapped when the last shared view of the snapshot is dropped.
+#[derive(Debug, Clone)]
+pub struct SharedView {
+    view: Arc<ArcView>,
+}
+
+/// The mappings of the shared views of the live snapshots, by the address
+/// of the snapshot. A mapping holds a reference to its snapshot, so the
+/// address of a snapshot with a live mapping is never reused.
+static SHARED_MAPPINGS: Mutex<BTreeMap<usize, Weak<ArcView>>> = Mutex::new(BTreeMap::new());
+
+impl Snapshot {
+    /// Create a read-only view into the content of this snapshot, like
+    /// [`view_readonly`](Snapshot::view_readonly), that aliases the mapping
+    /// of the other shared views of this snapshot, if any.
+    /// Only the first shared view maps the snapshot, so this avoids
+    /// mapping the snapshot again for every reader, unlike
+    /// [`view_arc`](Snapshot::view_arc).
+    /// The mapping is unmapped when the last shared view is dropped, and
+    /// the next shared view maps the snapshot again.
+    pub fn shared_view(self: &Arc<Self>) -> std::io::Result<SharedView> {
+        // the mappings are not cached in the snapshot, which would make
+        // snapshots, and so views, interior mutable
+        let key = Arc::as_ptr(self) as usize;
+        let mut mappings = SHARED_MAPPINGS.lock().unwrap();
+        if let Some(view) = mappings.get(&key).and_then(Weak::upgrade) {
+            return Ok(SharedView { view });
+        }
+        let mut view = self.view_arc()?;
+        if view.size > 0 {
+            view.protect(.., Access::READ)?;
+        }
+        let view = Arc::new(view);
+        mappings.retain(|_, mapping| mapping.strong_count() > 0);
+        mappings.insert(key, Arc::downgrade(&view));
+        Ok(SharedView { view })
+    }
+}
+
+impl SharedView {
+    /// Returns the content of the view as a slice.
+    pub fn as_slice(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+
+    /// Returns a pointer to the start of the view, the same for every
+    /// shared view of the snapshot that is alive at the same time.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.view.as_ptr()
+    }
+}
+
+impl Deref for SharedView {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+}
+
+impl<I: SliceIndex<[u8]>> Index<I> for SharedView {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.view.as_slice()[index]
+    }
+}
+
+impl AsRef<[u8]> for SharedView {
+    fn as_ref(&self) -> &[u8] {
+        self.view.as_slice()
+    }
+}
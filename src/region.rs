@@ -0,0 +1,87 @@
+use std::ops::{Deref, Index, RangeBounds};
+use std::slice::SliceIndex;
+
+use super::{to_range, SnapshotError, View};
+
+/// A region of the content of a [`View`], created with
+/// [`region`](View::region), addressed from the start of the region.
+///
+/// This is a thin wrapper of a slice of the view that also remembers where
+/// the region starts in the view, so code parsing the region can use
+/// offsets relative to it, while still being able to report offsets in
+/// the view, e.g., in error messages.
+#[derive(Debug, Clone, Copy)]
+pub struct RegionRef<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<S> View<S> {
+    /// Returns the bytes of `range` of the view as a [`RegionRef`], whose
+    /// offsets are relative to the start of the range, so `region[0]` is
+    /// the byte at `range.start` of the view.
+    ///
+    /// Returns an `InvalidInput` error wrapping a
+    /// [`SnapshotError::SliceOutOfBounds`] if the range is out of bounds,
+    /// like [`slice`](View::slice).
+    pub fn region(&self, range: impl RangeBounds<usize>) -> std::io::Result<RegionRef<'_>> {
+        let range = self.slice_range(range)?;
+        Ok(RegionRef {
+            offset: range.start,
+            data: &self.as_slice()[range],
+        })
+    }
+}
+
+impl<'a> RegionRef<'a> {
+    /// Returns the offset of the start of the region in its view.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the content of the region as a slice, borrowed from the
+    /// view rather than from the region.
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the bytes of `range` of this region, relative to the start
+    /// of this region, as a region of the same view.
+    ///
+    /// Returns an `InvalidInput` error wrapping a
+    /// [`SnapshotError::SliceOutOfBounds`] with the range and the length
+    /// of this region if the range is out of bounds.
+    pub fn region(&self, range: impl RangeBounds<usize>) -> std::io::Result<RegionRef<'a>> {
+        let len = self.data.len();
+        let range = to_range(range, len)?;
+        if range.start > range.end || range.end > len {
+            return Err(SnapshotError::SliceOutOfBounds { range, len }.into());
+        }
+        Ok(RegionRef {
+            offset: self.offset + range.start,
+            data: &self.data[range],
+        })
+    }
+}
+
+impl Deref for RegionRef<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl<I: SliceIndex<[u8]>> Index<I> for RegionRef<'_> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.data[index]
+    }
+}
+
+impl AsRef<[u8]> for RegionRef<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.data
+    }
+}
@@ -0,0 +1,131 @@
+use std::ops::{Deref, DerefMut, RangeBounds};
+
+use super::{protection_range, protection_runs, Access, AccessMap, Caching, MutView, Snapshot};
+
+/// A long-lived editable [`Snapshot`] together with the memory protection
+/// of its mutable view.
+///
+/// Memory protection belongs to a [`View`](super::View), and is lost
+/// when the view is dropped.
+/// A session records the protection instead, and applies it to every
+/// mutable view created with [`view_mut`](SnapshotSession::view_mut).
+/// This allows the snapshot to be resized with
+/// [`grow`](SnapshotSession::grow), which requires that no views exist,
+/// without losing the protection.
+///
+/// Only the memory protection, including the caching attributes, is
+/// carried over. Mutable views write straight to the snapshot, so there
+/// are no [dirty pages](super::View::dirty_pages) to carry.
+#[derive(Debug)]
+pub struct SnapshotSession {
+    snapshot: Snapshot,
+    protection: AccessMap,
+    caching: AccessMap<Caching>,
+}
+
+/// A mutable view of the snapshot of a [`SnapshotSession`], created with
+/// [`view_mut`](SnapshotSession::view_mut).
+///
+/// The memory protection of the view when it's dropped, including the
+/// changes made directly on the view, becomes the protection of the
+/// session.
+/// The wrapper dereferences to its view.
+#[derive(Debug)]
+pub struct SessionView<'a> {
+    view: MutView<'a>,
+    protection: &'a mut AccessMap,
+    caching: &'a mut AccessMap<Caching>,
+}
+
+impl SnapshotSession {
+    /// Create a new session for the given snapshot, with no memory
+    /// protection applied.
+    pub fn new(snapshot: Snapshot) -> Self {
+        Self {
+            snapshot,
+            protection: AccessMap::default(),
+            caching: AccessMap::default(),
+        }
+    }
+
+    /// Returns the snapshot of this session.
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    /// Consumes the session and returns its snapshot.
+    pub fn into_snapshot(self) -> Snapshot {
+        self.snapshot
+    }
+
+    /// Record the access permissions of a memory region of the snapshot.
+    /// The permissions are applied to every view created with
+    /// [`view_mut`](SnapshotSession::view_mut).
+    /// The same restrictions as for [`View::protect`](super::View::protect)
+    /// apply to the `region` parameter.
+    pub fn protect(
+        &mut self,
+        region: impl RangeBounds<usize>,
+        allow: Access,
+    ) -> std::io::Result<()> {
+        let region = protection_range(region, self.snapshot.size)?;
+        self.caching.remove(region.clone());
+        self.protection.insert(region, allow.implied());
+        Ok(())
+    }
+
+    /// Create a mutable view into the content of the snapshot, with the
+    /// memory protection of the session applied to it.
+    /// The protection of the view is recorded back into the session when
+    /// the view is dropped.
+    pub fn view_mut(&mut self) -> std::io::Result<SessionView<'_>> {
+        let default = Access::READ | Access::WRITE;
+        let mut view = self.snapshot.view_mut()?;
+        let len = view.capacity();
+        for (region, allow, caching) in
+            protection_runs(&self.protection, &self.caching, len, 0..len)
+        {
+            if allow != default || caching != Caching::Default {
+                view.protect_with_caching(region, allow, caching)?;
+            }
+        }
+        Ok(SessionView {
+            view,
+            protection: &mut self.protection,
+            caching: &mut self.caching,
+        })
+    }
+
+    /// Grow the snapshot to at least `new_size` bytes, preserving its
+    /// content and the memory protection of the session.
+    /// The new bytes are zeroed, and the new pages have the default
+    /// read and write access.
+    /// Calling this method with a size smaller than the current size of
+    /// the snapshot has no effect.
+    ///
+    /// See [`Snapshot::grow`] for how the snapshot is grown.
+    pub fn grow(&mut self, new_size: usize) -> std::io::Result<()> {
+        self.snapshot.grow(new_size)
+    }
+}
+
+impl<'a> Deref for SessionView<'a> {
+    type Target = MutView<'a>;
+
+    fn deref(&self) -> &MutView<'a> {
+        &self.view
+    }
+}
+
+impl DerefMut for SessionView<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.view
+    }
+}
+
+impl Drop for SessionView<'_> {
+    fn drop(&mut self) {
+        *self.protection = self.view.protection.clone();
+        *self.caching = self.view.caching.clone();
+    }
+}
@@ -0,0 +1,128 @@
+//! Sharing the backing of a snapshot with other processes.
+//!
+//! This is not available under Miri, where the backing of a snapshot is a
+//! heap allocation.
+
+#[cfg(unix)]
+use std::os::fd::{AsFd as _, BorrowedFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsHandle as _, BorrowedHandle};
+use std::sync::Arc;
+
+use super::{OwnedFileDescriptor, RawFileDescriptor, Snapshot};
+
+impl Snapshot {
+    /// Returns the file descriptor of the backing of this snapshot, to
+    /// share it with another process.
+    ///
+    /// On Windows this is a `HANDLE` to a file mapping. On other platforms
+    /// it's the file the snapshot was created from, if any, and otherwise
+    /// a memfd on Linux, and an anonymous shared memory object on macOS
+    /// and FreeBSD.
+    /// It can be sent to another process with `SCM_RIGHTS` over a Unix
+    /// socket, or with `DuplicateHandle` on Windows, and turned back into a
+    /// snapshot there with [`from_shared_fd`](Snapshot::from_shared_fd).
+    ///
+    /// The file descriptor is owned by the snapshot, and is closed when the
+    /// snapshot and the snapshots sharing its backing are dropped.
+    /// Resizing the snapshot, or taking a mutable view of it while it
+    /// shares its backing with other snapshots, may give it a new backing.
+    pub fn as_raw_fd_shared(&self) -> RawFileDescriptor {
+        self.as_raw_fd()
+    }
+
+    /// Create a snapshot of `size` bytes from a file descriptor received
+    /// from another process, sharing the backing of the snapshot it came
+    /// from, see [`as_raw_fd_shared`](Snapshot::as_raw_fd_shared).
+    /// The actual snapshot size will be rounded up to the next system page size.
+    ///
+    /// Both snapshots share the same memory. Writes to a
+    /// [mutable view](Snapshot::view_mut) of either are visible to every
+    /// view of both, except in the pages those views have already written
+    /// to, while writes to a [copy-on-write view](Snapshot::view) stay
+    /// private to the view, as always.
+    /// Mutable views in different processes alias each other, and nothing
+    /// prevents them from writing to the same memory at the same time, so
+    /// the processes have to synchronize their accesses themselves.
+    ///
+    /// Returns an error in the same cases as [`from_raw`](Snapshot::from_raw).
+    pub fn from_shared_fd(fd: OwnedFileDescriptor, size: usize) -> std::io::Result<Self> {
+        Self::from_raw(fd, size)
+    }
+
+    /// Create a snapshot of `size` bytes backed by an existing file
+    /// descriptor, e.g., a memfd created by another library, taking
+    /// ownership of it.
+    /// The actual snapshot size will be rounded up to the next system page size.
+    ///
+    /// The content of the descriptor is not copied: views of the snapshot
+    /// map it directly, and writes to mutable views are written to it.
+    /// The descriptor is closed when the snapshot is dropped.
+    /// Unlike [`from_file`](Snapshot::from_file), the descriptor can be
+    /// larger than the snapshot, and only its first `size` bytes are used.
+    /// On Windows the descriptor must be a `HANDLE` to a file mapping, not
+    /// to a file.
+    ///
+    /// On Linux, macOS and FreeBSD, returns an `InvalidInput` error if the
+    /// descriptor is smaller than `size`. On Windows the size of the file
+    /// mapping can't be checked, and creating a view fails instead.
+    pub fn from_raw(fd: OwnedFileDescriptor, size: usize) -> std::io::Result<Self> {
+        let size = size.next_multiple_of(super::page_size());
+        #[cfg(unix)]
+        {
+            let file = std::fs::File::from(fd);
+            let len = file.metadata()?.len() as usize;
+            if len < size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Backing of {len} bytes is smaller than the snapshot size {size}"),
+                ));
+            }
+            Ok(Self::new(file.into(), size))
+        }
+        #[cfg(windows)]
+        {
+            Ok(Self::new(fd, size))
+        }
+    }
+
+    /// Borrow the file descriptor of the backing of this snapshot, e.g., to
+    /// pass it to other libraries for operations this crate doesn't
+    /// provide. See [`as_raw_fd_shared`](Snapshot::as_raw_fd_shared) for
+    /// what the descriptor is on each platform.
+    ///
+    /// The backing is at least as large as the snapshot, whose size is a
+    /// multiple of the system page size, except for snapshots created from
+    /// a file, whose backing is the file itself, and may end before the
+    /// last page of the snapshot. Writing to the backing changes the
+    /// content of the snapshot, and of the snapshots sharing it.
+    #[cfg(unix)]
+    pub fn backing(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+
+    /// Borrow the file descriptor of the backing of this snapshot, e.g., to
+    /// pass it to other libraries for operations this crate doesn't
+    /// provide. See [`as_raw_fd_shared`](Snapshot::as_raw_fd_shared) for
+    /// what the descriptor is on each platform.
+    ///
+    /// The file mapping is at least as large as the snapshot, whose size
+    /// is a multiple of the system page size. Writing to its views changes
+    /// the content of the snapshot, and of the snapshots sharing it.
+    #[cfg(windows)]
+    pub fn backing(&self) -> BorrowedHandle<'_> {
+        self.file.as_handle()
+    }
+
+    /// Consume this snapshot and return the file descriptor of its backing,
+    /// to take ownership of it, with the same content and length as with
+    /// [`backing`](Snapshot::backing).
+    /// If the backing is shared with other snapshots, e.g., after
+    /// [`try_clone_shared`](Snapshot::try_clone_shared), it's copied into a new backing first.
+    pub fn try_into_backing(mut self) -> std::io::Result<OwnedFileDescriptor> {
+        self.make_unique()?;
+        let Self { file, .. } = self;
+        // the backing is not shared after `make_unique`
+        Ok(Arc::into_inner(file).unwrap())
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::{Read, Write};
+
+use super::{is_zero, Snapshot};
+
+/// Marks the end of the page records of a sparse snapshot.
+const END_OF_PAGES: u64 = u64::MAX;
+
+impl Snapshot {
+    /// Write the content of the snapshot to a writer in a sparse format,
+    /// where pages that are entirely zero are omitted.
+    ///
+    /// The format is a header with the size of the snapshot, its
+    /// [logical length](Snapshot::set_logical_len) and the system page
+    /// size, followed by a record for each page that is not entirely zero,
+    /// with the index of the page and its content, and terminated by an
+    /// index of `u64::MAX`. All the integers are little-endian `u64`s.
+    /// Use [`read_sparse`](Snapshot::read_sparse) to read it back.
+    ///
+    /// # Serde
+    /// memsnap doesn't depend on serde, but the sparse format can embed a
+    /// snapshot in serde-serialized state, with a module for
+    /// `#[serde(with = "...")]` like:
+    ///
+    /// ```rust,ignore
+    /// mod sparse_snapshot {
+    ///     use memsnap::Snapshot;
+    ///     use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+    ///
+    ///     pub fn serialize<S: Serializer>(snapshot: &Snapshot, s: S) -> Result<S::Ok, S::Error> {
+    ///         let mut buf = Vec::new();
+    ///         snapshot.write_sparse(&mut buf).map_err(S::Error::custom)?;
+    ///         s.serialize_bytes(&buf)
+    ///     }
+    ///
+    ///     pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Snapshot, D::Error> {
+    ///         let buf = serde_bytes::ByteBuf::deserialize(d)?;
+    ///         Snapshot::read_sparse(&mut &buf[..]).map_err(D::Error::custom)
+    ///     }
+    /// }
+    /// ```
+    pub fn write_sparse<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let page = super::page_size();
+        w.write_all(&(self.size as u64).to_le_bytes())?;
+        w.write_all(&(self.len as u64).to_le_bytes())?;
+        w.write_all(&(page as u64).to_le_bytes())?;
+
+        let view = self.view()?;
+        for (idx, chunk) in view.as_full_slice().chunks(page).enumerate() {
+            if !is_zero(chunk) {
+                w.write_all(&(idx as u64).to_le_bytes())?;
+                w.write_all(chunk)?;
+            }
+        }
+        w.write_all(&END_OF_PAGES.to_le_bytes())
+    }
+
+    /// Create a new snapshot from the content read from a reader in the
+    /// sparse format written by [`write_sparse`](Snapshot::write_sparse).
+    /// Only the pages present in the stream are written to the snapshot,
+    /// and the rest of it stays zeroed.
+    /// The stream can be read on a system with a different page size.
+    ///
+    /// Returns an `InvalidData` error if the header of the stream is not
+    /// valid, e.g., if the size of the snapshot can't be allocated, or a
+    /// page of the stream is out of the bounds of the snapshot, and an
+    /// `UnexpectedEof` error if the stream ends before its terminator.
+    pub fn read_sparse<R: Read>(r: &mut R) -> std::io::Result<Self> {
+        let size = read_usize(r)?;
+        let len = read_usize(r)?;
+        let page = read_usize(r)?;
+        if page == 0 || len > size {
+            return Err(invalid_data("Sparse snapshot header is not valid"));
+        }
+        if size.checked_next_multiple_of(super::page_size()).is_none() {
+            return Err(invalid_data("Sparse snapshot is too large"));
+        }
+
+        let mut this = Self::zeroed(size)?;
+        this.set_logical_len(len)?;
+        let mut view = this.view_mut()?;
+        loop {
+            let idx = read_u64(r)?;
+            if idx == END_OF_PAGES {
+                break;
+            }
+            let start = (idx as usize)
+                .checked_mul(page)
+                .filter(|start| start.checked_add(page).is_some_and(|end| end <= size))
+                .ok_or_else(|| invalid_data("Sparse snapshot page out of bounds"))?;
+            r.read_exact(&mut view.as_full_mut_slice()[start..start + page])?;
+        }
+        drop(view);
+        Ok(this)
+    }
+}
+
+fn read_u64<R: Read>(r: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_usize<R: Read>(r: &mut R) -> std::io::Result<usize> {
+    usize::try_from(read_u64(r)?).map_err(|_| invalid_data("Sparse snapshot is too large"))
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
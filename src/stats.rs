@@ -0,0 +1,50 @@
+use super::{View, ViewMode};
+
+/// Statistics about the memory of a [`View`], as returned by
+/// [`stats`](View::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ViewStats {
+    /// The number of minor page faults since the view was created, those
+    /// that didn't need to read from disk or swap, e.g., mapping a page
+    /// that was already in memory, or copying a page of a copy-on-write
+    /// view when it's first written.
+    pub minor_faults: u64,
+    /// The number of major page faults since the view was created, those
+    /// that needed to read from disk or swap.
+    pub major_faults: u64,
+    /// The number of pages of a copy-on-write view that were copied
+    /// because they were written to, and that a
+    /// [`restore`](View::restore) would discard.
+    /// This is always `0` for mutable views.
+    pub copied_pages: usize,
+}
+
+impl<S> View<S> {
+    /// Returns statistics about the memory of this view, e.g., to tell if
+    /// restoring the view is cheaper than creating a new one.
+    ///
+    /// Page faults are counted for the whole process, so the counts also
+    /// include the faults in other views, and in any other memory, since
+    /// the view was created. They are read with `getrusage` on Linux,
+    /// macOS and FreeBSD, and with `GetProcessMemoryInfo` on Windows,
+    /// which doesn't tell major faults apart, so every fault is counted
+    /// as minor there. They are always `0` under Miri.
+    ///
+    /// The copied pages are the [dirty pages](View::dirty_pages) of the
+    /// view, and are as accurate as those on each platform.
+    pub fn stats(&self) -> std::io::Result<ViewStats> {
+        let (minor, major) = super::r#impl::fault_counts_impl();
+        let copied_pages = if self.mode == ViewMode::Cow {
+            let page = super::page_size();
+            let dirty = self.dirty_pages()?;
+            dirty.into_iter().map(|range| range.len() / page).sum()
+        } else {
+            0
+        };
+        Ok(ViewStats {
+            minor_faults: minor.saturating_sub(self.faults.0),
+            major_faults: major.saturating_sub(self.faults.1),
+            copied_pages,
+        })
+    }
+}
@@ -0,0 +1,134 @@
+use std::marker::PhantomData;
+use std::ops::{Range, RangeBounds};
+
+use super::{to_range, Cleanup, MutView, SnapshotError, View};
+
+/// A view into a region of another view, created with
+/// [`subview`](View::subview), [`split_at_mut`](View::split_at_mut) or
+/// [`split_regions`](View::split_regions).
+/// See [`View`] for more details.
+///
+/// A sub-view shares the mapping of the view it was created from, and
+/// borrows it mutably, so sub-views of the same view never overlap and
+/// can be sent to different threads.
+/// Sub-views inherit the memory protection of their region, but changes
+/// to the memory protection of a sub-view are not tracked by the view it
+/// was created from.
+pub type SubView<'a> = View<Borrowed<'a>>;
+
+/// The handle of a [`SubView`] to the view it was created from.
+#[derive(Debug)]
+pub struct Borrowed<'a>(PhantomData<&'a mut [u8]>);
+
+impl MutView<'_> {
+    /// Split this view into two disjoint sub-views at `mid`, one for the
+    /// bytes before `mid` and one for the bytes from `mid`, like
+    /// [`slice::split_at_mut`].
+    /// The split point doesn't need to be page-aligned, but sub-views that
+    /// don't start at a page boundary can't be protected.
+    ///
+    /// Returns an `InvalidInput` error if `mid` is out of bounds.
+    pub fn split_at_mut(&mut self, mid: usize) -> std::io::Result<(SubView<'_>, SubView<'_>)> {
+        if mid > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        // SAFETY: the two regions don't overlap, and the sub-views borrow
+        // this view mutably
+        Ok(unsafe { (self.part(0..mid), self.part(mid..self.size)) })
+    }
+
+    /// Split this view into disjoint sub-views, one for each of `regions`,
+    /// returned in the same order.
+    /// Like with [`split_at_mut`](View::split_at_mut), the regions don't
+    /// need to be page-aligned.
+    ///
+    /// Returns an `InvalidInput` error if any region is out of bounds, or
+    /// if any two regions overlap.
+    pub fn split_regions(&mut self, regions: &[Range<usize>]) -> std::io::Result<Vec<SubView<'_>>> {
+        let mut sorted: Vec<_> = regions.iter().collect();
+        sorted.sort_by_key(|region| region.start);
+        let in_bounds = sorted
+            .iter()
+            .all(|region| region.start <= region.end && region.end <= self.size);
+        if !in_bounds {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        if sorted.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Split regions must be disjoint",
+            ));
+        }
+        // SAFETY: the regions don't overlap, and the sub-views borrow this
+        // view mutably
+        Ok(regions
+            .iter()
+            .map(|region| unsafe { self.part(region.clone()) })
+            .collect())
+    }
+}
+
+impl<S> View<S> {
+    /// Create a sub-view of `region` of this view, sharing its mapping.
+    ///
+    /// The sub-view is a view of its own, scoped to the region:
+    /// [`protect`](View::protect), [`restore`](View::restore) and
+    /// [`take_snapshot`](View::take_snapshot) only affect the memory of the
+    /// region, and offsets are relative to its start.
+    /// Restoring a sub-view that doesn't start and end at a page boundary
+    /// would touch the memory around it, so it returns an `InvalidInput`
+    /// error instead.
+    /// This view is borrowed mutably while the sub-view is alive, since
+    /// the sub-view can write to its memory.
+    ///
+    /// Returns an `InvalidInput` error if `region` is out of bounds.
+    pub fn subview(&mut self, region: impl RangeBounds<usize>) -> std::io::Result<SubView<'_>> {
+        let region = to_range(region, self.size)?;
+        if region.start > region.end || region.end > self.size {
+            return Err(SnapshotError::RangeOutOfBounds.into());
+        }
+        // SAFETY: the region is in bounds, and the sub-view borrows this
+        // view mutably
+        Ok(unsafe { self.part(region) })
+    }
+
+    /// Create a sub-view of `region` of this view, sharing its mapping.
+    ///
+    /// # Safety
+    /// The region must be within the bounds of this view, the sub-view
+    /// must not outlive this view, and it must not overlap with any other
+    /// live sub-view.
+    unsafe fn part<'a>(&self, region: Range<usize>) -> SubView<'a> {
+        View {
+            fd: self.fd,
+            ptr: unsafe { self.ptr.add(region.start) },
+            size: region.len(),
+            len: region.len(),
+            offset: self.offset + region.start,
+            borrowed: true,
+            mode: self.mode,
+            logical: self.logical.slice(region.clone()),
+            protection: self.protection.slice(region.clone()),
+            caching: self.caching.slice(region),
+            overlaid: self.overlaid,
+            layers: self.layers.clone(),
+            guarded: false,
+            #[cfg(feature = "protection-history")]
+            history: Default::default(),
+            // the view this was created from already detects aliasing
+            #[cfg(debug_assertions)]
+            alias: None,
+            cleanup: Cleanup::default(),
+            // sub-views don't cover the whole root snapshot
+            root: None,
+            position: 0,
+            write_back: self.write_back,
+            // the view this was created from writes the changes back
+            journal: None,
+            faults: self.faults,
+            baseline: None,
+            metadata: self.metadata.clone(),
+            _snapshot: Borrowed(PhantomData),
+        }
+    }
+}
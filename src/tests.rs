@@ -1,10 +1,16 @@
+use std::collections::HashMap;
 use std::hint::black_box;
 use std::io::Write as _;
 use std::sync::Arc;
 
 use segv_test::assert_segv;
 
-use super::{Access, Snapshot};
+use super::{
+    Access, Advice, Caching, LayeredSnapshot, Snapshot, SnapshotError, SnapshotPool,
+    SnapshotSession, ZeroizeOnDrop,
+};
+
+mod access;
 
 #[test]
 fn test_empty_snapshot() {
@@ -25,6 +31,7 @@ fn test_empty_file() {
 }
 
 #[test]
+#[allow(clippy::len_zero)] // the view's length is what's being tested
 fn test_zeroed() {
     // Test that MemorySnapshot::zeroed genertes a snapshot full of zeros
     // of at least the requested size (it may be larger due to alignment)
@@ -32,18 +39,34 @@ fn test_zeroed() {
     let view = snapshot.view().unwrap();
     assert!(view.len() >= 1);
     assert!(view.as_slice().iter().all(|&b| b == 0));
-    assert!(view.len() % page_size::get() == 0);
+    assert!(view.len() % super::page_size() == 0);
+}
+
+#[test]
+fn test_filled() {
+    // Test that Snapshot::filled generates a snapshot where every byte,
+    // including the ones added by alignment, is the fill byte.
+    let snapshot = Snapshot::filled(10, 0xff).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), super::page_size());
+    assert!(view.as_slice().iter().all(|&b| b == 0xff));
+
+    let snapshot = Snapshot::filled(10, 0).unwrap();
+    let view = snapshot.view().unwrap();
+    assert!(view.as_slice().iter().all(|&b| b == 0));
 }
 
 #[test]
 fn test_from_slice() {
     // Test that MemorySnapshot::from_slice genertes a snapshot initialized to
     // the contents of the slice.
-    // The resulting allocation may be larger than the slice due to alignment.
+    // The resulting allocation may be larger than the slice due to alignment,
+    // but the view has the length of the slice.
     let snapshot = Snapshot::from_slice(b"hello slice").unwrap();
     let view = snapshot.view().unwrap();
-    assert_eq!(&view[..11], b"hello slice");
-    assert!(view.len() % page_size::get() == 0);
+    assert_eq!(&view[..], b"hello slice");
+    assert_eq!(view.len(), 11);
+    assert!(view.capacity() % super::page_size() == 0);
 }
 
 #[test]
@@ -58,6 +81,35 @@ fn test_from_file() {
     assert_eq!(&view[..10], b"hello file");
 }
 
+#[test]
+#[cfg(unix)]
+#[cfg_attr(miri, ignore = "shm_open is not supported under miri")]
+fn test_named_snapshot() {
+    // Test that a named snapshot can be opened by its name, sharing its
+    // content, and that its name is only removed on drop when requested.
+    let name = format!("/memsnap-test-{}", std::process::id());
+    let mut snapshot1 = super::NamedSnapshot::create(&name, 10).unwrap();
+    assert_eq!(snapshot1.name(), name);
+    assert!(!snapshot1.unlink_on_drop());
+    assert_eq!(snapshot1.view().unwrap().len(), page_size::get());
+    assert!(super::NamedSnapshot::create(&name, 10).is_err());
+
+    let snapshot2 = super::NamedSnapshot::open(&name).unwrap();
+    snapshot1.view_mut().unwrap()[0] = 42;
+    assert_eq!(snapshot2.view().unwrap()[0], 42);
+    drop(snapshot1);
+
+    let mut snapshot3 = super::NamedSnapshot::open(&name).unwrap();
+    assert_eq!(snapshot3.view().unwrap()[0], 42);
+    snapshot3.set_unlink_on_drop(true);
+    drop(snapshot3);
+
+    let err = super::NamedSnapshot::open(&name).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    // the segment is still usable by the snapshots that opened it
+    assert_eq!(snapshot2.view().unwrap()[0], 42);
+}
+
 #[test]
 fn test_view_mut() {
     // Test that mutating a snapshot view with view_mut actually mutates the
@@ -121,6 +173,22 @@ fn test_view_arc() {
     assert_eq!(&view[..11], b"hello world");
 }
 
+#[test]
+fn test_view_arc_owner() {
+    // Test that an `ArcView` can own its content on behalf of containers
+    // like `bytes::Bytes`, which need an owner that is `'static`, `Send`
+    // and `AsRef<[u8]>`.
+    fn into_owner(view: impl AsRef<[u8]> + Send + 'static) -> Box<dyn AsRef<[u8]> + Send> {
+        Box::new(view)
+    }
+
+    let snapshot = Arc::new(Snapshot::from_slice(b"hello world").unwrap());
+    let owner = into_owner(snapshot.view_arc().unwrap());
+    drop(snapshot);
+    let owner = std::thread::spawn(move || owner).join().unwrap();
+    assert_eq!(&(*owner).as_ref()[..11], b"hello world");
+}
+
 #[test]
 fn test_try_clone_snapshot() {
     // Test that cloning a snapshot works and that mutating the original snapshot
@@ -154,6 +222,7 @@ fn test_take_snapshot() {
 }
 
 #[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
 fn test_protect_none() {
     // Test that protecting a view with MemoryAccess::NONE causes a
     // segmentation fault when reading from it
@@ -166,6 +235,7 @@ fn test_protect_none() {
 }
 
 #[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
 fn test_protect_read() {
     // Test that protecting a view with MemoryAccess::READ can successfully
     // read from that memory
@@ -188,3 +258,2855 @@ fn test_protect_write() {
     black_box(view[0]);
     view[0] = 1;
 }
+
+#[test]
+fn test_logical_protect() {
+    // Test that logical protection records byte-granular permissions
+    // that can be checked, and that later calls override earlier ones.
+    let snapshot = Snapshot::zeroed(32).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.logical_protect(5..17, Access::NONE).unwrap();
+    view.logical_protect(10..12, Access::READ).unwrap();
+
+    assert!(view.check_access(4, Access::WRITE));
+    assert!(!view.check_access(5, Access::READ));
+    assert!(view.check_access(10, Access::READ));
+    assert!(!view.check_access(11, Access::WRITE));
+    assert!(!view.check_access(16, Access::READ));
+    assert!(view.check_access(17, Access::READ | Access::WRITE));
+    assert!(!view.check_access(view.len(), Access::READ));
+
+    // logical protection is not enforced by the MMU
+    view[5] = 1;
+
+    view.restore().unwrap();
+    assert!(view.check_access(5, Access::READ));
+}
+
+#[test]
+fn test_regions() {
+    // Test that the protection regions of a view are coalesced runs that
+    // cover the whole view.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    let rw = Access::READ | Access::WRITE;
+
+    assert_eq!(view.regions().collect::<Vec<_>>(), [(0..4 * page, rw)]);
+
+    view.protect(page..2 * page, Access::READ).unwrap();
+    view.protect(2 * page..3 * page, Access::READ).unwrap();
+    view.protect(3 * page..4 * page, Access::WRITE).unwrap();
+
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..page, rw),
+            (page..3 * page, Access::READ),
+            (3 * page..4 * page, rw)
+        ]
+    );
+}
+
+#[test]
+fn test_from_slice_sparse() {
+    // Test that a snapshot created from a mostly zero slice has the same
+    // content as the slice, even though zero pages are not written.
+    let page = super::page_size();
+    let mut buf = vec![0u8; 4 * page + 10];
+    buf[page + 1] = 1;
+    buf[4 * page + 9] = 2;
+
+    let snapshot = Snapshot::from_slice(&buf).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(&view[..buf.len()], &buf[..]);
+
+    let snapshot = Snapshot::from_slice(&vec![0u8; 3 * page]).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 3 * page);
+    assert!(view.as_slice().iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_protect_with_caching() {
+    // Test that protecting a view with caching attributes keeps the
+    // requested access permissions.
+    let mut snapshot1 = Snapshot::from_slice(b"hello world").unwrap();
+    let mut view = snapshot1.view_mut().unwrap();
+    view.protect_with_caching(.., Access::WRITE, Caching::WriteCombined)
+        .unwrap();
+
+    view[0] = b'j';
+    assert_eq!(&view[..5], b"jello");
+}
+
+#[test]
+fn test_reset_caching() {
+    // Test that caching attributes are undone along with the access
+    // permissions by reset_protections, restore and protection guards.
+    #[cfg(windows)]
+    fn caching(view: &super::View<&Snapshot>, offset: usize) -> u32 {
+        use windows::Win32::System::Memory::{VirtualQuery, MEMORY_BASIC_INFORMATION};
+        let mut info = MEMORY_BASIC_INFORMATION::default();
+        let len = std::mem::size_of::<MEMORY_BASIC_INFORMATION>();
+        let ptr = unsafe { view.as_ptr().add(offset) };
+        assert_ne!(unsafe { VirtualQuery(Some(ptr as _), &mut info, len) }, 0);
+        info.Protect.0 & !0xff
+    }
+    #[cfg(not(windows))]
+    fn caching(_view: &super::View<&Snapshot>, _offset: usize) -> u32 {
+        0
+    }
+    #[cfg(windows)]
+    let (uncached, write_combined) = (0x200, 0x400);
+    #[cfg(not(windows))]
+    let (uncached, write_combined) = (0, 0);
+
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    let rw = Access::READ | Access::WRITE;
+
+    view.protect_with_caching(..page, rw, Caching::Uncached)
+        .unwrap();
+    view.protect_with_caching(page.., Access::READ, Caching::WriteCombined)
+        .unwrap();
+    assert_eq!(caching(&view, 0), uncached);
+    assert_eq!(caching(&view, page), write_combined);
+    view[0] = 1;
+    assert_eq!(view[0], 1);
+
+    view.reset_protections().unwrap();
+    assert_eq!(caching(&view, 0), 0);
+    assert_eq!(caching(&view, page), 0);
+
+    view.protect_with_caching(..page, rw, Caching::Uncached)
+        .unwrap();
+    {
+        let guard = view.protect_scoped(..page, Access::READ).unwrap();
+        assert_eq!(caching(&guard, 0), 0);
+    }
+    assert_eq!(caching(&view, 0), uncached);
+
+    view.restore().unwrap();
+    assert_eq!(caching(&view, 0), 0);
+    assert_eq!(view[0], 0);
+}
+
+#[test]
+fn test_try_clone_n() {
+    // Test that cloning a snapshot several times at once creates
+    // independent snapshots with the same content, whether they were
+    // created with reflinks or not.
+    let d = tempfile::tempdir().unwrap();
+    let mut f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.write_all(b"hello world").unwrap();
+
+    for snapshot in [
+        Snapshot::from_slice(b"hello world").unwrap(),
+        Snapshot::from_file(f).unwrap(),
+    ] {
+        let mut clones = snapshot.try_clone_n(3).unwrap();
+        assert_eq!(clones.len(), 3);
+
+        clones[0].view_mut().unwrap()[0..11].copy_from_slice(b"hello slice");
+
+        assert_eq!(&clones[0].view().unwrap()[..11], b"hello slice");
+        assert_eq!(&clones[1].view().unwrap()[..11], b"hello world");
+        assert_eq!(&clones[2].view().unwrap()[..11], b"hello world");
+        assert_eq!(&snapshot.view().unwrap()[..11], b"hello world");
+    }
+}
+
+#[test]
+fn test_view_hash_eq() {
+    // Test that views compare and hash by content, consistently with
+    // slices, so they can be looked up by slice in a HashMap.
+    let snapshot1 = Snapshot::from_slice(b"hello world").unwrap();
+    let snapshot2 = Snapshot::from_slice(b"hello world").unwrap();
+    let snapshot3 = Snapshot::from_slice(b"hello slice").unwrap();
+
+    assert_eq!(snapshot1.view().unwrap(), snapshot2.view().unwrap());
+    assert_ne!(snapshot1.view().unwrap(), snapshot3.view().unwrap());
+
+    let mut map = HashMap::new();
+    map.insert(snapshot1.view().unwrap(), 1);
+    map.insert(snapshot3.view().unwrap(), 3);
+
+    assert_eq!(map.get(&b"hello world"[..]), Some(&1));
+    assert_eq!(map.get(&b"hello slice"[..]), Some(&3));
+    assert_eq!(map.get(&b"hello there"[..]), None);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_session_grow() {
+    // Test that growing a session preserves the content of the snapshot
+    // and the memory protection, and that the new pages are writable.
+    let page = super::page_size();
+    let mut session = SnapshotSession::new(Snapshot::from_slice(b"hello world").unwrap());
+    session.protect(0..page, Access::READ).unwrap();
+
+    session.grow(3 * page).unwrap();
+    assert_eq!(session.snapshot().view().unwrap().len(), 3 * page);
+
+    let mut view = session.view_mut().unwrap();
+    assert_eq!(&view[..11], b"hello world");
+    assert!(view[page..].iter().all(|&b| b == 0));
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..page, Access::READ),
+            (page..3 * page, Access::READ | Access::WRITE)
+        ]
+    );
+    view[page] = 1;
+    assert_segv!(view[0] = 1);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_session_view_protect() {
+    // Test that the protection set directly on the view of a session is
+    // recorded into the session when the view is dropped.
+    let page = super::page_size();
+    let mut session = SnapshotSession::new(Snapshot::zeroed(2 * page).unwrap());
+    session.protect(..page, Access::READ).unwrap();
+
+    let mut view = session.view_mut().unwrap();
+    view.reset_protections().unwrap();
+    view.protect_with_caching(page.., Access::READ, Caching::Uncached)
+        .unwrap();
+    drop(view);
+
+    session.grow(3 * page).unwrap();
+    let mut view = session.view_mut().unwrap();
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..page, Access::READ | Access::WRITE),
+            (page..2 * page, Access::READ),
+            (2 * page..3 * page, Access::READ | Access::WRITE)
+        ]
+    );
+    assert_eq!((*view).caching.get(page), Some(Caching::Uncached));
+    view[0] = 1;
+    assert_segv!(view[page] = 1);
+}
+
+#[test]
+fn test_reset_protections() {
+    // Test that resetting the protections of a view makes it readable and
+    // writable again, without discarding its content.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view[0] = 1;
+    view.protect(0..page, Access::NONE).unwrap();
+    view.protect(2 * page..3 * page, Access::READ).unwrap();
+
+    view.reset_protections().unwrap();
+
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [(0..3 * page, Access::READ | Access::WRITE)]
+    );
+    assert_eq!(view[0], 1);
+    view[0] = 2;
+    view[2 * page] = 2;
+}
+
+#[test]
+fn test_layered_snapshot() {
+    // Test that a view of a layered snapshot reads each page from the
+    // topmost layer that overrides it, and that writing to the view
+    // doesn't affect any of the layers.
+    let page = super::page_size();
+    let base = Arc::new(Snapshot::from_slice(&vec![0u8; 4 * page]).unwrap());
+    let v1 = Arc::new(Snapshot::from_slice(&vec![1u8; 4 * page]).unwrap());
+    let v2 = Arc::new(Snapshot::from_slice(&vec![2u8; 4 * page]).unwrap());
+
+    let mut layered = LayeredSnapshot::new(base.clone());
+    layered.push_layer(v1.clone(), [1, 2]).unwrap();
+    layered.push_layer(v2.clone(), [2, 3]).unwrap();
+    assert!(layered.push_layer(v2.clone(), [4]).is_err());
+
+    let mut view = layered.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 0));
+    assert!(view[page..2 * page].iter().all(|&b| b == 1));
+    assert!(view[2 * page..].iter().all(|&b| b == 2));
+
+    view[page] = 5;
+    assert_eq!(view[page], 5);
+    assert_eq!(v1.view().unwrap()[page], 1);
+
+    // Test that restoring the view, or a region of it, restores the
+    // content of the layers rather than that of the base snapshot.
+    view[0] = 5;
+    view[3 * page] = 5;
+    view.restore_range(3 * page..).unwrap();
+    assert_eq!(view[3 * page], 2);
+    assert_eq!(view[page], 5);
+
+    view.restore().unwrap();
+    assert_eq!(view[0], 0);
+    assert!(view[page..2 * page].iter().all(|&b| b == 1));
+    assert!(view[2 * page..].iter().all(|&b| b == 2));
+    assert!(base.view().unwrap().iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_capture_layer() {
+    // Test that only the pages of a layered view that changed are captured
+    // into a new layer, and that the chain of layers composes them.
+    let page = super::page_size();
+    let base = Arc::new(Snapshot::from_slice(&vec![0u8; 4 * page]).unwrap());
+    let v1 = Arc::new(Snapshot::from_slice(&vec![1u8; 4 * page]).unwrap());
+    let mut layered = LayeredSnapshot::new(base.clone());
+    layered.push_layer(v1, [1]).unwrap();
+
+    let mut view = layered.view().unwrap();
+    view[page] = 2;
+    view[2 * page] = 3;
+    view[3 * page] = 0;
+    let (layer, pages) = view.capture_layer().unwrap();
+    assert_eq!(pages, [1, 2]);
+    drop(view);
+    layered.push_layer(Arc::new(layer), pages).unwrap();
+
+    let mut view = layered.view().unwrap();
+    assert_eq!(view[page], 2);
+    assert_eq!(view[page + 1], 1);
+    assert_eq!(view[2 * page], 3);
+    assert!(view[3 * page..].iter().all(|&b| b == 0));
+
+    view[0] = 4;
+    let (layer, pages) = view.capture_layer().unwrap();
+    assert_eq!(pages, [0]);
+    assert_eq!(layer.view().unwrap()[0], 4);
+
+    // Test that taking a snapshot of the view flattens the chain.
+    let mut flat = vec![0u8; 4 * page];
+    flat[0] = 4;
+    flat[page..2 * page].fill(1);
+    flat[page] = 2;
+    flat[2 * page] = 3;
+    assert_eq!(
+        view.take_snapshot().unwrap().view().unwrap().as_slice(),
+        &flat[..]
+    );
+    assert!(base.view().unwrap().iter().all(|&b| b == 0));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "aliased")]
+#[cfg_attr(miri, ignore = "snapshots don't share the backing file under miri")]
+fn test_aliased_view_mut() {
+    // Test that in debug builds, mutable access to a mutable view panics
+    // when another mutable view of the same backing file is alive.
+    let d = tempfile::tempdir().unwrap();
+    let f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.set_len(10).unwrap();
+    let mut snapshot1 = Snapshot::from_file(f.try_clone().unwrap()).unwrap();
+    let mut snapshot2 = Snapshot::from_file(f).unwrap();
+
+    let mut view1 = snapshot1.view_mut().unwrap();
+    let _view2 = snapshot2.view_mut().unwrap();
+    view1[0] = 1;
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[cfg_attr(miri, ignore = "snapshots don't share the backing file under miri")]
+fn test_unaliased_view_mut() {
+    // Test that in debug builds, a mutable view can be mutated once the
+    // other mutable views of the same backing file are dropped.
+    let d = tempfile::tempdir().unwrap();
+    let f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.set_len(10).unwrap();
+    let mut snapshot1 = Snapshot::from_file(f.try_clone().unwrap()).unwrap();
+    let mut snapshot2 = Snapshot::from_file(f).unwrap();
+
+    let mut view1 = snapshot1.view_mut().unwrap();
+    drop(snapshot2.view_mut().unwrap());
+    view1[0] = 1;
+    drop(view1);
+
+    let mut view2 = snapshot2.view_mut().unwrap();
+    view2[1] = 2;
+    assert_eq!(view2[1], 2);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+#[cfg_attr(miri, ignore = "O_DIRECT is not supported under miri")]
+fn test_from_file_direct_io() {
+    // Test that a snapshot can be created from a file opened with
+    // O_DIRECT only if its length is a multiple of its block size.
+    use std::os::unix::fs::{MetadataExt as _, OpenOptionsExt as _};
+
+    let d = tempfile::tempdir().unwrap();
+    let path = d.path().join("tempfile");
+    let open = || {
+        std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(&path)
+    };
+    let Ok(f) = open() else {
+        // the filesystem doesn't support direct I/O
+        return;
+    };
+    let block_size = f.metadata().unwrap().blksize();
+
+    f.set_len(block_size + 1).unwrap();
+    let err = Snapshot::from_file(f).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let f = open().unwrap();
+    f.set_len(block_size).unwrap();
+    let snapshot = Snapshot::from_file(f).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len() % block_size as usize, 0);
+    assert_eq!(view.len() % super::page_size(), 0);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_capture_apply_protections() {
+    // Test that a captured protection layout can be applied again after
+    // the protection of the view changes.
+    let page = super::page_size();
+    let rw = Access::READ | Access::WRITE;
+    let mut snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view.protect(page..3 * page, Access::READ).unwrap();
+    let layout_a = view.capture_protections();
+
+    view.protect(0..2 * page, Access::NONE).unwrap();
+    view.protect(3 * page..4 * page, Access::READ).unwrap();
+    let layout_b = view.capture_protections();
+
+    view.apply_protections(&layout_a).unwrap();
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..page, rw),
+            (page..3 * page, Access::READ),
+            (3 * page..4 * page, rw)
+        ]
+    );
+    view[0] = 1;
+    view[3 * page] = 1;
+    assert_segv!(view[page] = 1);
+
+    view.apply_protections(&layout_b).unwrap();
+    assert_eq!(view.capture_protections(), layout_b);
+    assert_segv!(black_box(view[0]));
+}
+
+#[test]
+#[cfg(feature = "digest")]
+fn test_from_file_verified() {
+    // Test that a snapshot is only created from a file if the digest of
+    // its content matches the expected digest.
+    use super::DigestAlgo;
+
+    let d = tempfile::tempdir().unwrap();
+    let path = d.path().join("tempfile");
+    std::fs::write(&path, b"hello file").unwrap();
+
+    for algo in [DigestAlgo::Sha256, DigestAlgo::Sha512] {
+        let expected = algo.digest(b"hello file");
+
+        let f = std::fs::File::open(&path).unwrap();
+        let snapshot = Snapshot::from_file_verified(f, &expected, algo).unwrap();
+        assert_eq!(&snapshot.view().unwrap()[..10], b"hello file");
+
+        let f = std::fs::File::open(&path).unwrap();
+        let err = Snapshot::from_file_verified(f, &expected[1..], algo).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn test_zero_fast() {
+    // Test that zeroing a region of a view zeroes exactly that region,
+    // modifying the root snapshot only for mutable views.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1u8; 4 * page]).unwrap();
+
+    let mut view = snapshot.view().unwrap();
+    view.zero_fast(10..3 * page + 10).unwrap();
+    assert!(view[..10].iter().all(|&b| b == 1));
+    assert!(view[10..3 * page + 10].iter().all(|&b| b == 0));
+    assert!(view[3 * page + 10..].iter().all(|&b| b == 1));
+    view.restore().unwrap();
+    assert!(view.as_slice().iter().all(|&b| b == 1));
+    drop(view);
+
+    let mut view = snapshot.view_mut().unwrap();
+    view.zero_fast(page..2 * page + 1).unwrap();
+    drop(view);
+    let view = snapshot.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 1));
+    assert!(view[page..2 * page + 1].iter().all(|&b| b == 0));
+    assert!(view[2 * page + 1..].iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_views_send_sync() {
+    // Test that the views of the public snapshot handles are Send and Sync.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<super::CowView>();
+    assert_send_sync::<super::MutView>();
+    assert_send_sync::<super::ArcView>();
+    assert_send_sync::<super::LayeredView>();
+}
+
+#[test]
+#[cfg(feature = "protection-history")]
+fn test_protection_history() {
+    // Test that the protection changes of a view are recorded, and that
+    // the history of a page only contains the changes that touch it.
+    let page = super::page_size();
+    let rw = Access::READ | Access::WRITE;
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view.protect(0..2 * page, Access::READ).unwrap();
+    view.protect(page..3 * page, Access::NONE).unwrap();
+
+    let history = view.protection_history(10);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].range, 0..page * 2);
+    assert_eq!((history[0].old, history[0].new), (rw, Access::READ));
+
+    let history = view.protection_history(page + 10);
+    let changes: Vec<_> = history
+        .iter()
+        .map(|e| (e.range.clone(), e.old, e.new))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (0..2 * page, rw, Access::READ),
+            (page..2 * page, Access::READ, Access::NONE),
+        ]
+    );
+}
+
+#[test]
+fn test_concat() {
+    // Test that concatenating snapshots lays out each part at a
+    // page-aligned offset with its content.
+    let page = super::page_size();
+    let part1 = Snapshot::from_slice(b"hello").unwrap();
+    let part2 = Snapshot::from_slice(&vec![2u8; page + 1]).unwrap();
+    let part3 = Snapshot::from_slice(b"world").unwrap();
+
+    let (snapshot, offsets) = Snapshot::concat(&[&part1, &part2, &part3]).unwrap();
+    assert_eq!(offsets, [0, page, 3 * page]);
+
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 4 * page);
+    assert_eq!(&view[..5], b"hello");
+    assert!(view[5..page].iter().all(|&b| b == 0));
+    assert!(view[page..2 * page + 1].iter().all(|&b| b == 2));
+    assert!(view[2 * page + 1..3 * page].iter().all(|&b| b == 0));
+    assert_eq!(&view[3 * page..3 * page + 5], b"world");
+
+    let (snapshot, offsets) = Snapshot::concat(&[]).unwrap();
+    assert!(offsets.is_empty());
+    assert_eq!(snapshot.view().unwrap().len(), 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+#[cfg_attr(miri, ignore = "overlaying pages is not supported under miri")]
+fn test_map_page_from() {
+    // Test that a page mapped from another snapshot shows the content of
+    // that snapshot, and that writes to it modify neither snapshot.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let src = Snapshot::filled(page, 0xaa).unwrap();
+
+    let mut view = snapshot.view_mut().unwrap();
+    assert!(view.map_page_from(2, &src, 0).is_err());
+    assert!(view.map_page_from(1, &src, 1).is_err());
+
+    view.map_page_from(1, &src, 0).unwrap();
+    assert!(view[page..].iter().all(|&b| b == 0xaa));
+
+    view[page] = 1;
+    view[0] = 1;
+    drop(view);
+
+    assert!(src.view().unwrap().as_slice().iter().all(|&b| b == 0xaa));
+    let view = snapshot.view().unwrap();
+    assert_eq!(view[0], 1);
+    assert!(view[page..].iter().all(|&b| b == 0));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+#[cfg_attr(miri, ignore = "overlaying pages is not supported under miri")]
+fn test_copy_from_overlaid() {
+    // Test that the content of a page mapped from another snapshot is
+    // copied, rather than the page of the backing underneath it, and that
+    // copying into such a page writes to the page that's mapped.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(page).unwrap();
+    let mut other = Snapshot::zeroed(page).unwrap();
+    let src = Snapshot::filled(page, 0xaa).unwrap();
+
+    let mut src_view = snapshot.view_mut().unwrap();
+    src_view.map_page_from(0, &src, 0).unwrap();
+    let mut dst_view = other.view_mut().unwrap();
+    assert_eq!(dst_view.copy_from(&src_view, .., 0).unwrap(), page);
+    assert!(dst_view.iter().all(|&b| b == 0xaa));
+    drop(src_view);
+    dst_view.fill(.., 0xbb).unwrap();
+    drop(dst_view);
+
+    let mut dst_view = snapshot.view_mut().unwrap();
+    dst_view.map_page_from(0, &src, 0).unwrap();
+    let src_view = other.view().unwrap();
+    assert_eq!(dst_view.copy_from(&src_view, .., 0).unwrap(), page);
+    assert!(dst_view.iter().all(|&b| b == 0xbb));
+}
+
+#[test]
+fn test_view_with_cleanup() {
+    // Test that the cleanup callbacks of a view are called in order when
+    // the view is dropped, and not before.
+    let (tx, rx) = std::sync::mpsc::channel();
+    let snapshot = Snapshot::zeroed(10).unwrap();
+    let tx2 = tx.clone();
+    let view = snapshot
+        .view()
+        .unwrap()
+        .with_cleanup(move || tx.send(1).unwrap())
+        .with_cleanup(move || tx2.send(2).unwrap());
+
+    assert!(rx.try_recv().is_err());
+    drop(view);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+#[cfg(feature = "tempfile")]
+fn test_from_temp_file() {
+    // Test that a snapshot created from a temporary file has the content
+    // of the file, and that the file is only deleted once the snapshot
+    // and all its views are dropped.
+    let mut f = tempfile::NamedTempFile::new().unwrap();
+    f.write_all(b"hello file").unwrap();
+    let path = f.path().to_path_buf();
+
+    let snapshot = Arc::new(Snapshot::from_temp_file(f).unwrap());
+    let view = snapshot.view_arc().unwrap();
+    assert_eq!(&view[..10], b"hello file");
+
+    drop(snapshot);
+    assert!(path.exists());
+
+    drop(view);
+    assert!(!path.exists());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_apply_permission_bitmap() {
+    // Test that a permission bitmap sets the protection of every page, and
+    // that it's rejected if its length is not the page count of the view.
+    let page = super::page_size();
+    let rw = Access::READ | Access::WRITE;
+    let snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    assert_eq!(view.page_count(), 4);
+    assert!(view.apply_permission_bitmap(&[rw; 3]).is_err());
+
+    view.apply_permission_bitmap(&[Access::READ, Access::READ, rw, Access::NONE])
+        .unwrap();
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..2 * page, Access::READ),
+            (2 * page..3 * page, rw),
+            (3 * page..4 * page, Access::NONE)
+        ]
+    );
+    assert_eq!(view.query_protection(page).unwrap(), Access::READ);
+    assert_eq!(view.query_protection(3 * page).unwrap(), Access::NONE);
+
+    view.apply_permission_bitmap(&[rw; 4]).unwrap();
+    assert_eq!(view.regions().collect::<Vec<_>>(), [(0..4 * page, rw)]);
+    view[3 * page] = 1;
+}
+
+#[test]
+fn test_view_weak() {
+    // Test that a weak reference to a snapshot can be viewed while the
+    // snapshot is alive, and that the view keeps the snapshot alive.
+    let snapshot = Arc::new(Snapshot::from_slice(b"hello world").unwrap());
+    let weak = Arc::downgrade(&snapshot);
+
+    let view = Snapshot::view_weak(&weak).unwrap().unwrap();
+    drop(snapshot);
+    assert_eq!(&view[..11], b"hello world");
+    assert!(weak.upgrade().is_some());
+
+    drop(view);
+    assert!(Snapshot::view_weak(&weak).is_none());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(miri)))]
+fn test_thread_local_protection() {
+    // Test that threads can have different access to the same region of a
+    // view through a protection key.
+    use super::ThreadLocalProtection;
+
+    let Ok(key) = ThreadLocalProtection::new() else {
+        // protection keys are not supported on this system
+        return;
+    };
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    key.assign(&mut view, .., Access::READ | Access::WRITE)
+        .unwrap();
+    assert_eq!(key.thread_access(), Access::READ | Access::WRITE);
+    view[0] = 1;
+
+    key.set_thread_access(Access::READ);
+    assert_eq!(key.thread_access(), Access::READ);
+    assert_eq!(black_box(view[0]), 1);
+    assert_segv!(view[0] = 2);
+
+    // recovering from the fault leaves the thread with the access of
+    // signal handlers, so set it again
+    key.set_thread_access(Access::READ);
+    let addr = view.as_mut_ptr() as usize;
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            key.set_thread_access(Access::READ | Access::WRITE);
+            unsafe { *(addr as *mut u8) = 3 };
+        });
+    });
+    assert_eq!(key.thread_access(), Access::READ);
+    assert_eq!(black_box(view[0]), 3);
+
+    key.set_thread_access(Access::NONE);
+    assert_segv!(black_box(view[0]));
+    key.set_thread_access(Access::READ | Access::WRITE);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_protect_scoped() {
+    // Test that dropping a protection guard restores the protection that
+    // each page had before the guard was created.
+    let page = super::page_size();
+    let rw = Access::READ | Access::WRITE;
+    let snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.protect(0..page, Access::READ).unwrap();
+
+    {
+        let mut guard = view.protect_scoped(.., Access::NONE).unwrap();
+        assert_segv!(black_box(guard[2 * page]));
+        {
+            let mut guard = guard.protect_scoped(page..2 * page, rw).unwrap();
+            guard[page] = 1;
+        }
+        assert_segv!(black_box(guard[page]));
+    }
+
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [(0..page, Access::READ), (page..3 * page, rw)]
+    );
+    assert_segv!(view[0] = 1);
+    view[2 * page] = 1;
+    assert_eq!(view[page], 1);
+}
+
+#[test]
+fn test_restore_range() {
+    // Test that restoring a region of a view discards the changes and the
+    // protection in that region only.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view[0] = 1;
+    view[page] = 1;
+    view[2 * page] = 1;
+    view.protect(page..3 * page, Access::READ).unwrap();
+    let ptr = view.as_ptr();
+
+    assert!(view.restore_range(1..page).is_err());
+    assert!(view.restore_range(0..4 * page).is_err());
+
+    view.restore_range(page..2 * page).unwrap();
+    assert_eq!(view.as_ptr(), ptr);
+    assert_eq!((view[0], view[page], view[2 * page]), (1, 0, 1));
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        [
+            (0..2 * page, Access::READ | Access::WRITE),
+            (2 * page..3 * page, Access::READ)
+        ]
+    );
+    view[page] = 2;
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+#[cfg_attr(miri, ignore = "dirty pages are not tracked under miri")]
+fn test_dirty_pages() {
+    // Test that written pages are reported as dirty, even if the written
+    // value is the same, and that restoring the view cleans them.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 5 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+    black_box(view[0]);
+    assert_eq!(view.dirty_pages().unwrap(), []);
+
+    view[page] = 1;
+    view[2 * page + 1] = 2;
+    view.protect(3 * page..4 * page, Access::READ).unwrap();
+    view[4 * page] = 3;
+    assert_eq!(
+        view.dirty_pages().unwrap(),
+        [page..3 * page, 4 * page..5 * page]
+    );
+
+    let ptr = view.as_ptr();
+    view.restore().unwrap();
+    assert_eq!(view.as_ptr(), ptr);
+    assert_eq!(view.dirty_pages().unwrap(), []);
+    assert_eq!((view[2 * page + 1], view[4 * page]), (1, 1));
+    view[3 * page] = 2;
+    drop(view);
+
+    let mut snapshot = snapshot;
+    let mut view = snapshot.view_mut().unwrap();
+    view[0] = 2;
+    assert_eq!(view.dirty_pages().unwrap(), []);
+}
+
+#[test]
+fn test_snapshot_grow() {
+    // Test that growing a snapshot preserves its content and zeroes the
+    // new bytes, also when its file can't be extended.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(b"hello world").unwrap();
+    snapshot.grow(page / 2).unwrap();
+    assert_eq!(snapshot.view().unwrap().capacity(), page);
+
+    snapshot.grow(2 * page + 1).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 3 * page);
+    assert_eq!(&view[..11], b"hello world");
+    assert!(view[11..].iter().all(|&b| b == 0));
+
+    let d = tempfile::tempdir().unwrap();
+    std::fs::write(d.path().join("tempfile"), b"hello file").unwrap();
+    let f = std::fs::File::open(d.path().join("tempfile")).unwrap();
+    let mut snapshot = Snapshot::from_file(f).unwrap();
+    snapshot.grow(2 * page).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 2 * page);
+    assert_eq!(&view[..10], b"hello file");
+    assert!(view[10..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_snapshot_truncate() {
+    // Test that truncating a snapshot keeps the content before the new
+    // size, and that truncating to a larger size has no effect.
+    let page = super::page_size();
+    let mut buf = vec![1; 3 * page];
+    buf[page..].fill(2);
+    let mut snapshot = Snapshot::from_slice(&buf).unwrap();
+    snapshot.truncate(4 * page).unwrap();
+    assert_eq!(snapshot.view().unwrap().len(), 3 * page);
+
+    snapshot.truncate(page + 1).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.as_slice(), &buf[..2 * page]);
+    drop(view);
+
+    snapshot.grow(3 * page).unwrap();
+    let view = snapshot.view().unwrap();
+    assert!(view[2 * page..].iter().all(|&b| b == 0));
+
+    let d = tempfile::tempdir().unwrap();
+    std::fs::write(d.path().join("tempfile"), &buf).unwrap();
+    let f = std::fs::File::open(d.path().join("tempfile")).unwrap();
+    let mut snapshot = Snapshot::from_file(f).unwrap();
+    snapshot.truncate(page).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), &buf[..page]);
+}
+
+#[test]
+fn test_write_to_read_from() {
+    // Test that a snapshot written to a writer can be read back, and that
+    // reading fails if the reader ends too early.
+    let page = super::page_size();
+    let mut buf = vec![0; 2 * page];
+    buf[..11].copy_from_slice(b"hello world");
+    let snapshot = Snapshot::from_slice(&buf).unwrap();
+
+    let mut out = Vec::new();
+    snapshot.write_to(&mut out).unwrap();
+    assert_eq!(out, buf);
+
+    let snapshot = Snapshot::read_from(&mut out.as_slice(), 11).unwrap();
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), page);
+    assert_eq!(&view[..11], b"hello world");
+    assert!(view[11..].iter().all(|&b| b == 0));
+
+    let err = Snapshot::read_from(&mut &out[..page], 2 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_write_read_sparse() {
+    // Test that a snapshot written in the sparse format omits the zero
+    // pages, and that it can be read back.
+    let page = super::page_size();
+    let mut buf = vec![0; 8 * page];
+    buf[3 * page + 5] = 1;
+    buf[8 * page - 1] = 2;
+    let snapshot = Snapshot::from_slice(&buf).unwrap();
+
+    let mut out = Vec::new();
+    snapshot.write_sparse(&mut out).unwrap();
+    assert_eq!(out.len(), 8 * 4 + 2 * (8 + page));
+
+    let snapshot = Snapshot::read_sparse(&mut out.as_slice()).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), &buf[..]);
+
+    let err = Snapshot::read_sparse(&mut &out[..out.len() - 8]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    out[24..32].copy_from_slice(&8u64.to_le_bytes());
+    let err = Snapshot::read_sparse(&mut out.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    // Test that the logical length of the snapshot is kept.
+    let snapshot = Snapshot::from_slice(b"hello").unwrap();
+    let mut out = Vec::new();
+    snapshot.write_sparse(&mut out).unwrap();
+    let snapshot = Snapshot::read_sparse(&mut out.as_slice()).unwrap();
+    assert_eq!(snapshot.logical_len(), 5);
+    assert_eq!(snapshot.view().unwrap().as_slice(), b"hello");
+
+    // Test that invalid headers are rejected instead of allocating.
+    let header = |size: u64, len: u64, page: u64| {
+        let mut out = Vec::new();
+        for n in [size, len, page, u64::MAX] {
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        out
+    };
+    for out in [
+        header(u64::MAX - 10, 0, page as u64),
+        header(page as u64, 0, 0),
+        header(page as u64, page as u64 + 1, page as u64),
+    ] {
+        let err = Snapshot::read_sparse(&mut out.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn test_content_hash() {
+    // Test that the content hash only depends on the content of the view,
+    // and not on how its snapshot was built.
+    let page = super::page_size();
+    let d = tempfile::tempdir().unwrap();
+    let mut f = std::fs::File::create_new(d.path().join("tempfile")).unwrap();
+    f.write_all(b"hello world").unwrap();
+    let snapshot1 = Snapshot::from_file(f).unwrap();
+    let snapshot2 = Snapshot::from_slice(b"hello world").unwrap();
+    let snapshot3 = Snapshot::from_slice(b"hello world!").unwrap();
+    let snapshot4 = Snapshot::zeroed(page).unwrap();
+    let snapshot5 = Snapshot::zeroed(2 * page).unwrap();
+
+    let hash = |snapshot: &Snapshot| snapshot.view().unwrap().content_hash();
+    assert_eq!(hash(&snapshot1), hash(&snapshot2));
+    assert_ne!(hash(&snapshot2), hash(&snapshot3));
+    assert_ne!(hash(&snapshot4), hash(&snapshot5));
+
+    let mut view = snapshot2.view().unwrap();
+    view[0] = b'j';
+    assert_ne!(view.content_hash(), hash(&snapshot2));
+}
+
+#[test]
+fn test_content_eq() {
+    // Test that snapshots are compared by size and content.
+    let page = super::page_size();
+    let snapshot1 = Snapshot::from_slice(b"hello world").unwrap();
+    let snapshot2 = Snapshot::from_slice(b"hello world").unwrap();
+    let snapshot3 = Snapshot::from_slice(b"hello world!").unwrap();
+    let snapshot4 = Snapshot::zeroed(page).unwrap();
+    let snapshot5 = Snapshot::zeroed(2 * page).unwrap();
+
+    assert!(snapshot1.content_eq(&snapshot2).unwrap());
+    assert!(snapshot1.content_eq(&snapshot1).unwrap());
+    assert!(!snapshot1.content_eq(&snapshot3).unwrap());
+    assert!(!snapshot4.content_eq(&snapshot5).unwrap());
+    assert!(snapshot1.view().unwrap() == snapshot2.view().unwrap());
+
+    let empty1 = Snapshot::zeroed(0).unwrap();
+    let empty2 = Snapshot::from_slice(b"").unwrap();
+    assert!(empty1.content_eq(&empty2).unwrap());
+    assert!(!empty1.content_eq(&snapshot4).unwrap());
+}
+
+#[test]
+fn test_view_cursor() {
+    // Test that a cursor reads the content of a view, and that reads past
+    // the end return zero bytes.
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut snapshot = Snapshot::from_slice(b"hello world").unwrap();
+    let view = snapshot.view().unwrap();
+    let mut cursor = view.cursor();
+
+    let mut buf = [0; 5];
+    cursor.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    cursor.seek(SeekFrom::Start(6)).unwrap();
+    cursor.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    let mut rest = Vec::new();
+    cursor.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(cursor.read(&mut buf).unwrap(), 0);
+    assert_eq!(cursor.seek(SeekFrom::End(10)).unwrap(), 21);
+    assert_eq!(cursor.read(&mut buf).unwrap(), 0);
+    drop(view);
+
+    // Test that writes through a mutable cursor reach the snapshot.
+    let mut view = snapshot.view_mut().unwrap();
+    let mut cursor = view.cursor_mut();
+    cursor.seek(SeekFrom::Start(6)).unwrap();
+    cursor.write_all(b"there").unwrap();
+    assert_eq!(cursor.position(), 11);
+    cursor.seek(SeekFrom::End(0)).unwrap();
+    assert!(cursor.write_all(b"!").is_err());
+    drop(view);
+
+    assert_eq!(&snapshot.view().unwrap()[..11], b"hello there");
+}
+
+#[test]
+fn test_view_write() {
+    // Test that writes to a mutable view advance its position, and that
+    // writes that don't fit are truncated.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+
+    let name = "world";
+    write!(view, "hello {name}").unwrap();
+    assert_eq!(view.position(), 11);
+
+    view.set_position(page - 2);
+    assert_eq!(view.write(b"abcd").unwrap(), 2);
+    assert_eq!(view.position(), page);
+    assert_eq!(view.write(b"abcd").unwrap(), 0);
+    assert!(view.write_all(b"abcd").is_err());
+
+    view.set_position(2 * page);
+    assert_eq!(view.write(b"abcd").unwrap(), 0);
+    assert_eq!(view.position(), 2 * page);
+    drop(view);
+
+    let view = snapshot.view().unwrap();
+    assert_eq!(&view[..11], b"hello world");
+    assert_eq!(&view[page - 2..], b"ab");
+}
+
+#[test]
+fn test_view_deref() {
+    // Test that views can be used where a slice is expected.
+    fn starts_with_hello(buf: impl AsRef<[u8]>) -> bool {
+        buf.as_ref().starts_with(b"hello")
+    }
+
+    let snapshot = Snapshot::from_slice(b"hello world").unwrap();
+    let mut view = snapshot.view().unwrap();
+    assert!(starts_with_hello(&*view));
+    assert!(view.starts_with(b"hello"));
+
+    view[..5].copy_from_slice(b"jello");
+    let slice: &mut [u8] = &mut view;
+    slice[0] = b'y';
+    assert!(!starts_with_hello(view.as_ref()));
+    assert_eq!(&view.as_mut()[..5], b"yello");
+    assert!(starts_with_hello(snapshot.view().unwrap()));
+}
+
+#[test]
+fn test_view_get() {
+    // Test that checked accessors return `None` instead of panicking when
+    // out of bounds.
+    let snapshot = Snapshot::from_slice(b"hello world").unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    assert_eq!(view.get(0), Some(&b'h'));
+    assert_eq!(view.get(6..11), Some(&b"world"[..]));
+    assert_eq!(view.get(11), None);
+    assert_eq!(view.get(10..12), None);
+
+    view.get_mut(..5).unwrap().copy_from_slice(b"jello");
+    assert_eq!(view.get_mut(11..), Some(&mut [][..]));
+    assert_eq!(view.get_mut(12..), None);
+    assert_eq!(&view[..5], b"jello");
+}
+
+#[test]
+fn test_zeroed_named() {
+    // Test that named snapshots are zeroed, that long names are truncated,
+    // and that names with nul bytes are rejected.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed_named(page, "my_snapshot").unwrap();
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 0));
+    Snapshot::zeroed_named(page, &"é".repeat(200)).unwrap();
+
+    let err = Snapshot::zeroed_named(page, "my\0snapshot").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    #[cfg(all(target_os = "linux", not(miri)))]
+    {
+        use std::os::fd::AsRawFd as _;
+        let fd = snapshot.file.as_raw_fd();
+        let link = std::fs::read_link(format!("/proc/self/fd/{fd}")).unwrap();
+        assert_eq!(link.to_str().unwrap(), "/memfd:my_snapshot (deleted)");
+    }
+}
+
+#[test]
+fn test_advise() {
+    // Test that `DontNeed` discards the changes to a copy-on-write view,
+    // keeping its memory protection, and that other hints keep the content.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1; 3 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.fill(.., 2).unwrap();
+    view.protect(2 * page.., Access::READ).unwrap();
+
+    view.advise(.., Advice::WillNeed).unwrap();
+    view.advise(.., Advice::Sequential).unwrap();
+    view.advise(.., Advice::Random).unwrap();
+    assert!(view.iter().all(|&b| b == 2));
+
+    for advice in [Advice::HugePage, Advice::NoHugePage] {
+        match view.advise(.., advice) {
+            Err(err) if cfg!(not(target_os = "linux")) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::Unsupported)
+            }
+            res => res.unwrap(),
+        }
+    }
+    assert!(view.iter().all(|&b| b == 2));
+
+    view.advise(page.., Advice::DontNeed).unwrap();
+    assert!(view[..page].iter().all(|&b| b == 2));
+    assert!(view[page..].iter().all(|&b| b == 1));
+    assert_eq!(view.query_protection(2 * page).unwrap(), Access::READ);
+    drop(view);
+
+    let mut view = snapshot.view_mut().unwrap();
+    view.fill(.., 3).unwrap();
+    view.advise(.., Advice::DontNeed).unwrap();
+    assert!(view.iter().all(|&b| b == 3));
+
+    let err = view.advise(1..page, Advice::WillNeed).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_lock() {
+    // Test that regions of a view can be locked and unlocked, and that
+    // the scoped lock gives access to the view.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 2 * page]).unwrap();
+    let view = snapshot.view().unwrap();
+
+    match view.lock(..page) {
+        Ok(()) => {}
+        // the memlock limit of the sandbox may be too low
+        Err(err) if err.kind() == std::io::ErrorKind::OutOfMemory => return,
+        Err(err) => panic!("{err}"),
+    }
+    view.unlock(..page).unwrap();
+
+    let guard = view.lock_scoped(page..).unwrap();
+    assert!(guard.iter().all(|&b| b == 1));
+    drop(guard);
+
+    let err = view.lock(..1).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "there are no guard pages under miri")]
+fn test_view_guarded() {
+    // Test that accessing the pages right before and after a guarded view
+    // causes a segmentation fault, and that the guards survive a restore.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 2 * page]).unwrap();
+    let mut view = snapshot.view_guarded().unwrap();
+    assert_eq!(view.len(), 2 * page);
+    assert!(view.iter().all(|&b| b == 1));
+
+    view.fill(.., 2).unwrap();
+    view.restore().unwrap();
+    assert!(view.iter().all(|&b| b == 1));
+
+    let ptr = view.as_mut_ptr();
+    assert_segv!(black_box(unsafe { ptr.sub(1).read_volatile() }));
+    assert_segv!(unsafe { ptr.add(2 * page).write_volatile(1) });
+}
+
+#[test]
+fn test_view_populated() {
+    // Test that a populated view behaves like any other copy-on-write
+    // view, and that populating it doesn't make its pages dirty.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 3 * page]).unwrap();
+    let mut view = snapshot.view_populated().unwrap();
+    assert_eq!(view.len(), 3 * page);
+    assert!(view.iter().all(|&b| b == 1));
+    // dirty pages are only tracked precisely on Linux
+    let tracked = cfg!(all(target_os = "linux", not(miri)));
+    if tracked {
+        assert_eq!(view.dirty_pages().unwrap(), []);
+    }
+
+    view[page] = 2;
+    if tracked {
+        assert_eq!(view.dirty_pages().unwrap(), vec![page..2 * page]);
+    }
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 1));
+
+    view.restore().unwrap();
+    assert!(view.iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_save_to_path_open_path() {
+    // Test that a snapshot saved to a file can be opened back with the
+    // same content, including pages that are entirely zero.
+    let page = super::page_size();
+    let d = tempfile::tempdir().unwrap();
+    let path = d.path().join("snapshot");
+
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view[..5].copy_from_slice(b"hello");
+    view[2 * page..2 * page + 5].copy_from_slice(b"world");
+    drop(view);
+
+    snapshot.save_to_path(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 3 * page as u64);
+    let opened = Snapshot::open_path(&path).unwrap();
+    assert!(opened.content_eq(&snapshot).unwrap());
+
+    // Test that saving truncates an existing file, and that an empty
+    // snapshot saves an empty file.
+    let empty = Snapshot::zeroed(0).unwrap();
+    empty.save_to_path(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    assert!(Snapshot::open_path(&path)
+        .unwrap()
+        .content_eq(&empty)
+        .unwrap());
+}
+
+#[test]
+fn test_split_at_mut() {
+    // Test that a mutable view can be split into disjoint sub-views that
+    // are written from different threads.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+
+    let (mut left, mut right) = view.split_at_mut(page + 1).unwrap();
+    assert_eq!(left.len(), page + 1);
+    assert_eq!(right.len(), page - 1);
+    std::thread::scope(|s| {
+        s.spawn(|| left.as_mut_slice().fill(1));
+        s.spawn(|| right.as_mut_slice().fill(2));
+    });
+    drop((left, right));
+
+    // Test that zeroing a sub-view only zeroes its own pages.
+    let (_, mut right) = view.split_at_mut(page).unwrap();
+    right.zero_fast(..).unwrap();
+    drop(right);
+
+    assert!(view.split_at_mut(2 * page + 1).is_err());
+    drop(view);
+
+    let view = snapshot.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 1));
+    assert!(view[page..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_split_regions() {
+    // Test that a mutable view can be split into several disjoint
+    // sub-views, and that overlapping regions are rejected.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+
+    let mut parts = view.split_regions(&[2 * page..3 * page, 10..20]).unwrap();
+    parts[0].fill(.., 1).unwrap();
+    parts[1].fill(.., 2).unwrap();
+    assert_eq!(parts[1].len(), 10);
+    drop(parts);
+
+    let err = view
+        .split_regions(&[0..page, page - 1..2 * page])
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(view.split_regions(&[0..page, page..3 * page + 1]).is_err());
+
+    assert!(view[..10].iter().all(|&b| b == 0));
+    assert!(view[10..20].iter().all(|&b| b == 2));
+    assert!(view[2 * page..].iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_pages() {
+    // Test that the pages of a view are iterated in order, with the
+    // last page of a sub-view being shorter.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    assert_eq!(view.page_count(), 3);
+
+    for (idx, page) in view.pages_mut().enumerate() {
+        page.fill(idx as u8);
+    }
+    assert_eq!(view.pages().count(), 3);
+    for (idx, page) in view.pages().enumerate() {
+        assert!(page.iter().all(|&b| b == idx as u8));
+    }
+
+    let (_, right) = view.split_at_mut(page + 1).unwrap();
+    assert_eq!(right.page_count(), 2);
+    let lens: Vec<_> = right.pages().map(|page| page.len()).collect();
+    assert_eq!(lens, [page, page - 1]);
+}
+
+#[test]
+fn test_take_snapshot_unmodified() {
+    // Test that a snapshot taken from an unmodified view has the content
+    // of the root snapshot, and that both stay independent when either is
+    // modified afterwards.
+    let page = super::page_size();
+    let mut snapshot1 = Snapshot::from_slice(b"hello world").unwrap();
+    let mut snapshot2 = snapshot1.view().unwrap().take_snapshot().unwrap();
+    assert!(snapshot2.content_eq(&snapshot1).unwrap());
+
+    snapshot1.view_mut().unwrap()[..5].copy_from_slice(b"jello");
+    assert_eq!(&snapshot2.view().unwrap()[..11], b"hello world");
+    snapshot2.view_mut().unwrap()[..5].copy_from_slice(b"yello");
+    assert_eq!(&snapshot1.view().unwrap()[..11], b"jello world");
+
+    let snapshot3 = snapshot1.view().unwrap().take_snapshot().unwrap();
+    snapshot1.grow(2 * page).unwrap();
+    assert_eq!(snapshot3.view().unwrap().capacity(), page);
+    assert_eq!(&snapshot3.view().unwrap()[..11], b"jello world");
+
+    // Test that modified and zeroed views are copied.
+    let mut view = snapshot3.view().unwrap();
+    view[0] = b'm';
+    let snapshot4 = view.take_snapshot().unwrap();
+    assert_eq!(&snapshot4.view().unwrap()[..11], b"mello world");
+    view.restore().unwrap();
+    view.zero_fast(..).unwrap();
+    let snapshot5 = view.take_snapshot().unwrap();
+    assert!(snapshot5.view().unwrap().iter().all(|&b| b == 0));
+    view.restore().unwrap();
+    assert_eq!(&view[..11], b"jello world");
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "userfaultfd is not supported under miri")]
+fn test_view_lazy() {
+    // Test that the pages of a lazy view are populated by the provider on
+    // first access, once for each page, even with concurrent accesses.
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let view = snapshot.view_lazy(move |offset, buf| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        buf.fill((offset / super::page_size()) as u8 + 1);
+    });
+    let mut view = match view {
+        Ok(view) => view,
+        // userfaultfd may be unsupported, or need privileges
+        Err(err) if err.kind() == std::io::ErrorKind::Unsupported => return,
+        Err(err) if err.raw_os_error().is_some() => return,
+        Err(err) => panic!("{err}"),
+    };
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+
+    assert_eq!(view[2 * page], 3);
+    assert_eq!(view[2 * page + 1], 3);
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| assert!(view[..page].iter().all(|&b| b == 1)));
+        }
+    });
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    view[3 * page] = 0;
+    assert_eq!(view[3 * page + 1], 4);
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+    view.restore().unwrap();
+    assert!(view.iter().all(|&b| b == 0));
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_try_clone_cow() {
+    // Test that a clone has the same content as the original, whether it
+    // was created with a reflink or not, and that both are independent.
+    let d = tempfile::tempdir().unwrap();
+    let mut f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.write_all(b"hello world").unwrap();
+
+    for mut snapshot in [
+        Snapshot::from_file(f).unwrap(),
+        Snapshot::from_slice(b"hello world").unwrap(),
+    ] {
+        let (mut clone, _) = snapshot.try_clone_cow().unwrap();
+        assert!(clone.content_eq(&snapshot).unwrap());
+
+        clone.view_mut().unwrap()[..5].copy_from_slice(b"jello");
+        snapshot.view_mut().unwrap()[6..].fill(0);
+        assert_eq!(&clone.view().unwrap()[..11], b"jello world");
+        assert_eq!(&snapshot.view().unwrap()[..6], b"hello ");
+    }
+}
+
+#[test]
+fn test_copy_from() {
+    // Test that regions are copied between views, whether they are
+    // page-aligned or not, and that invalid ranges are rejected.
+    let page = super::page_size();
+    let src = Snapshot::from_slice(&vec![1; 2 * page]).unwrap();
+    let mut dst = Snapshot::zeroed(3 * page).unwrap();
+
+    let src_view = src.view().unwrap();
+    let mut dst_view = dst.view_mut().unwrap();
+    assert_eq!(dst_view.copy_from(&src_view, .., page).unwrap(), 2 * page);
+    assert_eq!(dst_view.copy_from(&src_view, 1..11, 0).unwrap(), 10);
+    drop(dst_view);
+    let view = dst.view().unwrap();
+    assert!(view[..10].iter().all(|&b| b == 1));
+    assert!(view[10..page].iter().all(|&b| b == 0));
+    assert!(view[page..].iter().all(|&b| b == 1));
+    drop(view);
+
+    // Test that the changes of a copy-on-write view are copied.
+    let mut src_view = src.view().unwrap();
+    src_view[..page].fill(2);
+    let mut dst_view = dst.view_mut().unwrap();
+    dst_view.copy_from(&src_view, ..page, 2 * page).unwrap();
+    assert!(dst_view[2 * page..].iter().all(|&b| b == 2));
+
+    let err = dst_view.copy_from(&src_view, .., 2 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(dst_view.copy_from(&src_view, ..3 * page, 0).is_err());
+
+    // Test that copying into a copy-on-write view doesn't modify its root
+    // snapshot.
+    drop(dst_view);
+    let mut dst_view = dst.view().unwrap();
+    dst_view.copy_from(&src.view().unwrap(), ..page, 0).unwrap();
+    assert!(dst_view[..page].iter().all(|&b| b == 1));
+    assert!(dst.view().unwrap()[10..page].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_zeroed_in() {
+    // Test that a snapshot can be backed by a file in a given directory,
+    // and that the file doesn't show up in the directory.
+    let dir = tempfile::tempdir().unwrap();
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed_in(page + 1, dir.path()).unwrap();
+    assert_eq!(snapshot.size, 2 * page);
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 0));
+
+    snapshot.view_mut().unwrap()[..5].copy_from_slice(b"hello");
+    let mut view = snapshot.view().unwrap();
+    view[..5].copy_from_slice(b"world");
+    assert_eq!(&view[..5], b"world");
+    assert_eq!(&snapshot.view().unwrap()[..5], b"hello");
+    drop(view);
+
+    #[cfg(not(windows))]
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    assert!(Snapshot::zeroed_in(page, dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_try_clone_shared() {
+    // Test that a shared clone starts with the content of its parent, and
+    // that modifying either of them doesn't modify the other.
+    let page = super::page_size();
+    let mut base = Snapshot::from_slice(&vec![1; 2 * page]).unwrap();
+    let mut layer = base.try_clone_shared().unwrap();
+    let mut top = layer.try_clone_shared().unwrap();
+    assert!(top.view().unwrap().iter().all(|&b| b == 1));
+
+    base.view_mut().unwrap()[..page].fill(2);
+    layer.view_mut().unwrap()[page..].fill(3);
+    assert!(base.view().unwrap()[..page].iter().all(|&b| b == 2));
+    assert!(base.view().unwrap()[page..].iter().all(|&b| b == 1));
+    assert!(layer.view().unwrap()[..page].iter().all(|&b| b == 1));
+    assert!(layer.view().unwrap()[page..].iter().all(|&b| b == 3));
+    assert!(top.view().unwrap().iter().all(|&b| b == 1));
+
+    top.view_mut().unwrap()[..page].fill(4);
+    assert!(top.view().unwrap()[..page].iter().all(|&b| b == 4));
+    assert!(layer.view().unwrap()[..page].iter().all(|&b| b == 1));
+
+    // Test that taking a snapshot of a view of a shared clone flattens it.
+    let mut view = layer.view().unwrap();
+    view[..page].fill(5);
+    let flat = view.take_snapshot().unwrap();
+    drop(view);
+    assert!(flat.view().unwrap()[..page].iter().all(|&b| b == 5));
+    assert!(flat.view().unwrap()[page..].iter().all(|&b| b == 3));
+}
+
+#[test]
+fn test_checkpoint() {
+    // Test that rolling back to a checkpoint reverts the changes made
+    // since, and only stashes the pages that were modified.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1; 8 * page]).unwrap();
+    let first = snapshot.checkpoint();
+    snapshot.view_mut().unwrap()[..page].fill(2);
+    let second = snapshot.checkpoint();
+    let mut view = snapshot.view_mut().unwrap();
+    view[..page].fill(3);
+    view[2 * page..3 * page].fill(3);
+    view.protect(2 * page..3 * page, Access::NONE).unwrap();
+    drop(view);
+    assert_eq!(snapshot.checkpoint_count(), 2);
+    assert_eq!(snapshot.view().unwrap()[2 * page], 3);
+
+    let checkpoints = snapshot.checkpoints.as_ref().unwrap();
+    assert_eq!(checkpoints.stashed_pages(), [1, 2]);
+
+    snapshot.rollback(second).unwrap();
+    let view = snapshot.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 2));
+    assert!(view[page..].iter().all(|&b| b == 1));
+    drop(view);
+
+    snapshot.view_mut().unwrap()[page..2 * page].fill(4);
+    snapshot.rollback(first).unwrap();
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 1));
+    assert_eq!(snapshot.checkpoint_count(), 1);
+    let err = snapshot.rollback(second).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Test that releasing a checkpoint keeps the changes, which can still
+    // be reverted by an earlier checkpoint.
+    let second = snapshot.checkpoint();
+    snapshot.view_mut().unwrap()[..page].fill(5);
+    snapshot.release_checkpoint(second).unwrap();
+    assert_eq!(snapshot.view().unwrap()[0], 5);
+    snapshot.rollback(first).unwrap();
+    assert_eq!(snapshot.view().unwrap()[0], 1);
+    snapshot.release_checkpoint(first).unwrap();
+    assert_eq!(snapshot.checkpoint_count(), 0);
+
+    // Test that without checkpoints mutable views write to the snapshot
+    // directly again.
+    let mut view = snapshot.view_mut().unwrap();
+    view[0] = 6;
+    assert_eq!(view.mode, super::ViewMode::Mutable);
+}
+
+#[test]
+fn test_view_fill() {
+    // Test that regions of copy-on-write and mutable views are filled,
+    // and that out of bounds regions are rejected.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1; 2 * page]).unwrap();
+
+    let mut view = snapshot.view().unwrap();
+    view.fill(10..20, 7).unwrap();
+    assert!(view[10..20].iter().all(|&b| b == 7));
+    assert_eq!(view[9], 1);
+    assert_eq!(view[20], 1);
+    view.zero(..).unwrap();
+    assert!(view.iter().all(|&b| b == 0));
+    drop(view);
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 1));
+
+    let mut view = snapshot.view_mut().unwrap();
+    view.fill(page.., 3).unwrap();
+    view.zero(..page).unwrap();
+    let err = view.fill(page..2 * page + 1, 3).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(view.zero(2 * page..3 * page).is_err());
+    drop(view);
+
+    let view = snapshot.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 0));
+    assert!(view[page..].iter().all(|&b| b == 3));
+}
+
+#[test]
+fn test_view_copy_within() {
+    // Test that bytes are moved within a view, with overlapping regions,
+    // and that out of bounds regions are rejected.
+    let mut snapshot = Snapshot::from_slice(b"0123456789").unwrap();
+    let len = 10;
+    let mut view = snapshot.view_mut().unwrap();
+    view.copy_within(0..6, 2).unwrap();
+    assert_eq!(&view[..], b"0101234589");
+    view.copy_within(2..8, 0).unwrap();
+    assert_eq!(&view[..], b"0123454589");
+    view.copy_within(len - 1.., 0).unwrap();
+    assert_eq!(view[0], b'9');
+
+    let err = view.copy_within(0..2, len - 1).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(view.copy_within(..len + 1, 0).is_err());
+    assert!(view.copy_within(.., usize::MAX).is_err());
+}
+
+#[test]
+fn test_exec_view() {
+    // Test that code written to a mutable view can be executed once the
+    // view is made executable, and that the region is no longer writable.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    // mov eax, 42; ret
+    view[page..page + 6].copy_from_slice(&[0xb8, 0x2a, 0x00, 0x00, 0x00, 0xc3]);
+
+    view.make_executable(page..).unwrap();
+    assert_eq!(
+        view.query_protection(page).unwrap(),
+        Access::READ | Access::EXEC
+    );
+
+    let exec = view.into_executable(page..).unwrap();
+    assert_eq!(exec.region(), page..2 * page);
+    assert_eq!(exec[page], 0xb8);
+    #[cfg(all(target_arch = "x86_64", not(miri)))]
+    {
+        let f: extern "C" fn() -> i32 = unsafe { std::mem::transmute(exec.as_ptr()) };
+        assert_eq!(f(), 42);
+    }
+
+    let mut view = exec.into_mut().unwrap();
+    view[page] = 0;
+    assert!(view.into_executable(page..page + 1).is_err());
+}
+
+#[test]
+fn test_exec_view_past_len() {
+    // Test that the executable region of a view can extend past its
+    // length, up to its capacity.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&[0xc3; 100]).unwrap();
+    let view = snapshot.view_mut().unwrap();
+    let ptr = view.as_ptr();
+    let exec = view.into_executable(..).unwrap();
+    assert_eq!(exec.region(), 0..page);
+    assert_eq!(exec.as_ptr(), ptr);
+    assert_eq!(exec.len(), page);
+    assert_eq!(exec[99], 0xc3);
+    #[cfg(all(target_arch = "x86_64", not(miri)))]
+    {
+        let f: extern "C" fn() = unsafe { std::mem::transmute(exec.as_ptr()) };
+        f();
+    }
+}
+
+#[test]
+fn test_view_find() {
+    // Test that patterns are found anywhere in a view, including across
+    // page boundaries.
+    let page = super::page_size();
+    let mut buf = vec![0; 3 * page];
+    buf[page - 2..page + 2].copy_from_slice(b"abcd");
+    buf[2 * page + 10..2 * page + 14].copy_from_slice(b"abcd");
+    let snapshot = Snapshot::from_slice(&buf).unwrap();
+    let view = snapshot.view().unwrap();
+
+    assert_eq!(view.find(b"abcd"), Some(page - 2));
+    assert_eq!(view.find(b"d"), Some(page + 1));
+    assert_eq!(view.find(b""), Some(0));
+    assert_eq!(view.find(b"abce"), None);
+    assert_eq!(view.find_all(b"abcd"), [page - 2, 2 * page + 10]);
+    assert_eq!(view.find_all(&[0; 2]).len(), (3 * page - 8) / 2);
+    assert!(view.find_all(b"x").is_empty());
+}
+
+#[test]
+fn test_bind_numa() {
+    // Test that a view can be bound to the first NUMA node, which always
+    // exists, but not to a node that doesn't.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 4 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+    let err = view.bind_numa(1..page, 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    match view.bind_numa(.., 0) {
+        Err(err) if err.kind() == std::io::ErrorKind::Unsupported => return,
+        res => res.unwrap(),
+    }
+    view.bind_numa(page..3 * page, 0).unwrap();
+    view.fill(.., 2).unwrap();
+    assert!(view.iter().all(|&b| b == 2));
+
+    let err = view.bind_numa(.., 1 << 20).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_residency() {
+    // Test that every page overlapped by a region is reported, and that
+    // accessed pages are resident.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.lock(page..2 * page).unwrap();
+    view[3 * page] = 1;
+
+    let residency = view.residency(..).unwrap();
+    assert_eq!(residency.len(), 4);
+    assert!(residency[1] && residency[3]);
+    assert_eq!(view.residency(page + 1..3 * page + 1).unwrap().len(), 3);
+    assert_eq!(view.residency(page + 1..page + 2).unwrap(), [true]);
+    assert!(view.residency(page..page).unwrap().is_empty());
+
+    let err = view.residency(..5 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_shared_fd() {
+    // Test that a snapshot created from the shared file descriptor of
+    // another sees the writes of its mutable views, but not those of its
+    // copy-on-write views.
+    #[cfg(unix)]
+    let fd = |snapshot: &Snapshot| unsafe {
+        std::os::fd::BorrowedFd::borrow_raw(snapshot.as_raw_fd_shared()).try_clone_to_owned()
+    };
+    #[cfg(windows)]
+    let fd = |snapshot: &Snapshot| unsafe {
+        std::os::windows::io::BorrowedHandle::borrow_raw(snapshot.as_raw_fd_shared())
+            .try_clone_to_owned()
+    };
+
+    let page = super::page_size();
+    let mut parent = Snapshot::zeroed(2 * page).unwrap();
+    let mut child = Snapshot::from_shared_fd(fd(&parent).unwrap(), 2 * page).unwrap();
+    assert_eq!(child.size, 2 * page);
+
+    parent.view_mut().unwrap()[..5].copy_from_slice(b"hello");
+    assert_eq!(&child.view().unwrap()[..5], b"hello");
+    child.view_mut().unwrap()[page..page + 5].copy_from_slice(b"world");
+    assert_eq!(&parent.view().unwrap()[page..page + 5], b"world");
+
+    let mut view = child.view().unwrap();
+    view[0] = b'j';
+    assert_eq!(&parent.view().unwrap()[..5], b"hello");
+
+    #[cfg(unix)]
+    {
+        let err = Snapshot::from_shared_fd(fd(&parent).unwrap(), 3 * page).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+#[cfg(all(unix, not(miri)))]
+fn test_from_raw() {
+    // Test that a snapshot adopts an existing file descriptor without
+    // copying its content, and rounds its size up to whole pages.
+    use std::os::unix::fs::FileExt as _;
+
+    let page = super::page_size();
+    let file = tempfile::tempfile().unwrap();
+    file.set_len(3 * page as u64).unwrap();
+    file.write_all_at(b"hello", 0).unwrap();
+    let fd = std::os::fd::OwnedFd::from(file.try_clone().unwrap());
+
+    let mut snapshot = Snapshot::from_raw(fd, page + 1).unwrap();
+    assert_eq!(snapshot.size, 2 * page);
+    assert_eq!(&snapshot.view().unwrap()[..5], b"hello");
+    snapshot.view_mut().unwrap()[page..page + 5].copy_from_slice(b"world");
+    let mut buf = [0; 5];
+    file.read_exact_at(&mut buf, page as u64).unwrap();
+    assert_eq!(&buf, b"world");
+
+    let fd = std::os::fd::OwnedFd::from(file);
+    let err = Snapshot::from_raw(fd, 4 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_async_constructors() {
+    // Test that the async constructors complete with the same snapshot as
+    // their blocking counterparts, and report their errors.
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+        let waker = Arc::new(Unpark(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    let page = super::page_size();
+    let d = tempfile::tempdir().unwrap();
+    let path = d.path().join("snapshot");
+    let mut content = vec![0; 2 * page];
+    content[page..page + 5].copy_from_slice(b"hello");
+    std::fs::write(&path, &content).unwrap();
+
+    let snapshot = block_on(Snapshot::from_path_async(&path)).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), content);
+    let file = std::fs::File::open(&path).unwrap();
+    let snapshot = block_on(Snapshot::from_file_async(&file)).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), content);
+    let snapshot = block_on(Snapshot::from_file_async(file)).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), content);
+    let reader = std::io::Cursor::new(content.clone());
+    let snapshot = block_on(Snapshot::read_from_async(reader, 2 * page)).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), content);
+
+    let err = block_on(Snapshot::from_path_async(d.path().join("missing"))).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_snapshot_error() {
+    // Test that invalid ranges are reported with a typed error that can be
+    // recovered from the returned io error, keeping its error kind.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    let err = view.protect(1..page, Access::READ).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::NotPageAligned
+    ));
+    let err = view.protect(..3 * page, Access::READ).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+
+    let err = std::io::Error::from(SnapshotError::Unsupported("unsupported"));
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert_eq!(err.to_string(), "unsupported");
+    let err = SnapshotError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+    let SnapshotError::Os(err) = err else {
+        panic!("expected an os error, got {err:?}");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_range_errors_typed() {
+    // Test that the byte-level and page-level methods report out of bounds
+    // ranges with the typed error, not only protect.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(2 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    let errors = [
+        view.fill(..2 * page + 1, 1).unwrap_err(),
+        view.copy_within(..2, 2 * page).unwrap_err(),
+        view.zero_fast(page..2 * page + 1).unwrap_err(),
+        view.residency(..3 * page).unwrap_err(),
+        view.query_protection(2 * page).unwrap_err(),
+        view.logical_protect(..3 * page, Access::READ).unwrap_err(),
+    ];
+    for err in errors {
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            SnapshotError::from(err),
+            SnapshotError::RangeOutOfBounds
+        ));
+    }
+}
+
+#[test]
+fn test_range_overflow() {
+    // Test that ranges extending past `usize::MAX` are reported as out of
+    // bounds, rather than overflowing.
+    use std::ops::Bound;
+
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    let past_end = (Bound::Excluded(usize::MAX), Bound::Unbounded);
+
+    let errors = [
+        view.slice(0..=usize::MAX).unwrap_err(),
+        view.slice(past_end).unwrap_err(),
+        view.fill(0..=usize::MAX, 1).unwrap_err(),
+        view.zero(0..=usize::MAX).unwrap_err(),
+        view.fill_nontemporal(0..=usize::MAX, 1).unwrap_err(),
+        view.copy_within(0..=usize::MAX, 0).unwrap_err(),
+        view.copy_within(past_end, 0).unwrap_err(),
+        view.region(0..=usize::MAX).unwrap_err(),
+        view.region(..page)
+            .unwrap()
+            .region(0..=usize::MAX)
+            .unwrap_err(),
+        view.subview(0..=usize::MAX).unwrap_err(),
+        view.subview(past_end).unwrap_err(),
+    ];
+    for err in errors {
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            SnapshotError::from(err),
+            SnapshotError::SliceOutOfBounds { len, .. } if len == page
+        ));
+    }
+    assert!(view.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_view_stats() {
+    // Test that the pages written to a copy-on-write view are reported as
+    // copied, and that faults are counted from the creation of the view.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1; 4 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+    let before = view.stats().unwrap();
+    view[0] = 2;
+    view[2 * page] = 2;
+
+    let stats = view.stats().unwrap();
+    assert!(stats.minor_faults >= before.minor_faults);
+    if cfg!(all(target_os = "linux", not(miri))) {
+        assert_eq!(stats.copied_pages, 2);
+    }
+    view.restore().unwrap();
+    if cfg!(all(target_os = "linux", not(miri))) {
+        assert_eq!(view.stats().unwrap().copied_pages, 0);
+    }
+    drop(view);
+
+    let mut view = snapshot.view_mut().unwrap();
+    view[0] = 3;
+    assert_eq!(view.stats().unwrap().copied_pages, 0);
+}
+
+#[test]
+fn test_logical_len() {
+    // Test that views report the logical length of their snapshot, while
+    // the rest of the capacity stays mapped and page-based methods still
+    // cover it.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(b"hello slice").unwrap();
+    snapshot.set_logical_len(11).unwrap();
+    assert_eq!(snapshot.logical_len(), 11);
+
+    let mut view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 11);
+    assert_eq!(view.capacity(), page);
+    assert_eq!(view.as_slice(), b"hello slice");
+    assert_eq!(&view[..], b"hello slice");
+    assert_eq!(view.as_full_slice().len(), page);
+    assert_eq!(view.get(11), None);
+    assert!(view.fill(..12, 1).is_err());
+    view.as_full_mut_slice()[page - 1] = 1;
+    view.protect(.., Access::READ).unwrap();
+    view.restore().unwrap();
+    assert_eq!(view.as_full_slice()[page - 1], 0);
+    drop(view);
+
+    let clone = snapshot.try_clone().unwrap();
+    assert_eq!(clone.view().unwrap().len(), 11);
+    assert!(clone.content_eq(&snapshot).unwrap());
+    let mut view = snapshot.view_mut().unwrap();
+    assert_eq!(view.len(), 11);
+    view[..5].copy_from_slice(b"jello");
+    drop(view);
+    assert_eq!(snapshot.view().unwrap().as_slice(), b"jello slice");
+
+    snapshot.grow(2 * page).unwrap();
+    assert_eq!(snapshot.view().unwrap().len(), 2 * page);
+    let err = snapshot.set_logical_len(2 * page + 1).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_subview() {
+    // Test that a sub-view is a view of its own over a region of a view,
+    // and that protecting and restoring it doesn't touch the memory
+    // around it.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 4 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.fill(.., 2).unwrap();
+
+    let mut sub = view.subview(page..3 * page).unwrap();
+    assert_eq!(sub.len(), 2 * page);
+    sub.fill(.., 3).unwrap();
+    let taken = sub.take_snapshot().unwrap();
+    assert!(taken.view().unwrap().iter().all(|&b| b == 3));
+    sub.protect(..page, Access::READ).unwrap();
+    sub.restore().unwrap();
+    assert!(sub.iter().all(|&b| b == 1));
+    sub.fill(..page, 4).unwrap();
+    drop(sub);
+
+    // the pages around the sub-view keep their content and are still
+    // writable
+    view.fill(..page, 5).unwrap();
+    view.fill(3 * page.., 5).unwrap();
+    assert!(view[..page].iter().all(|&b| b == 5));
+    assert!(view[page..2 * page].iter().all(|&b| b == 4));
+    assert!(view[2 * page..3 * page].iter().all(|&b| b == 1));
+    assert!(view[3 * page..].iter().all(|&b| b == 5));
+
+    let mut sub = view.subview(1..page).unwrap();
+    assert_eq!(sub[0], 5);
+    let err = sub.restore().unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::NotPageAligned
+    ));
+    drop(sub);
+
+    let err = view.subview(..4 * page + 1).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+}
+
+#[test]
+fn test_from_reader() {
+    // Test that a snapshot can be read from a reader of unknown size, and
+    // that its logical length is the number of bytes read.
+    let page = super::page_size();
+    let data: Vec<u8> = (0..5 * page + 7).map(|i| (i % 251) as u8).collect();
+    let snapshot = Snapshot::from_reader(&data[..]).unwrap();
+    assert_eq!(snapshot.logical_len(), data.len());
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.capacity(), 6 * page);
+    assert_eq!(view.as_slice(), &data[..]);
+    drop(view);
+
+    // Test that short reads and zero pages are handled.
+    let mut data = vec![0; 2 * page];
+    data[page + 1] = 1;
+    let reader = std::io::Read::chain(&data[..3], &data[3..]);
+    let snapshot = Snapshot::from_reader(reader).unwrap();
+    assert_eq!(snapshot.view().unwrap().as_slice(), &data[..]);
+
+    // Test that an empty reader gives an empty snapshot.
+    let snapshot = Snapshot::from_reader(std::io::empty()).unwrap();
+    assert_eq!(snapshot.view().unwrap().len(), 0);
+}
+
+#[test]
+fn test_zeroize() {
+    // Test that zeroizing a snapshot clears its content and the content
+    // stashed in its checkpoints.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![7; 2 * page]).unwrap();
+    snapshot.checkpoint();
+    snapshot.view_mut().unwrap().fill(.., 8).unwrap();
+    snapshot.zeroize().unwrap();
+    assert_eq!(snapshot.checkpoint_count(), 0);
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 0));
+
+    // Test that zeroizing a snapshot sharing its backing leaves the
+    // other snapshots untouched.
+    let mut snapshot = Snapshot::from_slice(&vec![7; page]).unwrap();
+    let clone = snapshot.try_clone_shared().unwrap();
+    snapshot.zeroize().unwrap();
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 0));
+    assert!(clone.view().unwrap().iter().all(|&b| b == 7));
+
+    // Test that the wrapper zeroizes the snapshot when dropped, and can
+    // be unwrapped without zeroizing it.
+    let d = tempfile::tempdir().unwrap();
+    let path = d.path().join("secret");
+    std::fs::write(&path, vec![9; page]).unwrap();
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    let snapshot = ZeroizeOnDrop::new(Snapshot::from_file(file).unwrap());
+    assert!(snapshot.view().unwrap().iter().all(|&b| b == 9));
+    let snapshot = ZeroizeOnDrop::new(snapshot.into_inner());
+    drop(snapshot);
+    if cfg!(not(miri)) {
+        assert!(std::fs::read(&path).unwrap().iter().all(|&b| b == 0));
+    }
+}
+
+#[test]
+fn test_swap() {
+    // Test that swapping snapshots swaps their content, size and logical
+    // length.
+    let page = super::page_size();
+    let mut active = Snapshot::from_slice(&vec![1; page]).unwrap();
+    let mut standby = Snapshot::from_slice(&vec![2; 2 * page]).unwrap();
+    standby.set_logical_len(page + 1).unwrap();
+    let fd = active.as_raw_fd();
+
+    active.swap(&mut standby);
+    assert_eq!(standby.as_raw_fd(), fd);
+    assert_eq!(active.logical_len(), page + 1);
+    assert!(active
+        .view()
+        .unwrap()
+        .as_full_slice()
+        .iter()
+        .all(|&b| b == 2));
+    assert_eq!(active.view().unwrap().capacity(), 2 * page);
+    assert!(standby.view().unwrap().iter().all(|&b| b == 1));
+    assert_eq!(standby.view().unwrap().len(), page);
+}
+
+#[test]
+fn test_read_write_at() {
+    // Test that typed values can be read and written at unaligned
+    // offsets, and that out of bounds offsets are rejected.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(page).unwrap();
+    let mut view = snapshot.view().unwrap();
+    view.write_at(1, 0x1234_5678u32.to_le()).unwrap();
+    assert_eq!(&view[1..5], &[0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(u32::from_le(view.read_at(1).unwrap()), 0x1234_5678);
+    assert_eq!(
+        view.read_at::<u16>(2).unwrap(),
+        u16::from_ne_bytes([0x56, 0x34])
+    );
+
+    view.write_at(page - 8, [1.5f32, -2.0]).unwrap();
+    assert_eq!(view.read_at::<[f32; 2]>(page - 8).unwrap(), [1.5, -2.0]);
+
+    let err = view.read_at::<u64>(page - 7).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+    assert!(view.write_at(usize::MAX, 0u8).is_err());
+}
+
+#[test]
+fn test_snapshot_pool() {
+    // Test that a pool recycles the backing of dropped snapshots, zeroed,
+    // and keeps at most `max_idle` of them.
+    let page = super::page_size();
+    let pool = SnapshotPool::new(2 * page - 1, 1);
+    assert_eq!(pool.size(), 2 * page);
+
+    let mut snapshot = pool.acquire().unwrap();
+    snapshot.view_mut().unwrap().fill(.., 1).unwrap();
+    snapshot.set_logical_len(1).unwrap();
+    let fd = snapshot.as_raw_fd();
+    let other = pool.acquire().unwrap();
+    drop(snapshot);
+    drop(other);
+    assert_eq!(pool.idle_count(), 1);
+
+    let snapshot = pool.acquire().unwrap();
+    assert_eq!(pool.idle_count(), 0);
+    assert_eq!(snapshot.as_raw_fd(), fd);
+    let view = snapshot.view().unwrap();
+    assert_eq!(view.len(), 2 * page);
+    assert!(view.iter().all(|&b| b == 0));
+    drop(view);
+
+    // Test that snapshots taken out of the pool, or resized, are not
+    // returned to it.
+    let snapshot = snapshot.into_inner();
+    drop(snapshot);
+    let mut snapshot = pool.acquire().unwrap();
+    snapshot.grow(3 * page).unwrap();
+    drop(snapshot);
+    assert_eq!(pool.idle_count(), 0);
+
+    // Test that snapshots replaced by snapshots that were not created by
+    // the pool are not returned to it, and their backing is not zeroed.
+    let d = tempfile::tempdir().unwrap();
+    let mut f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.write_all(&vec![1; 2 * page]).unwrap();
+    let mut snapshot = pool.acquire().unwrap();
+    *snapshot = Snapshot::from_file(f.try_clone().unwrap()).unwrap();
+    drop(snapshot);
+    assert_eq!(pool.idle_count(), 0);
+    let content = std::fs::read(d.path().join("tempfile")).unwrap();
+    assert!(content.iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_mut_view_baseline() {
+    // Test that a mutable view can be reverted to a baseline, more than
+    // once, and that the changes are reflected in the snapshot.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(&vec![1; 3 * page]).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    assert!(view.revert_to_baseline().is_err());
+    view.fill(page.., 2).unwrap();
+    view.checkpoint_baseline().unwrap();
+    for _ in 0..2 {
+        view.fill(..2 * page, 3).unwrap();
+        view.revert_to_baseline().unwrap();
+        assert!(view[..page].iter().all(|&b| b == 1));
+        assert!(view[page..].iter().all(|&b| b == 2));
+    }
+    view.fill(.., 4).unwrap();
+    view.revert_to_baseline().unwrap();
+    drop(view);
+    assert!(snapshot.view().unwrap()[page..].iter().all(|&b| b == 2));
+
+    // Test that the baseline of a mutable view of a snapshot with
+    // checkpoints includes the changes not yet written back.
+    snapshot.checkpoint();
+    let mut view = snapshot.view_mut().unwrap();
+    view.fill(..page, 5).unwrap();
+    view.checkpoint_baseline().unwrap();
+    view.fill(.., 6).unwrap();
+    view.revert_to_baseline().unwrap();
+    drop(view);
+    let view = snapshot.view().unwrap();
+    assert!(view[..page].iter().all(|&b| b == 5));
+    assert!(view[page..].iter().all(|&b| b == 2));
+}
+
+#[test]
+fn test_arc_view_clone() {
+    // Test that cloning an `ArcView` maps the snapshot again, so every
+    // clone has its own copy-on-write pages.
+    let snapshot = Arc::new(Snapshot::from_slice(b"shared base").unwrap());
+    let mut view = snapshot.view_arc().unwrap();
+    view[0] = b'S';
+    let mut clone = view.clone();
+    assert_ne!(clone.as_ptr(), view.as_ptr());
+    assert_eq!(&clone[..11], b"shared base");
+    clone[1] = b'H';
+    assert_eq!(&view[..11], b"Shared base");
+    assert_eq!(&snapshot.view().unwrap()[..11], b"shared base");
+
+    let handle = std::thread::spawn(move || clone[..2].to_vec());
+    assert_eq!(handle.join().unwrap(), b"sH");
+
+    let clone = view.try_clone_view().unwrap();
+    assert_ne!(clone.as_ptr(), view.as_ptr());
+    assert_eq!(&clone[..11], b"shared base");
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_view_readonly() {
+    // Test that a read-only view can be read, but writing to it faults.
+    let snapshot = Snapshot::from_slice(b"read only").unwrap();
+    let view = snapshot.view_readonly().unwrap();
+    assert_eq!(&view[..], b"read only");
+    assert_eq!(view.as_slice().len(), 9);
+    assert!(view.starts_with(b"read"));
+
+    let ptr = view.as_ptr() as *mut u8;
+    assert_segv!(unsafe { ptr.write_volatile(1) });
+
+    let empty = Snapshot::from_slice(&[]).unwrap();
+    assert!(empty.view_readonly().unwrap().is_empty());
+}
+
+#[test]
+fn test_commit() {
+    // Test that committing a region populates it without changing its
+    // content, and that it can be committed again.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 3 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    // Test that read-only and unaligned regions are rejected.
+    view.protect(..page, Access::READ).unwrap();
+    let err = view.commit(..2 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    let err = view.commit(1..page).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::NotPageAligned
+    ));
+
+    match view.commit(page..3 * page) {
+        Ok(()) => {}
+        // the memlock limit of the sandbox may be too low
+        Err(err) if err.kind() == std::io::ErrorKind::OutOfMemory => return,
+        Err(err) => panic!("{err}"),
+    }
+    view.commit(page..3 * page).unwrap();
+    assert!(view.iter().all(|&b| b == 1));
+    if cfg!(all(target_os = "linux", not(miri))) {
+        assert_eq!(view.dirty_pages().unwrap(), vec![page..3 * page]);
+    }
+    view.unlock(..).unwrap();
+}
+
+#[test]
+fn test_compact() {
+    // Test that compacting a view releases the pages that were zeroed,
+    // and that they still read as zero.
+    let page = super::page_size();
+    let linux = cfg!(all(target_os = "linux", not(miri)));
+    let mut snapshot = Snapshot::zeroed(3 * page).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view.fill(.., 1).unwrap();
+    view.fill(page..2 * page, 0).unwrap();
+    let released = view.compact().unwrap();
+    assert_eq!(released, if linux { 1 } else { 0 });
+    if linux {
+        assert_eq!(view.residency(..).unwrap(), vec![true, false, true]);
+    }
+    assert_eq!(view.compact().unwrap(), 0);
+    assert!(view[page..2 * page].iter().all(|&b| b == 0));
+    assert!(view[..page].iter().all(|&b| b == 1));
+    drop(view);
+
+    // Test that only the dirty pages of a copy-on-write view are
+    // released, and that protected pages keep their protection.
+    let mut view = snapshot.view().unwrap();
+    view.fill(2 * page.., 0).unwrap();
+    view.protect(2 * page.., Access::READ).unwrap();
+    let released = view.compact().unwrap();
+    let cow = cfg!(all(
+        any(target_os = "linux", target_os = "macos"),
+        not(miri)
+    ));
+    assert_eq!(released, if cow { 1 } else { 0 });
+    assert!(view[2 * page..].iter().all(|&b| b == 0));
+    assert_eq!(view.query_protection(2 * page).unwrap(), Access::READ);
+    assert!(view[..page].iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_try_from() {
+    // Test that snapshots can be created with `TryFrom` from a slice and
+    // from a file.
+    let snapshot: Snapshot = b"try from".as_slice().try_into().unwrap();
+    assert_eq!(&snapshot.view().unwrap()[..8], b"try from");
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"from file").unwrap();
+    let snapshot = Snapshot::try_from(file).unwrap();
+    assert_eq!(&snapshot.view().unwrap()[..9], b"from file");
+}
+
+#[test]
+fn test_shared_view() {
+    // Test that the shared views of a snapshot alias the same read-only
+    // mapping, which is mapped again once they are all dropped.
+    let snapshot = Arc::new(Snapshot::from_slice(b"shared view").unwrap());
+    let first = snapshot.shared_view().unwrap();
+    let second = snapshot.shared_view().unwrap();
+    assert_eq!(first.as_ptr(), second.as_ptr());
+    assert_eq!(first.clone().as_ptr(), first.as_ptr());
+    assert_eq!(&second[..11], b"shared view");
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || snapshot.shared_view().unwrap().as_ptr() as usize)
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), first.as_ptr() as usize);
+    }
+    drop((first, second));
+
+    let view = snapshot.shared_view().unwrap();
+    assert!(view.starts_with(b"shared"));
+    if cfg!(not(miri)) {
+        let ptr = view.as_ptr() as *mut u8;
+        assert_segv!(unsafe { ptr.write_volatile(1) });
+    }
+}
+
+#[test]
+fn test_view_slice() {
+    // Test that slicing a view with an out of bounds range returns an
+    // error with the range and the length of the view.
+    let len = 13;
+    let snapshot = Snapshot::from_slice(b"checked slice").unwrap();
+    let mut view = snapshot.view().unwrap();
+    assert_eq!(view.slice(8..13).unwrap(), b"slice");
+    assert_eq!(view.slice(..).unwrap().len(), len);
+    view.slice_mut(..7).unwrap().copy_from_slice(b"CHECKED");
+    assert_eq!(&view[..7], b"CHECKED");
+
+    let err = view.slice(len - 10..len + 30).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let msg = format!(
+        "range {}..{} out of bounds for view of length {len}",
+        len - 10,
+        len + 30
+    );
+    assert_eq!(err.to_string(), msg);
+    match SnapshotError::from(err) {
+        SnapshotError::SliceOutOfBounds { range, len: actual } => {
+            assert_eq!(range, len - 10..len + 30);
+            assert_eq!(actual, len);
+        }
+        err => panic!("unexpected error {err}"),
+    }
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = view.slice_mut(3..1);
+    assert!(reversed.is_err());
+}
+
+#[test]
+fn test_metadata() {
+    // Test that metadata is kept out of the content of a snapshot, and
+    // carried over to its clones and to the snapshots taken from its
+    // views.
+    let mut snapshot = Snapshot::from_slice(b"content").unwrap();
+    assert_eq!(snapshot.metadata(), b"");
+    snapshot.set_metadata(b"generation 7".to_vec());
+    assert_eq!(snapshot.metadata(), b"generation 7");
+    assert_eq!(snapshot.size, super::page_size());
+    assert_eq!(&snapshot.view().unwrap()[..], b"content");
+
+    assert_eq!(snapshot.try_clone().unwrap().metadata(), b"generation 7");
+    assert_eq!(
+        snapshot.try_clone_cow().unwrap().0.metadata(),
+        b"generation 7"
+    );
+    assert_eq!(
+        snapshot.try_clone_shared().unwrap().metadata(),
+        b"generation 7"
+    );
+    let mut view = snapshot.view().unwrap();
+    assert_eq!(view.take_snapshot().unwrap().metadata(), b"generation 7");
+    view[0] = b'C';
+    assert_eq!(view.take_snapshot().unwrap().metadata(), b"generation 7");
+    let sub = view.subview(..).unwrap();
+    assert_eq!(sub.take_snapshot().unwrap().metadata(), b"generation 7");
+    drop(sub);
+    drop(view);
+    let view = snapshot.view_mut().unwrap();
+    assert_eq!(view.take_snapshot().unwrap().metadata(), b"generation 7");
+    drop(view);
+
+    snapshot.set_metadata(Vec::new());
+    assert_eq!(snapshot.try_clone().unwrap().metadata(), b"");
+}
+
+#[test]
+fn test_effective_size() {
+    // Test that the mapped size is rounded up to a whole number of pages,
+    // without over-allocating for sizes that are already page-aligned,
+    // and that empty snapshots still map a page.
+    for page in [4096, 16384, 65536] {
+        super::PAGE_SIZE_OVERRIDE.set(Some(page));
+        assert_eq!(super::page_size(), page);
+        assert_eq!(super::effective_size(0), page);
+        assert_eq!(super::effective_size(1), page);
+        assert_eq!(super::effective_size(page), page);
+        assert_eq!(super::effective_size(page + 1), 2 * page);
+        assert_eq!(super::effective_size(3 * page), 3 * page);
+    }
+    super::PAGE_SIZE_OVERRIDE.set(None);
+    assert_eq!(super::page_size(), page_size::get());
+}
+
+#[test]
+fn test_snapshot_hash_eq() {
+    // Test that snapshots are compared and hashed by content, so they can
+    // key a hashed collection.
+    let snapshot1 = Snapshot::from_slice(b"artifact one").unwrap();
+    let snapshot2 = Snapshot::from_slice(b"artifact two").unwrap();
+    assert_eq!(snapshot1, snapshot1.try_clone().unwrap());
+    assert_ne!(snapshot1, snapshot2);
+    assert_eq!(
+        Snapshot::from_slice(&[]).unwrap(),
+        Snapshot::zeroed(0).unwrap()
+    );
+
+    let mut map = HashMap::new();
+    map.insert(snapshot1, 1);
+    map.insert(snapshot2, 2);
+    let key = Snapshot::from_slice(b"artifact two").unwrap();
+    assert_eq!(map.get(&key), Some(&2));
+    let mut key = Snapshot::from_slice(b"artifact one").unwrap();
+    assert_eq!(map.get(&key), Some(&1));
+    key.set_logical_len(11).unwrap();
+    assert_eq!(map.get(&key), None);
+}
+
+#[test]
+fn test_read_into_write_from() {
+    // Test that copying in and out of a view is cut short at the end of
+    // the view instead of panicking.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::from_slice(b"tail").unwrap();
+    snapshot.set_logical_len(4).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    let mut buf = [0; 8];
+    assert_eq!(view.read_into(1, &mut buf).unwrap(), 3);
+    assert_eq!(&buf[..3], b"ail");
+    assert_eq!(view.read_into(4, &mut buf).unwrap(), 0);
+    assert_eq!(view.read_into(page + 1, &mut buf).unwrap(), 0);
+
+    assert_eq!(view.write_from(2, b"lless").unwrap(), 2);
+    assert_eq!(view.as_slice(), b"tall");
+    assert_eq!(view.write_from(usize::MAX, b"x").unwrap(), 0);
+    assert_eq!(view.as_full_slice()[4], 0);
+}
+
+#[test]
+#[cfg(all(unix, not(miri)))]
+fn test_backing() {
+    // Test that the backing of a snapshot can be borrowed and taken, and
+    // that a shared backing is copied before it's taken.
+    use std::os::fd::AsRawFd as _;
+
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(b"backing").unwrap();
+    assert_eq!(snapshot.backing().as_raw_fd(), snapshot.as_raw_fd());
+    let layer = snapshot.try_clone_shared().unwrap();
+    let fd = layer.try_into_backing().unwrap();
+    assert_ne!(fd.as_raw_fd(), snapshot.as_raw_fd());
+
+    let file = std::fs::File::from(fd);
+    assert_eq!(file.metadata().unwrap().len() as usize, page);
+    let mut content = vec![0; 7];
+    std::os::unix::fs::FileExt::read_exact_at(&file, &mut content, 0).unwrap();
+    assert_eq!(content, b"backing");
+    assert_eq!(&snapshot.view().unwrap()[..7], b"backing");
+}
+
+#[test]
+fn test_restore_if_unchanged() {
+    // Test that a view is only restored when its check region still
+    // matches the root snapshot.
+    let page = super::page_size();
+    let snapshot = Snapshot::from_slice(&vec![1; 3 * page]).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    view[2 * page] = 2;
+    assert!(view.restore_if_unchanged(..page).unwrap());
+    assert_eq!(view[2 * page], 1);
+
+    // a page written with the value it already had is still unchanged
+    view[0] = 1;
+    view[page] = 2;
+    assert!(view.restore_if_unchanged(..8).unwrap());
+    assert_eq!(view[page], 1);
+
+    view[3] = 3;
+    view[page] = 2;
+    assert!(!view.restore_if_unchanged(..8).unwrap());
+    assert_eq!(view[3], 3);
+    assert_eq!(view[page], 2);
+
+    let mut sub = view.subview(page..2 * page).unwrap();
+    assert!(!sub.restore_if_unchanged(..1).unwrap());
+    assert!(sub.restore_if_unchanged(1..).unwrap());
+    assert_eq!(sub[0], 1);
+    drop(sub);
+    assert_eq!(view[3], 3);
+
+    let err = view.restore_if_unchanged(..3 * page + 1).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+}
+
+#[test]
+fn test_protect_many() {
+    // Test that several regions can be protected at once, and that none
+    // is protected if any of them is invalid.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    let err = view
+        .protect_many(&[(0..page, Access::READ), (page..page + 1, Access::NONE)])
+        .unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::NotPageAligned
+    ));
+    let err = view
+        .protect_many(&[(0..2 * page, Access::READ), (page..3 * page, Access::NONE)])
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        view.query_protection(0).unwrap(),
+        Access::READ | Access::WRITE
+    );
+
+    view.protect_many(&[
+        (3 * page..4 * page, Access::NONE),
+        (0..page, Access::READ),
+        (page..2 * page, Access::READ),
+    ])
+    .unwrap();
+    assert_eq!(
+        view.regions().collect::<Vec<_>>(),
+        vec![
+            (0..2 * page, Access::READ),
+            (2 * page..3 * page, Access::READ | Access::WRITE),
+            (3 * page..4 * page, Access::NONE),
+        ]
+    );
+    assert_eq!(view.query_protection(page).unwrap(), Access::READ);
+    assert_eq!(view.query_protection(3 * page).unwrap(), Access::NONE);
+    view[2 * page] = 1;
+}
+
+#[test]
+fn test_current_access() {
+    // Test that the protection of a region round-trips with the access
+    // passed to protect, and that regions with mixed protection fail.
+    let page = super::page_size();
+    let snapshot = Snapshot::zeroed(4 * page).unwrap();
+    let mut view = snapshot.view().unwrap();
+
+    assert_eq!(
+        view.current_access(..).unwrap(),
+        Access::READ | Access::WRITE
+    );
+    view.protect(page..3 * page, Access::READ).unwrap();
+    view.protect(3 * page.., Access::WRITE).unwrap();
+    assert_eq!(view.current_access(page..3 * page).unwrap(), Access::READ);
+    assert_eq!(
+        view.current_access(page + 1..page + 2).unwrap(),
+        Access::READ
+    );
+    assert_eq!(
+        view.current_access(3 * page..).unwrap(),
+        Access::WRITE.implied()
+    );
+    assert_eq!(
+        view.current_access(0..page).unwrap(),
+        Access::READ | Access::WRITE
+    );
+
+    let err = view.current_access(0..2 * page).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let err = view.current_access(page..page).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+    assert!(view.current_access(..4 * page + 1).is_err());
+}
+
+#[test]
+fn test_debug_preview() {
+    // Test that the debug output of snapshots and views shows a preview of
+    // the content, without reading the bytes that are protected.
+    let page = super::page_size();
+    let mut snapshot = Snapshot::zeroed(2 * page).unwrap();
+    snapshot.view_mut().unwrap()[..5].copy_from_slice(b"Hello");
+    let debug = format!("{snapshot:?}");
+    assert!(debug.contains("[48 65 6c 6c ... 00 00 00 00]"), "{debug}");
+
+    let mut view = snapshot.view().unwrap();
+    view.protect(page.., Access::NONE).unwrap();
+    let debug = format!("{view:?}");
+    assert!(debug.contains("mode: Cow"), "{debug}");
+    assert!(debug.contains("[48 65 6c 6c ... ?? ?? ?? ??]"), "{debug}");
+    drop(view);
+
+    snapshot.set_logical_len(3).unwrap();
+    assert!(format!("{snapshot:?}").contains("[48 65 6c]"));
+    snapshot.set_logical_len(0).unwrap();
+    assert!(format!("{snapshot:?}").contains("content: []"));
+}
+
+#[test]
+fn test_view_region() {
+    // Test that a region of a view is addressed from its start, and that
+    // out of bounds regions fail.
+    let mut snapshot = Snapshot::zeroed(super::page_size()).unwrap();
+    snapshot.view_mut().unwrap()[..8].copy_from_slice(b"abcdefgh");
+    let view = snapshot.view().unwrap();
+
+    let region = view.region(2..6).unwrap();
+    assert_eq!(region.offset(), 2);
+    assert_eq!(region[0], b'c');
+    assert_eq!(&region[..], b"cdef");
+    let inner = region.region(1..).unwrap();
+    assert_eq!(inner.offset(), 3);
+    assert_eq!(inner.as_slice(), b"def");
+
+    let err = region.region(2..5).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::SliceOutOfBounds { range, len: 4 } if range == (2..5)
+    ));
+    assert!(view.region(..view.len() + 1).is_err());
+}
+
+#[test]
+fn test_fill_nontemporal() {
+    // Test that filling a region with non-temporal stores sets exactly the
+    // bytes of the region, for large and small unaligned regions.
+    // Non-temporal stores are not used under miri, which is slow enough
+    // without filling a large view.
+    let len = if cfg!(miri) { 64 * 1024 } else { 512 * 1024 };
+    let mut snapshot = Snapshot::zeroed(len).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+
+    view.fill_nontemporal(3..len - 5, 0xa5).unwrap();
+    assert_eq!(&view[..3], &[0; 3]);
+    assert!(view[3..len - 5].iter().all(|&b| b == 0xa5));
+    assert_eq!(&view[len - 5..len], &[0; 5]);
+
+    view.fill_nontemporal(1..4, 0xff).unwrap();
+    assert_eq!(&view[..5], &[0, 0xff, 0xff, 0xff, 0xa5]);
+    assert!(view.fill_nontemporal(..view.len() + 1, 0).is_err());
+    drop(view);
+    assert_eq!(snapshot.view().unwrap()[len / 2], 0xa5);
+}
+
+#[test]
+fn test_content_eq_logical() {
+    // Test that comparing the logical content of snapshots ignores their
+    // sizes and the bytes past the logical length.
+    let page = super::page_size();
+    let a = Snapshot::from_slice(b"hi").unwrap();
+    let mut b = Snapshot::zeroed(2 * page).unwrap();
+    b.view_mut().unwrap()[..3].copy_from_slice(b"hi!");
+
+    assert!(!a.content_eq(&b).unwrap());
+    assert!(a.content_eq_logical(&b, 2).unwrap());
+    assert!(a.content_eq_logical(&b, 0).unwrap());
+    assert!(!a.content_eq_logical(&b, 3).unwrap());
+
+    let err = a.content_eq_logical(&b, page + 1).unwrap_err();
+    assert!(matches!(
+        SnapshotError::from(err),
+        SnapshotError::RangeOutOfBounds
+    ));
+}
+
+#[test]
+fn test_flush() {
+    // Test that flushing a mutable view of a file-backed snapshot writes
+    // its changes to the file, and that invalid regions fail.
+    let page = super::page_size();
+    let d = tempfile::tempdir().unwrap();
+    let mut f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(d.path().join("tempfile"))
+        .unwrap();
+    f.set_len(2 * page as u64).unwrap();
+    let mut snapshot = Snapshot::from_file(f.try_clone().unwrap()).unwrap();
+    let mut view = snapshot.view_mut().unwrap();
+    view[page..page + 5].copy_from_slice(b"hello");
+    view.flush(page..).unwrap();
+    view.flush(..).unwrap();
+    assert!(matches!(
+        SnapshotError::from(view.flush(1..page).unwrap_err()),
+        SnapshotError::NotPageAligned
+    ));
+    assert!(view.flush(..3 * page).is_err());
+    drop(view);
+
+    // under miri the snapshot holds a copy of the file
+    if cfg!(not(miri)) {
+        let mut content = [0; 5];
+        std::io::Seek::seek(&mut f, std::io::SeekFrom::Start(page as u64)).unwrap();
+        std::io::Read::read_exact(&mut f, &mut content).unwrap();
+        assert_eq!(&content, b"hello");
+    }
+
+    snapshot.checkpoint();
+    let view = snapshot.view_mut().unwrap();
+    let err = view.flush(..).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
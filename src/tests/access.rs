@@ -0,0 +1,78 @@
+use crate::{Access, Snapshot};
+
+/// Every combination of access permissions, together with the access that
+/// the memory actually allows after protecting a view with it, on every
+/// platform and for both copy-on-write and mutable views.
+/// Write and execute access imply read access.
+const TRUTH_TABLE: [(Access, Access); 8] = [
+    (Access::NONE, Access::NONE),
+    (Access::READ, Access::READ),
+    (Access::WRITE, Access::READ.union(Access::WRITE)),
+    (Access::EXEC, Access::READ.union(Access::EXEC)),
+    (
+        Access::READ.union(Access::WRITE),
+        Access::READ.union(Access::WRITE),
+    ),
+    (
+        Access::READ.union(Access::EXEC),
+        Access::READ.union(Access::EXEC),
+    ),
+    (Access::WRITE.union(Access::EXEC), Access::all()),
+    (Access::all(), Access::all()),
+];
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_protect_cow_truth_table() {
+    // Test that protecting a copy-on-write view results in the access
+    // documented in the truth table.
+    let snapshot = Snapshot::zeroed(crate::page_size()).unwrap();
+    for (allow, expected) in TRUTH_TABLE {
+        let mut view = snapshot.view().unwrap();
+        view.protect(.., allow).unwrap();
+        assert_eq!(view.query_protection(0).unwrap(), expected, "{allow:?}");
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_protect_mut_truth_table() {
+    // Test that protecting a mutable view results in the access
+    // documented in the truth table.
+    let mut snapshot = Snapshot::zeroed(crate::page_size()).unwrap();
+    for (allow, expected) in TRUTH_TABLE {
+        let mut view = snapshot.view_mut().unwrap();
+        view.protect(.., allow).unwrap();
+        assert_eq!(view.query_protection(0).unwrap(), expected, "{allow:?}");
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "memory protection is not enforced under miri")]
+fn test_protect_after_write() {
+    // Test that protecting a page of a copy-on-write view that has
+    // already been copied results in the same access as for a page that
+    // has not been written.
+    let snapshot = Snapshot::zeroed(crate::page_size()).unwrap();
+    for (allow, expected) in TRUTH_TABLE {
+        let mut view = snapshot.view().unwrap();
+        view[0] = 1;
+        view.protect(.., allow).unwrap();
+        assert_eq!(view.query_protection(0).unwrap(), expected, "{allow:?}");
+    }
+}
+
+#[test]
+fn test_default_protection() {
+    // Test that newly created views allow read and write access.
+    let mut snapshot = Snapshot::zeroed(crate::page_size()).unwrap();
+    let expected = Access::READ | Access::WRITE;
+    assert_eq!(
+        snapshot.view().unwrap().query_protection(0).unwrap(),
+        expected
+    );
+    assert_eq!(
+        snapshot.view_mut().unwrap().query_protection(0).unwrap(),
+        expected
+    );
+}
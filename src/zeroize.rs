@@ -0,0 +1,102 @@
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+
+use super::{Advice, MutView, Snapshot, ViewMode};
+
+/// A [`Snapshot`] that is [zeroized](Snapshot::zeroize) when it's dropped,
+/// for snapshots holding secrets.
+///
+/// The wrapper dereferences to its snapshot, so views of the snapshot
+/// can be created as usual.
+/// Errors zeroizing the snapshot when the wrapper is dropped are ignored.
+#[derive(Debug)]
+pub struct ZeroizeOnDrop {
+    snapshot: ManuallyDrop<Snapshot>,
+}
+
+impl Snapshot {
+    /// Overwrite the whole content of this snapshot with zeros, in a way
+    /// that the compiler can't optimize away, e.g., to wipe secrets.
+    /// Unmapping the views of a snapshot doesn't clear its backing, so
+    /// this should be called before dropping a snapshot holding secrets,
+    /// or the snapshot should be wrapped in a [`ZeroizeOnDrop`].
+    ///
+    /// The content stashed in the [checkpoints](Snapshot::checkpoint) of
+    /// the snapshot is zeroized too, and the checkpoints are discarded.
+    /// The pages are released afterwards, like with [`Advice::DontNeed`].
+    ///
+    /// If the backing of the snapshot is shared with other snapshots,
+    /// e.g., after [`try_clone_shared`](Snapshot::try_clone_shared), only this
+    /// snapshot is zeroized: it gets a new zeroed backing, and the other
+    /// snapshots keep the content.
+    pub fn zeroize(&mut self) -> std::io::Result<()> {
+        if let Some(mut checkpoints) = self.checkpoints.take() {
+            checkpoints.zeroize();
+        }
+        if std::sync::Arc::get_mut(&mut self.file).is_none() {
+            let mut snapshot = Self::zeroed(self.size)?;
+            std::mem::swap(&mut self.file, &mut snapshot.file);
+            return Ok(());
+        }
+        if self.size == 0 {
+            return Ok(());
+        }
+        // bypass `view_mut`, which could copy the content
+        let (fd, size) = (self.as_raw_fd(), self.size);
+        let mut view = MutView::new(self, fd, size, ViewMode::Mutable)?;
+        volatile_zero(view.as_full_mut_slice());
+        view.advise(.., Advice::DontNeed)
+    }
+}
+
+impl ZeroizeOnDrop {
+    /// Wrap `snapshot`, to zeroize it when it's dropped.
+    pub fn new(snapshot: Snapshot) -> Self {
+        Self {
+            snapshot: ManuallyDrop::new(snapshot),
+        }
+    }
+
+    /// Unwrap the snapshot, without zeroizing it.
+    pub fn into_inner(self) -> Snapshot {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: the wrapper is not dropped, so the snapshot is taken once
+        unsafe { ManuallyDrop::take(&mut this.snapshot) }
+    }
+}
+
+impl Deref for ZeroizeOnDrop {
+    type Target = Snapshot;
+
+    fn deref(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+impl DerefMut for ZeroizeOnDrop {
+    fn deref_mut(&mut self) -> &mut Snapshot {
+        &mut self.snapshot
+    }
+}
+
+impl Drop for ZeroizeOnDrop {
+    fn drop(&mut self) {
+        let _ = self.snapshot.zeroize();
+        // SAFETY: the snapshot is not used after this
+        unsafe { ManuallyDrop::drop(&mut self.snapshot) };
+    }
+}
+
+/// Overwrite `buf` with zeros with volatile writes, which the compiler
+/// can't elide even if `buf` is never read again.
+pub(crate) fn volatile_zero(buf: &mut [u8]) {
+    // SAFETY: `u64` has no invalid bit patterns
+    let (prefix, words, suffix) = unsafe { buf.align_to_mut::<u64>() };
+    for byte in prefix.iter_mut().chain(suffix) {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    for word in words {
+        unsafe { std::ptr::write_volatile(word, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}